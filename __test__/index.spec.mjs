@@ -1,6 +1,91 @@
+import { execFile, spawn, spawnSync } from "node:child_process";
+import { randomBytes } from "node:crypto";
+import { createSocket } from "node:dgram";
+import {
+  appendFileSync,
+  existsSync,
+  mkdirSync,
+  mkdtempSync,
+  readdirSync,
+  readFileSync,
+  realpathSync,
+  rmSync,
+  statSync,
+  symlinkSync,
+  utimesSync,
+  writeFileSync,
+} from "node:fs";
+import { getEventListeners } from "node:events";
+import { connect as netConnect, createServer } from "node:net";
+import { homedir, tmpdir } from "node:os";
+import { join } from "node:path";
+import { PassThrough, Readable, Writable } from "node:stream";
+import { finished, pipeline } from "node:stream/promises";
+import { createGzip, gunzipSync } from "node:zlib";
+
 import test from "ava";
 
-import { connect } from "../index.js";
+import {
+  Agent,
+  buildCommand,
+  connect,
+  DisconnectReason,
+  escapeShellArg,
+  FileTransport,
+  FileType,
+  KeyPair,
+  PublicKey,
+  ShellDialect,
+  SignatureHash,
+  SshfpStatus,
+  SshServer,
+  SyncAction,
+  TransferDirection,
+  TransferState,
+} from "../index.js";
+
+// the tests of the agent support need a running ssh-agent
+const withAgent = process.env.SSH_AUTH_SOCK ? test : test.skip;
+
+// the tests of the SFTP support need OpenSSH's sftp-server, or the one at SFTP_SERVER
+const sftpServer = [
+  process.env.SFTP_SERVER,
+  "/usr/lib/openssh/sftp-server",
+  "/usr/libexec/openssh/sftp-server",
+  "/usr/libexec/sftp-server",
+].find((path) => path && existsSync(path));
+const withSftp = sftpServer ? test : test.skip;
+
+// serve the sftp subsystem by piping the channel through sftp-server, in a fresh directory
+async function openSftp(t, options, sftpOptions) {
+  const children = [];
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    ...options,
+    onSubsystem: (channel) => {
+      const child = spawn(sftpServer, [], { stdio: ["pipe", "pipe", "ignore"] });
+      children.push(child);
+      let written = Promise.resolve();
+      child.stdin.on("error", () => {});
+      channel.onData((data) => child.stdin.write(data));
+      channel.onEof(() => child.stdin.end());
+      // the client may close the channel first
+      child.stdout.on("data", (data) => {
+        written = written.then(() => channel.write(data)).catch(() => {});
+      });
+      child.on("exit", (code) => written.then(() => channel.exit(code ?? 1)).catch(() => {}));
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const sftp = await client.sftp(sftpOptions);
+  t.teardown(() => sftp.close());
+  const dir = mkdtempSync(join(tmpdir(), "ssh-sftp-test-"));
+  t.teardown(() => rmSync(dir, { recursive: true, force: true }));
+  return { client, sftp, dir, children };
+}
 
 test("connection failed without auth", async (t) => {
   if (process.platform !== "darwin" && process.platform !== "win32") {
@@ -9,3 +94,2134 @@ test("connection failed without auth", async (t) => {
     await t.notThrowsAsync(() => connect("github.com:22"));
   }
 });
+
+test("exec against the embedded server", async (t) => {
+  let clientVersion;
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: (user, password) => user === "user" && password === "password",
+    onExec: (channel, command, connection) => {
+      clientVersion = connection.clientVersion;
+      channel.write(`${command}\n`);
+      channel.exit(3);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const { status, output } = await client.exec("echo");
+  t.is(status, 3);
+  t.is(output.toString(), "echo\n");
+  t.true(clientVersion.startsWith("SSH-2.0-"));
+});
+
+test("exec calls run concurrently on one connection", async (t) => {
+  let releaseFirst;
+  const secondStarted = new Promise((resolve) => (releaseFirst = resolve));
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      // the first command only finishes once the second one has started
+      if (command === "first") await secondStarted;
+      else releaseFirst();
+      await channel.write(command);
+      await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const outputs = await Promise.all([client.exec("first"), client.exec("second")]);
+  t.deepEqual(
+    outputs.map(({ output }) => output.toString()),
+    ["first", "second"],
+  );
+});
+
+test("execBatch reports the result of each command", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      await channel.write(command);
+      await channel.exit(command === "fail" ? 1 : 0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const results = await client.execBatch(["first", "fail", "third"]);
+  t.deepEqual(
+    results.map(({ output, error }) => [output.status, output.output.toString(), error]),
+    [
+      [0, "first", undefined],
+      [1, "fail", undefined],
+      [0, "third", undefined],
+    ],
+  );
+  // commands that can not be run are reported rather than rejecting the batch
+  await client.disconnect(DisconnectReason.ByApplication, "done", "");
+  const failed = await client.execBatch(["first", "second"]);
+  t.is(failed.length, 2);
+  for (const { output, error } of failed) {
+    t.is(output, undefined);
+    t.is(typeof error, "string");
+  }
+});
+
+test("exec keeps stdout and stderr apart", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel) => {
+      await channel.write("payload\n");
+      await channel.writeStderr("warning\n");
+      await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const { output, stderr } = await client.exec("run");
+  t.is(output.toString(), "payload\n");
+  t.is(stderr.toString(), "warning\n");
+});
+
+test("exec reports the signal that killed the command", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel, command) =>
+      command === "crash" ? channel.exitSignal("SEGV", true, "Segmentation fault") : channel.exit(0),
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const crashed = await client.exec("crash");
+  t.is(crashed.exitSignal, "SEGV");
+  t.true(crashed.coreDumped);
+  t.is(crashed.errorMessage, "Segmentation fault");
+  const exited = await client.exec("true");
+  t.is(exited.status, 0);
+  t.is(exited.exitSignal, undefined);
+  t.false(exited.coreDumped);
+});
+
+test("exec truncates output at maxOutputBytes", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      await channel.write("0123456789");
+      await channel.writeStderr("abcdefghij");
+      // `hang` only ends when the client closes the channel
+      if (command !== "hang") await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const truncated = await client.exec("run", { maxOutputBytes: 15 });
+  t.is(truncated.output.toString(), "0123456789");
+  t.is(truncated.stderr.toString(), "abcde");
+  t.true(truncated.truncated);
+  t.false((await client.exec("run", { maxOutputBytes: 20 })).truncated);
+  const closed = await client.exec("hang", { maxOutputBytes: 5, closeOnTruncate: true });
+  t.is(closed.output.toString(), "01234");
+  t.true(closed.truncated);
+});
+
+test("exec throwOnNonZero rejects with the status and output", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      await channel.write("partial");
+      await channel.writeStderr("boom");
+      await (command === "kill" ? channel.exitSignal("KILL") : channel.exit(Number(command)));
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  t.is((await client.exec("0", { throwOnNonZero: true })).output.toString(), "partial");
+  t.is((await client.exec("2")).status, 2);
+  const failed = await t.throwsAsync(() => client.exec("2", { throwOnNonZero: true }), {
+    code: "NonZeroExit",
+    message: "NonZeroExit: the command exited with status 2\nstdout: partial\nstderr: boom",
+  });
+  t.true(failed instanceof Error);
+  t.is(failed.status, 2);
+  t.is(failed.exitSignal, undefined);
+  t.is(failed.stdout.toString(), "partial");
+  t.is(failed.stderr.toString(), "boom");
+  const killed = await t.throwsAsync(() => client.exec("kill", { throwOnNonZero: true }), {
+    message: /^NonZeroExit: the command was killed by SIGKILL\n/,
+  });
+  t.is(killed.exitSignal, "KILL");
+});
+
+test("exec writes stdin and closes it", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel) => {
+      const chunks = [];
+      channel.onData((data) => chunks.push(data));
+      channel.onEof(async () => {
+        await channel.write(Buffer.concat(chunks).toString().toUpperCase());
+        await channel.exit(0);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const input = "x".repeat(1024 * 1024);
+  const { output } = await client.exec("cat", { stdin: input });
+  t.is(output.toString(), input.toUpperCase());
+  t.is((await client.exec("cat", { stdin: Buffer.from("abc") })).output.toString(), "ABC");
+});
+
+test("spawn exposes stdin, stdout, stderr and exit", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      if (command === "crash") {
+        await channel.exitSignal("KILL");
+        return;
+      }
+      const chunks = [];
+      channel.onData((data) => chunks.push(data));
+      channel.onEof(async () => {
+        await channel.write(Buffer.concat(chunks).toString().toUpperCase());
+        await channel.writeStderr("done");
+        await channel.exit(3);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const child = await client.spawn("upper");
+  await child.stdin.write("abc");
+  await child.stdin.end(Buffer.from("def"));
+  const read = async (stream) => {
+    const chunks = [];
+    for await (const chunk of stream) {
+      chunks.push(chunk);
+    }
+    return Buffer.concat(chunks).toString();
+  };
+  t.deepEqual(await Promise.all([read(child.stdout), read(child.stderr)]), ["ABCDEF", "done"]);
+  t.deepEqual(await child.exit, { code: 3 });
+  const crashed = await client.spawn("crash");
+  await crashed.kill();
+  t.deepEqual(await crashed.exit, { signal: "KILL" });
+});
+
+test("attachStdio bridges the process stdio to a PTY command", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel, command) => {
+      const chunks = [];
+      channel.onData((data) => chunks.push(data));
+      channel.onEof(async () => {
+        await channel.write(`${channel.terminal.term} ${command}: ${Buffer.concat(chunks)}`);
+        await channel.writeStderr("done");
+        await channel.exit(5);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const script = `
+    const { connect, DisconnectReason } = require(${JSON.stringify(new URL("../index.js", import.meta.url).pathname)});
+    (async () => {
+      const client = await connect(process.argv[1]);
+      await client.authenticatePassword("user", "password");
+      const channel = await client.execPty("upper", { term: "vt100" });
+      process.exitCode = await channel.attachStdio();
+      await client.disconnect(DisconnectReason.ByApplication, "done", "");
+    })();
+  `;
+  const { code, stdout, stderr } = await new Promise((resolve) => {
+    const child = execFile(process.execPath, ["-e", script, `${server.host}:${server.port}`], (error, stdout, stderr) =>
+      resolve({ code: error?.code ?? 0, stdout, stderr }),
+    );
+    child.stdin.end("hello");
+  });
+  t.deepEqual({ code, stdout, stderr }, { code: 5, stdout: "vt100 upper: hello", stderr: "done" });
+});
+
+test("execScript pipes the script into the interpreter", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel, command) => {
+      const chunks = [];
+      channel.onData((data) => chunks.push(data));
+      channel.onEof(async () => {
+        await channel.write(`${command}\n${Buffer.concat(chunks)}`);
+        await channel.exit(command.startsWith("sh") ? 0 : 1);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const script = "echo 'it''s' \"$1\"\n";
+  const { status, output } = await client.execScript(script, { args: ["a b"] });
+  t.is(status, 0);
+  t.is(output.toString(), `sh -s -- 'a b'\n${script}`);
+  await t.throwsAsync(
+    () => client.execScript("print(1)", { interpreter: ["python3", "-"], throwOnNonZero: true }),
+    { message: /^NonZeroExit: the command exited with status 1\nstdout: python3 -\nprint\(1\)/ },
+  );
+  await t.throwsAsync(() => client.execScript("", { interpreter: [] }), {
+    message: "The interpreter can not be empty",
+  });
+});
+
+test("openSession exposes the channel lifecycle", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel, command) => {
+      const chunks = [];
+      channel.onData((data) => chunks.push(data));
+      channel.onEof(async () => {
+        await channel.write(`${command}: ${Buffer.concat(chunks)}`);
+        await channel.writeStderr("done");
+        await channel.exit(7);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const channel = await client.openSession();
+  const output = [];
+  const closed = new Promise((resolve) => channel.onClose(resolve));
+  channel.onData((data, stream) => output.push([stream, data.toString()]));
+  await channel.exec("echo");
+  await channel.write("hello ");
+  await channel.write(Buffer.from("world"));
+  await channel.eof();
+  t.is(await closed, 7);
+  t.deepEqual(output, [
+    ["stdout", "echo: hello world"],
+    ["stderr", "done"],
+  ]);
+  await t.throwsAsync(() => channel.write("late"), { message: "Channel is closed" });
+});
+
+test("Channel.flowStats reports the flow control of the channel", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel) => {
+      let received = 0;
+      channel.onData((data) => (received += data.length));
+      channel.onEof(async () => {
+        await channel.write(String(received));
+        await channel.exit(0);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const channel = await client.openSession();
+  const opened = channel.flowStats();
+  t.true(opened.maxPacketSize > 0);
+  t.true(opened.outboundWindow > 0);
+  t.is(opened.windowAdjusts, 0);
+  t.false(opened.waitingForWindow);
+  const output = [];
+  const closed = new Promise((resolve) => channel.onClose(resolve));
+  channel.onData((data) => output.push(data));
+  await channel.exec("count");
+  // twice the initial window can only get through with WindowAdjusts
+  const chunk = Buffer.alloc(1 << 20);
+  const chunks = 2 * Math.ceil(opened.outboundWindow / chunk.length);
+  for (let i = 0; i < chunks; i++) {
+    await channel.write(chunk);
+  }
+  await channel.eof();
+  t.is(await closed, 0);
+  t.is(Number(Buffer.concat(output)), chunks * chunk.length);
+  const stats = channel.flowStats();
+  t.true(stats.windowAdjusts > 0);
+  t.is(stats.maxPacketSize, opened.maxPacketSize);
+});
+
+test("shell requests a PTY and follows resizes", async (t) => {
+  let serverChannel;
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onShell: (channel) => {
+      serverChannel = channel;
+      channel.onData((data) => channel.write(data));
+      channel.onEof(() => channel.exit(0));
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  await t.throwsAsync(() => client.shell({ modes: { NOPE: 1 } }), { message: "Unknown terminal mode NOPE" });
+  const channel = await client.shell({ cols: 100, rows: 30, modes: { ECHO: 0 } });
+  const echoed = [];
+  let waiting;
+  channel.onData((data) => {
+    echoed.push(data.toString());
+    waiting?.();
+  });
+  const roundTrip = async (keys) => {
+    const received = new Promise((resolve) => (waiting = resolve));
+    await channel.write(keys);
+    await received;
+  };
+  await roundTrip("ls\r");
+  t.deepEqual(serverChannel.terminal, { term: "xterm-256color", cols: 100, rows: 30 });
+  await channel.windowChange(120, 40, 960, 640);
+  await roundTrip("exit\r");
+  t.deepEqual(serverChannel.terminal, { term: "xterm-256color", cols: 120, rows: 40 });
+  t.is(echoed.join(""), "ls\rexit\r");
+  const closed = new Promise((resolve) => channel.onClose(resolve));
+  await channel.eof();
+  t.is(await closed, 0);
+});
+
+test("AbortSignal cancels connect and exec", async (t) => {
+  const silent = createServer(() => {});
+  await new Promise((resolve) => silent.listen(0, "127.0.0.1", resolve));
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel, command) => {
+      if (command !== "hang") channel.exit(0);
+    },
+  });
+  t.teardown(() => {
+    silent.close();
+    server.close();
+  });
+  const abortError = { message: "AbortError: The operation was aborted" };
+  await t.throwsAsync(() => connect(`${server.host}:${server.port}`, { signal: AbortSignal.abort() }), abortError);
+  await t.throwsAsync(
+    () => connect(`127.0.0.1:${silent.address().port}`, { signal: AbortSignal.timeout(100) }),
+    abortError,
+  );
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  await t.throwsAsync(() => client.exec("hang", { signal: AbortSignal.timeout(100) }), abortError);
+  t.is((await client.exec("true")).status, 0);
+  // a signal reused across calls is left without their listeners once they are done
+  const controller = new AbortController();
+  for (let i = 0; i < 5; i++) {
+    await client.exec("true", { signal: controller.signal });
+  }
+  await new Promise((resolve) => setTimeout(resolve, 50));
+  t.is(getEventListeners(controller.signal, "abort").length, 0);
+  const pending = client.exec("hang", { signal: controller.signal });
+  controller.abort();
+  await t.throwsAsync(() => pending, abortError);
+});
+
+test("exec and setEnv pass environment variables", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel) => {
+      const { env } = channel;
+      channel.write(Object.keys(env).sort().map((name) => `${name}=${env[name]}`).join(" "));
+      channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const { output } = await client.exec("env", { env: { LANG: "C.UTF-8", GIT_SSH_VARIANT: "ssh 'x'" } });
+  t.is(output.toString(), "GIT_SSH_VARIANT=ssh 'x' LANG=C.UTF-8");
+  const channel = await client.openSession();
+  const chunks = [];
+  channel.onData((data) => chunks.push(data));
+  const closed = new Promise((resolve) => channel.onClose(resolve));
+  await channel.setEnv("TZ", "UTC");
+  await channel.exec("env");
+  await closed;
+  t.is(Buffer.concat(chunks).toString(), "TZ=UTC");
+});
+
+test("requestSubsystem speaks to a subsystem over the channel", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onSubsystem: (channel, name) => {
+      channel.onData(async (data) => {
+        await channel.write(`${name}<${data}>`);
+        await channel.exit(0);
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const channel = await client.openSession();
+  const chunks = [];
+  channel.onData((data) => chunks.push(data));
+  const closed = new Promise((resolve) => channel.onClose(resolve));
+  await channel.requestSubsystem("netconf");
+  await channel.write("<hello/>");
+  t.is(await closed, 0);
+  t.is(Buffer.concat(chunks).toString(), "netconf<<hello/>>");
+});
+
+test("channel output can be read with for await", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel) => {
+      await channel.write("one ");
+      await channel.writeStderr("ignored");
+      await channel.write("two");
+      await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.true(await client.authenticatePassword("user", "password"));
+  const channel = await client.openSession();
+  await channel.exec("count");
+  const chunks = [];
+  for await (const chunk of channel) {
+    chunks.push(chunk);
+  }
+  t.is(Buffer.concat(chunks).toString(), "one two");
+});
+
+test("keepalives detect a connection that went silent", async (t) => {
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPassword: () => true });
+  // forwards everything until frozen, then swallows what the server sends
+  let frozen = false;
+  const proxy = createServer((socket) => {
+    const upstream = netConnect(server.port, server.host);
+    socket.pipe(upstream);
+    upstream.on("data", (data) => frozen || socket.write(data));
+    socket.on("error", () => upstream.destroy());
+    upstream.on("error", () => socket.destroy());
+  });
+  await new Promise((resolve) => proxy.listen(0, "127.0.0.1", resolve));
+  t.teardown(() => {
+    proxy.close();
+    server.close();
+  });
+  let onClose;
+  const closed = new Promise((resolve) => (onClose = resolve));
+  const client = await connect(`127.0.0.1:${proxy.address().port}`, {
+    client: { keepaliveInterval: 100, keepaliveMax: 2 },
+    onClose,
+  });
+  t.true(await client.authenticatePassword("user", "password"));
+  // answered keepalives keep the connection open
+  await new Promise((resolve) => setTimeout(resolve, 350));
+  t.true(await client.isAlive());
+  frozen = true;
+  const info = await closed;
+  t.false(info.local);
+  t.is(info.message, "Keepalive timeout");
+});
+
+test("server auth callbacks", async (t) => {
+  const userKey = KeyPair.generateEd25519();
+  const fingerprint = userKey.clonePublicKey().fingerprint();
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    maxAuthAttempts: 2,
+    onAuthPassword: async (_user, password) => password === "password",
+    onAuthPublicKey: (_user, publicKey) => publicKey.fingerprint() === fingerprint,
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  const keyClient = await connect(addr);
+  t.false(await keyClient.authenticateKeyPair("user", KeyPair.generateEd25519()));
+  t.true(await keyClient.authenticateKeyPair("user", userKey));
+  const passwordClient = await connect(addr);
+  t.false(await passwordClient.authenticatePassword("user", "wrong"));
+  t.false(await passwordClient.authenticatePassword("user", "wrong"));
+  await t.throwsAsync(() => passwordClient.authenticatePassword("user", "password"));
+});
+
+test("execSudo answers the password prompt", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (channel, command) => {
+      const [, escaped, shell] = command.match(/^sudo -S -p '([^']*)' -- (sh -c .*)$/);
+      const prompt = escaped.replaceAll("%%", "%");
+      // split the prompt across two packets
+      channel.write(prompt.slice(0, 3));
+      channel.write(prompt.slice(3));
+      let input = "";
+      channel.onData((data) => {
+        input += data.toString();
+        if (!input.endsWith("\n")) return;
+        if (input === "hunter2\n") {
+          const { stdout, status } = spawnSync("sh", ["-c", shell]);
+          channel.write(stdout);
+          channel.exit(status);
+        } else {
+          input = "";
+          channel.write(`Sorry, try again.\n${prompt}`);
+        }
+      });
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const { status, output } = await client.execSudo("echo 0", { password: "hunter2" });
+  t.is(status, 0);
+  t.is(output.toString(), "0\n");
+  const compound = await client.execSudo(`cd / && echo "it's $(pwd)"; exit 3`, { password: "hunter2" });
+  t.is(compound.status, 3);
+  t.is(compound.output.toString(), "it's /\n");
+  const custom = await client.execSudo("echo 0", { password: "hunter2", prompt: "[%u password]" });
+  t.is(custom.output.toString(), "0\n");
+  await t.throwsAsync(() => client.execSudo("echo 0", { password: "wrong" }), {
+    message: "sudo rejected the password",
+  });
+});
+
+test("isAlive probes the connection", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const before = client.lastActivity();
+  t.true(await client.isAlive({ timeoutMs: 1000 }));
+  t.true(client.lastActivity() >= before);
+  server.close();
+  await new Promise((resolve) => setTimeout(resolve, 100));
+  t.false(await client.isAlive({ timeoutMs: 1000 }));
+});
+
+test("waitClosed resolves with the close reason", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: (_channel, _command, connection) => {
+      connection.disconnect(DisconnectReason.ByApplication, "bye");
+    },
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  const remote = await connect(addr);
+  await remote.authenticatePassword("user", "password");
+  await remote.exec("kick").catch(() => {});
+  t.deepEqual(await remote.waitClosed(), {
+    local: false,
+    reason: DisconnectReason.ByApplication,
+    message: "bye",
+  });
+  const local = await connect(addr);
+  await local.disconnect(DisconnectReason.ByApplication, "done", "");
+  t.deepEqual(await local.waitClosed(), {
+    local: true,
+    reason: DisconnectReason.ByApplication,
+    message: "done",
+  });
+});
+
+test("authBanner resolves with the server banner", async (t) => {
+  const withBanner = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    banner: "Authorized use only\n",
+    onAuthPassword: () => true,
+  });
+  const withoutBanner = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+  });
+  t.teardown(() => {
+    withBanner.close();
+    withoutBanner.close();
+  });
+  const banners = [];
+  const client = await connect(`${withBanner.host}:${withBanner.port}`, {
+    authBanner: (banner) => banners.push(banner),
+  });
+  const banner = client.authBanner();
+  await client.authenticatePassword("user", "password");
+  t.is(await banner, "Authorized use only\n");
+  t.deepEqual(banners, ["Authorized use only\n"]);
+  const silent = await connect(`${withoutBanner.host}:${withoutBanner.port}`);
+  await silent.authenticatePassword("user", "password");
+  t.is(await silent.authBanner(), null);
+});
+
+test("execStream splits output into lines", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel) => {
+      await channel.write("first\r");
+      await channel.write("\nsec");
+      await channel.writeStderr("oops\n");
+      await channel.write(Buffer.from([0x6f, 0x6e, 0x64, 0x0a, 0xff, 0x0a]));
+      // a CRLF split between chunks right after a line of the maximum length
+      await channel.write("wxyz\r");
+      await channel.write("\n");
+      await channel.write("abcdefghij");
+      await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const lines = [];
+  const status = await client.execStream("lines", {
+    maxLineLength: 4,
+    onLine: (line, stream) => lines.push(`${stream}:${line}`),
+  });
+  t.is(status, 0);
+  t.deepEqual(lines, [
+    "stdout:firs",
+    "stdout:t",
+    "stderr:oops",
+    "stdout:seco",
+    "stdout:nd",
+    "stdout:�",
+    "stdout:wxyz",
+    "stdout:abcd",
+    "stdout:efgh",
+    "stdout:ij",
+  ]);
+});
+
+test("buildCommand and escapeShellArg quote for the remote shell", (t) => {
+  t.is(escapeShellArg("it's $HOME"), `'it'\\''s $HOME'`);
+  t.is(escapeShellArg("plain.txt"), "plain.txt");
+  t.is(escapeShellArg(""), "''");
+  t.is(buildCommand(["rm", "--", "-rf; reboot"]), "rm -- '-rf; reboot'");
+  t.is(buildCommand(["echo", "$x"], ShellDialect.PowerShell), "& 'echo' '$x'");
+  t.throws(() => buildCommand([]), { message: "The argv array can not be empty" });
+});
+
+test("Config.passphrase decrypts key files", async (t) => {
+  const dir = mkdtempSync(join(tmpdir(), "ssh-key-test-"));
+  t.teardown(() => rmSync(dir, { recursive: true }));
+  const path = join(dir, "key");
+  spawnSync("ssh-keygen", ["-q", "-t", "ed25519", "-N", "secret", "-f", path]);
+  const fingerprint = PublicKey.parse(readFileSync(`${path}.pub`, "utf8")).fingerprint();
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPublicKey: (_user, key) => key.fingerprint() === fingerprint,
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  await t.throwsAsync(async () => (await connect(addr)).authenticateKeyPair("user", path), {
+    message: "The key is encrypted",
+  });
+  const asked = [];
+  const client = await connect(addr, {
+    passphrase: async (keyPath, attempt) => {
+      asked.push([keyPath, attempt]);
+      return attempt === 1 ? "wrong" : "secret";
+    },
+  });
+  t.true(await client.authenticateKeyPair("user", path));
+  t.deepEqual(asked, [
+    [path, 1],
+    [path, 2],
+  ]);
+  const givenUp = await connect(addr, { passphrase: () => null });
+  await t.throwsAsync(() => givenUp.authenticateKeyPair("user", path), { message: "The key is encrypted" });
+});
+
+// serial, since it points HOME at a directory of its own
+test.serial("authenticateDefaultKeys tries the keys ssh uses by default", async (t) => {
+  const home = mkdtempSync(join(tmpdir(), "ssh-home-test-"));
+  const previousHome = process.env.HOME;
+  process.env.HOME = home;
+  t.teardown(() => {
+    process.env.HOME = previousHome;
+    rmSync(home, { recursive: true });
+  });
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPublicKey: () => false });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  await t.throwsAsync(async () => (await connect(addr)).authenticateKeyPair("user"), {
+    message: `None of the default keys id_ed25519, id_ecdsa, id_rsa exist in ${join(home, ".ssh")}`,
+  });
+  mkdirSync(join(home, ".ssh"));
+  const keygen = (...args) => spawnSync("ssh-keygen", ["-q", "-N", "", ...args]);
+  keygen("-t", "ed25519", "-f", join(home, ".ssh", "id_ed25519"));
+  keygen("-t", "ecdsa", "-f", join(home, ".ssh", "id_ecdsa"));
+  keygen("-t", "ed25519", "-f", join(home, "ca"));
+  spawnSync("ssh-keygen", ["-q", "-s", join(home, "ca"), "-I", "user", "-n", "user", join(home, ".ssh", "id_ed25519.pub")]);
+  const accepted = PublicKey.parse(readFileSync(join(home, ".ssh", "id_ecdsa.pub"), "utf8")).fingerprint();
+  const offered = [];
+  const picky = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPublicKey: (_user, key) => {
+      offered.push(key.fingerprint());
+      return key.fingerprint() === accepted;
+    },
+  });
+  t.teardown(() => picky.close());
+  const client = await connect(`${picky.host}:${picky.port}`);
+  t.is(await client.authenticateDefaultKeys("user"), join(home, ".ssh", "id_ecdsa"));
+  t.is(offered.at(-1), accepted);
+  t.false(await (await connect(addr)).authenticateKeyPair("user"));
+});
+
+// serial, since it points HOME at a directory of its own
+test.serial("authenticateDefaultKeys skips keys it can not load", async (t) => {
+  const home = mkdtempSync(join(tmpdir(), "ssh-home-test-"));
+  const previousHome = process.env.HOME;
+  process.env.HOME = home;
+  t.teardown(() => {
+    process.env.HOME = previousHome;
+    rmSync(home, { recursive: true });
+  });
+  mkdirSync(join(home, ".ssh"));
+  const first = join(home, ".ssh", "id_ed25519");
+  spawnSync("ssh-keygen", ["-q", "-t", "ed25519", "-N", "secret", "-f", first]);
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPublicKey: () => true });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  await t.throwsAsync(async () => (await connect(addr)).authenticateDefaultKeys("user"), {
+    message: `None of the default keys could be loaded: ${first}: The key is encrypted`,
+  });
+  spawnSync("ssh-keygen", ["-q", "-t", "ecdsa", "-N", "", "-f", join(home, ".ssh", "id_ecdsa")]);
+  t.is(await (await connect(addr)).authenticateDefaultKeys("user"), join(home, ".ssh", "id_ecdsa"));
+});
+
+withAgent("authenticateAgent tries the agent identities", async (t) => {
+  const dir = mkdtempSync(join(tmpdir(), "ssh-agent-test-"));
+  const keys = ["first", "second"].map((name) => {
+    const path = join(dir, name);
+    spawnSync("ssh-keygen", ["-q", "-t", "ed25519", "-N", "", "-f", path]);
+    spawnSync("ssh-add", ["-q", path]);
+    return PublicKey.parse(readFileSync(`${path}.pub`, "utf8"));
+  });
+  t.teardown(() => {
+    spawnSync("ssh-add", ["-q", "-d", ...["first", "second"].map((name) => join(dir, name))]);
+    rmSync(dir, { recursive: true });
+  });
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPublicKey: (_user, key) => key.fingerprint() === keys[1].fingerprint(),
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  t.is(await (await connect(addr)).authenticateAgent("user", { maxAttempts: 1 }), null);
+  const accepted = await (await connect(addr)).authenticateAgent("user");
+  t.is(accepted.fingerprint(), keys[1].fingerprint());
+});
+
+test("authenticateKeyPair signs RSA keys with hashAlg", async (t) => {
+  const key = KeyPair.generateRsa(2048, SignatureHash.SHA2_512);
+  const fingerprint = key.clonePublicKey().fingerprint();
+  const algorithms = [];
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPublicKey: (_user, publicKey) => {
+      algorithms.push(publicKey.name());
+      return publicKey.fingerprint() === fingerprint;
+    },
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  t.true(await (await connect(addr)).authenticateKeyPair("user", key));
+  t.true(algorithms.includes("rsa-sha2-512"));
+  t.false(algorithms.includes("rsa-sha2-256"));
+  algorithms.length = 0;
+  t.true(await (await connect(addr)).authenticateKeyPair("user", key, { hashAlg: SignatureHash.SHA2_256 }));
+  t.true(algorithms.includes("rsa-sha2-256"));
+  t.false(algorithms.includes("rsa-sha2-512"));
+});
+
+test("KeyPair.fromPem decodes a key from its contents", (t) => {
+  const dir = mkdtempSync(join(tmpdir(), "ssh-key-test-"));
+  t.teardown(() => rmSync(dir, { recursive: true }));
+  const path = join(dir, "key");
+  spawnSync("ssh-keygen", ["-q", "-t", "ed25519", "-N", "secret", "-f", path]);
+  const fingerprint = PublicKey.parse(readFileSync(`${path}.pub`, "utf8")).fingerprint();
+  t.is(KeyPair.fromPem(readFileSync(path, "utf8"), "secret").clonePublicKey().fingerprint(), fingerprint);
+  t.is(KeyPair.fromPem(readFileSync(path), "secret").clonePublicKey().fingerprint(), fingerprint);
+  t.throws(() => KeyPair.fromPem(readFileSync(path), "wrong"));
+});
+
+withAgent("Config.agent picks the ssh-agent", async (t) => {
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPublicKey: () => false });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  t.is(await (await connect(addr, { agent: process.env.SSH_AUTH_SOCK })).authenticateAgent("user"), null);
+  await t.throwsAsync(async () => (await connect(addr, { agent: false })).authenticateAgent("user"), {
+    message: "No ssh-agent is connected",
+  });
+  await t.throwsAsync(() => connect(addr, { agent: "/nonexistent/agent.sock" }), {
+    message: /^Failed to connect to the ssh-agent at \/nonexistent\/agent\.sock: /,
+  });
+  const agent = await Agent.connect(process.env.SSH_AUTH_SOCK);
+  t.true(Array.isArray(await agent.listIdentities()));
+});
+
+withAgent("Agent manages the identities of the ssh-agent", async (t) => {
+  const agent = await Agent.connect();
+  const key = KeyPair.generateEd25519();
+  const fingerprint = key.clonePublicKey().fingerprint();
+  const held = async () => (await agent.listIdentities()).some((identity) => identity.fingerprint() === fingerprint);
+  t.false(await held());
+  await agent.addIdentity(key, { lifetime: 60 });
+  t.true(await held());
+  await agent.removeIdentity(key.clonePublicKey());
+  t.false(await held());
+});
+
+withAgent("Agent.addSmartcardKey asks the agent to load a PKCS#11 module", async (t) => {
+  const agent = await Agent.connect();
+  // the agent refuses modules outside its allowlist
+  await t.throwsAsync(() => agent.addSmartcardKey("/nonexistent/pkcs11.so", "1234", { lifetime: 60 }), {
+    message: "The ssh-agent added no keys from /nonexistent/pkcs11.so; it may not allow the module, or the pin is wrong",
+  });
+});
+
+// serial, since a locked agent fails the other tests that use it
+withAgent.serial("Agent lock and unlock", async (t) => {
+  const agent = await Agent.connect();
+  const key = KeyPair.generateEd25519();
+  await agent.addIdentity(key);
+  t.teardown(() => agent.removeIdentity(key.clonePublicKey()));
+  await agent.lock("secret");
+  t.deepEqual(await agent.listIdentities(), []);
+  await t.throwsAsync(() => agent.lock("again"), { message: "The ssh-agent refused to lock" });
+  await t.throwsAsync(() => agent.unlock("wrong"), {
+    message: "The ssh-agent refused to unlock; the passphrase may be wrong",
+  });
+  t.deepEqual(await agent.listIdentities(), []);
+  await agent.unlock("secret");
+  await t.throwsAsync(() => agent.unlock("secret"), {
+    message: "The ssh-agent refused to unlock; the passphrase may be wrong",
+  });
+  const fingerprint = key.clonePublicKey().fingerprint();
+  t.true((await agent.listIdentities()).some((identity) => identity.fingerprint() === fingerprint));
+});
+
+test("authenticateNone", async (t) => {
+  const open = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], noneAllowed: true });
+  t.teardown(() => open.close());
+  t.true(await (await connect(`${open.host}:${open.port}`)).authenticateNone("user"));
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPassword: () => true });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  t.false(await client.authenticateNone("user"));
+  t.true(await client.authenticatePassword("user", "password"));
+});
+
+test("exec quotes argv for the remote shell", async (t) => {
+  const commands = [];
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      commands.push(command);
+      if (process.platform !== "win32") {
+        await channel.write(spawnSync("sh", ["-c", command]).stdout);
+      }
+      await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const tricky = ["it's", "two\nlines", "$(id) `id` $HOME", "ünï ✓", "", "-la", 'a "b" \\'];
+  const { output } = await client.exec(["printf", "%s\\0", ...tricky]);
+  if (process.platform !== "win32") {
+    t.deepEqual([...output], [...Buffer.from(tricky.map((arg) => `${arg}\0`).join(""))]);
+  }
+  t.is(commands[0].split(" ")[0], "printf");
+  await client.exec(["dir", 'a "b"\\', "x&y"], { shell: ShellDialect.Cmd });
+  t.is(commands[1], '^"dir^" ^"a \\^"b\\^"\\\\^" ^"x^&y^"');
+  await client.exec(["Get-Item", "it's"], { shell: ShellDialect.PowerShell });
+  t.is(commands[2], "& 'Get-Item' 'it''s'");
+  await t.throwsAsync(() => client.exec([]), { message: "The argv array can not be empty" });
+});
+
+// answers every query with `records`, setting the DNSSEC AD flag if `authenticated`
+async function fakeResolver(records, authenticated) {
+  const socket = createSocket("udp4");
+  socket.on("message", (query, remote) => {
+    let end = 12;
+    while (query[end] !== 0) end += query[end] + 1;
+    const question = query.subarray(12, end + 5);
+    const answers = records.map((rdata) => {
+      const fixed = Buffer.from([0xc0, 0x0c, 0, 44, 0, 1, 0, 0, 0, 60, 0, rdata.length]);
+      return Buffer.concat([fixed, rdata]);
+    });
+    const header = Buffer.from([0, 0, 0x81, authenticated ? 0xa0 : 0x80, 0, 1, 0, records.length, 0, 0, 0, 0]);
+    query.copy(header, 0, 0, 2);
+    socket.send(Buffer.concat([header, question, ...answers]), remote.port, remote.address);
+  });
+  await new Promise((resolve) => socket.bind(0, "127.0.0.1", resolve));
+  return socket;
+}
+
+test("sshfp verifies the host key against DNS", async (t) => {
+  const hostKey = KeyPair.generateEd25519();
+  const server = await SshServer.listen({ hostKeys: [hostKey], onAuthPassword: () => true });
+  const fingerprint = Buffer.from(hostKey.clonePublicKey().fingerprint(), "base64");
+  const record = Buffer.concat([Buffer.from([4, 2]), fingerprint]);
+  const secure = await fakeResolver([record], true);
+  const insecure = await fakeResolver([record], false);
+  const wrong = await fakeResolver([Buffer.concat([Buffer.from([4, 2]), Buffer.alloc(32)])], true);
+  t.teardown(() => {
+    server.close();
+    secure.close();
+    insecure.close();
+    wrong.close();
+  });
+  const sshfp = (resolver) => ({
+    hostname: "host.example",
+    nameserver: `127.0.0.1:${resolver.address().port}`,
+  });
+  const client = await connect(`${server.host}:${server.port}`, { sshfp: sshfp(secure) });
+  t.true(await client.authenticatePassword("user", "password"));
+  await t.throwsAsync(() => connect(`${server.host}:${server.port}`, { sshfp: sshfp(insecure) }));
+  await t.throwsAsync(() => connect(`${server.host}:${server.port}`, { sshfp: sshfp(wrong) }));
+  await connect(`${server.host}:${server.port}`, {
+    sshfp: { ...sshfp(insecure), allowInsecureDns: true },
+  });
+  const statuses = [];
+  await connect(`${server.host}:${server.port}`, {
+    sshfp: sshfp(insecure),
+    checkServerKey: (_key, status) => statuses.push(status) > 0,
+  });
+  t.deepEqual(statuses, [SshfpStatus.Unverified]);
+});
+
+test("hostKeyStore trusts on first use and rejects changed keys", async (t) => {
+  const hostKey = KeyPair.generateEd25519();
+  const server = await SshServer.listen({ hostKeys: [hostKey], onAuthPassword: () => true });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  const keys = new Map();
+  const store = {
+    lookup: async (host, port) => keys.get(`${host}:${port}`) ?? [],
+    store: (host, port, key) => {
+      keys.set(`${host}:${port}`, [PublicKey.parse(key.toOpenSsh())]);
+    },
+  };
+  await t.throwsAsync(() => connect(addr, { hostKeyStore: store }));
+  t.is(keys.size, 0);
+  await connect(addr, { hostKeyStore: { ...store, trustOnFirstUse: true } });
+  t.is(keys.get(addr)[0].toOpenSsh(), hostKey.clonePublicKey().toOpenSsh());
+  const client = await connect(addr, { hostKeyStore: store, checkServerKey: () => false });
+  t.true(await client.authenticatePassword("user", "password"));
+  keys.set(addr, [KeyPair.generateEd25519().clonePublicKey()]);
+  await t.throwsAsync(() => connect(addr, { hostKeyStore: store, checkServerKey: () => true }), {
+    message: /has changed/,
+  });
+  keys.set(addr, [{ key: hostKey.clonePublicKey(), revoked: true }]);
+  await t.throwsAsync(() => connect(addr, { hostKeyStore: store }), { message: /has been revoked/ });
+});
+
+test("hostKeyStore rejects a malformed lookup result", async (t) => {
+  const hostKey = KeyPair.generateEd25519();
+  const server = await SshServer.listen({ hostKeys: [hostKey], onAuthPassword: () => true });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  let stored = 0;
+  const store = (lookup) => ({ lookup, store: () => stored++, trustOnFirstUse: true });
+  await t.throwsAsync(() => connect(addr, { hostKeyStore: store(() => undefined) }), {
+    message: /The host key store lookup returned an invalid value/,
+  });
+  const key = hostKey.clonePublicKey().toOpenSsh();
+  await t.throwsAsync(() => connect(addr, { hostKeyStore: store(() => [{ key }]) }), {
+    message: /The host key store lookup returned an invalid value: A host key entry must be a PublicKey/,
+  });
+  await t.throwsAsync(() => connect(addr, { hostKeyStore: store(async () => [{ key }]) }), {
+    message: /The host key store lookup returned an invalid value: A host key entry must be a PublicKey/,
+  });
+  t.is(stored, 0);
+});
+
+test("authenticateKeyboardInteractive answers prompts from rules", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthKeyboardInteractive: (_user, _submethods, responses) => {
+      if (responses === null) {
+        return {
+          instructions: "Two factors",
+          prompts: [{ prompt: "Password: " }, { prompt: "Verification Code: ", echo: true }, { prompt: "Site: " }],
+        };
+      }
+      return responses.join(",") === "hunter2,123456,lab";
+    },
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  const answers = [
+    { promptMatch: "Password:", response: "hunter2" },
+    { promptMatch: /verification code/i, response: async () => "123456" },
+  ];
+  const challenges = [];
+  const onPrompts = (challenge) => {
+    challenges.push(challenge);
+    return ["lab"];
+  };
+  t.true(await (await connect(addr)).authenticateKeyboardInteractive("user", { answers, onPrompts }));
+  t.deepEqual(challenges, [{ prompts: [{ prompt: "Site: ", echo: false }], name: "", instructions: "Two factors" }]);
+  const strict = await connect(addr);
+  await t.throwsAsync(() => strict.authenticateKeyboardInteractive("user", { answers }), {
+    message: 'No answer for the keyboard-interactive prompt "Site: "',
+  });
+  const lenient = await connect(addr);
+  t.false(await lenient.authenticateKeyboardInteractive("user", { answers, failOnUnmatchedPrompt: false }));
+});
+
+test("authenticatePassword calls a password function when sending it", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: (_user, password) => password === "from-store",
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  let calls = 0;
+  const client = await connect(addr);
+  t.true(
+    await client.authenticatePassword("user", async () => {
+      calls++;
+      return "from-store";
+    }),
+  );
+  t.is(calls, 1);
+  t.false(await (await connect(addr)).authenticatePassword("user", () => "wrong"));
+  await t.throwsAsync(
+    async () => (await connect(addr)).authenticatePassword("user", () => Promise.reject(new Error("locked"))),
+    { message: "locked" },
+  );
+});
+
+test("Config.authRetries repeats and Config.authTimeout bounds attempts", async (t) => {
+  let attempts = 0;
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => ++attempts > 1,
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  // a rejected credential is not sent again unless asked for
+  t.false(await (await connect(addr)).authenticatePassword("user", "password"));
+  t.is(attempts, 1);
+  attempts = 0;
+  const retrying = await connect(addr, { authRetries: 1, authRetryDelay: 10 });
+  t.true(await retrying.authenticatePassword("user", "password"));
+  t.is(attempts, 2);
+  // russh servers delay every rejection by a second
+  attempts = Number.NEGATIVE_INFINITY;
+  const stalled = await connect(addr, { authTimeout: 200 });
+  await t.throwsAsync(() => stalled.authenticatePassword("user", "password"), {
+    message: "The server did not answer the authentication attempt within 200 ms",
+  });
+  t.deepEqual(await stalled.waitClosed(), {
+    local: true,
+    reason: DisconnectReason.ByApplication,
+    message: "Authentication timed out",
+  });
+});
+
+test("authenticate tries the methods in turn", async (t) => {
+  const attempts = [];
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPublicKey: () => attempts.push("publickey") < 0,
+    onAuthPassword: (_user, password) => attempts.push("password") > 0 && password === "pw",
+  });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  const key = KeyPair.generateEd25519();
+  t.is(await (await connect(addr)).authenticate({ user: "user", key, password: "pw" }), "password");
+  t.deepEqual(attempts, ["publickey", "password"]);
+  attempts.length = 0;
+  const client = await connect(`ssh://user@${addr}`);
+  t.is(await client.authenticate({ methods: ["password", "publickey"], key, password: "wrong" }), null);
+  t.deepEqual(attempts, ["password", "publickey"]);
+  await t.throwsAsync(() => client.authenticate({ user: "user", methods: ["hostbased"] }), {
+    message: "Unknown authentication method hostbased",
+  });
+});
+
+test("connect accepts ssh:// URLs", async (t) => {
+  const users = [];
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: (user) => users.push(user) > 0,
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`ssh://de%70loy@${server.host}:${server.port}/`);
+  t.is(client.defaultUser, "deploy");
+  t.true(await client.authenticatePassword(client.defaultUser, "password"));
+  t.deepEqual(users, ["deploy"]);
+  t.is((await connect(`${server.host}:${server.port}`)).defaultUser, null);
+  await t.throwsAsync(() => connect(`sftp://${server.host}:${server.port}`), {
+    message: "Unsupported URL scheme sftp://, only ssh:// URLs can be connected to",
+  });
+  await t.throwsAsync(() => connect(`ssh://deploy:secret@${server.host}:${server.port}`), {
+    message: `Invalid URL ssh://deploy:secret@${server.host}:${server.port}: a password is not allowed`,
+  });
+});
+
+test("idleTimeoutMs closes a silent channel only", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: async (channel, command) => {
+      if (command === "hang") {
+        await channel.write("a");
+        await new Promise((resolve) => setTimeout(resolve, 150));
+        await channel.write("b");
+        return;
+      }
+      await channel.write("done");
+      await channel.exit(0);
+    },
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  await t.throwsAsync(() => client.exec("hang", { idleTimeoutMs: 300 }), {
+    message: "IdleTimeout: no data on the channel for 300 ms",
+  });
+  const chunks = [];
+  await t.throwsAsync(
+    () => client.execStream("hang", { idleTimeoutMs: 300, onData: (data) => chunks.push(data.toString()) }),
+    { message: "IdleTimeout: no data on the channel for 300 ms" },
+  );
+  t.deepEqual(chunks, ["a", "b"]);
+  t.is((await client.exec("echo", { idleTimeoutMs: 300 })).output.toString(), "done");
+});
+
+test("idleTimeoutMs closes silent interactive channels", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    // the server never writes unless told to, and never closes the channel
+    onExec: async (channel, command) => {
+      if (command === "hang") {
+        await channel.write("a");
+        await new Promise((resolve) => setTimeout(resolve, 150));
+        await channel.write("b");
+      } else if (command === "exit") {
+        await channel.exit(0);
+      }
+    },
+    onShell: () => {},
+    onSubsystem: () => {},
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const message = "IdleTimeout: no data on the channel for 300 ms";
+  const closedWith = (channel) =>
+    new Promise((resolve) => channel.onClose((status, error) => resolve(error)));
+
+  // received data resets the timer
+  const session = await client.openSession({ idleTimeoutMs: 300 });
+  await session.exec("hang");
+  const chunks = [];
+  const iterated = await t.throwsAsync(async () => {
+    for await (const chunk of session) chunks.push(chunk.toString());
+  });
+  t.is(iterated.message, message);
+  t.deepEqual(chunks, ["a", "b"]);
+  const error = await closedWith(session);
+  t.is(error.code, "IdleTimeout");
+  t.is(error.message, message);
+  await t.throwsAsync(() => session.write("late"), { message });
+
+  // so does sent data
+  const shell = await client.shell({ idleTimeoutMs: 300 });
+  const shellClosed = closedWith(shell);
+  for (let i = 0; i < 4; i++) {
+    await new Promise((resolve) => setTimeout(resolve, 150));
+    await shell.write("x");
+  }
+  t.is((await shellClosed).code, "IdleTimeout");
+
+  const pty = await client.execPty("sleep", { idleTimeoutMs: 300 });
+  t.is((await closedWith(pty)).message, message);
+
+  const subsystem = await client.openSession();
+  await subsystem.requestSubsystem("netconf", { idleTimeoutMs: 300 });
+  t.is((await closedWith(subsystem)).code, "IdleTimeout");
+
+  // a channel closed by the server in time has no error
+  const exec = await client.openSession({ idleTimeoutMs: 300 });
+  const execClosed = closedWith(exec);
+  await exec.exec("exit");
+  t.is(await execClosed, null);
+});
+
+withSftp("Client.sftp opens an SFTP session", async (t) => {
+  const { sftp } = await openSftp(t);
+  t.is(sftp.version, 3);
+});
+
+withSftp("Sftp.readFile and writeFile transfer whole files", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = Buffer.alloc(100_000, "0123456789");
+  await sftp.writeFile(join(dir, "data.bin"), data, { mode: 0o600 });
+  t.deepEqual(readFileSync(join(dir, "data.bin")), data);
+  t.is(statSync(join(dir, "data.bin")).mode & 0o777, 0o600);
+  t.deepEqual(await sftp.readFile(join(dir, "data.bin")), data);
+  await sftp.writeFile(join(dir, "data.bin"), "short");
+  t.is((await sftp.readFile(join(dir, "data.bin"))).toString(), "short");
+  await t.throwsAsync(() => sftp.readFile(join(dir, "missing")));
+});
+
+withSftp("Sftp.upload reports its progress", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = Buffer.alloc(80_000, "upload");
+  writeFileSync(join(dir, "local.bin"), data);
+  const progress = [];
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), {
+    onProgress: (transferred, total) => progress.push([transferred, total]),
+  });
+  await new Promise((resolve) => setImmediate(resolve));
+  t.deepEqual(readFileSync(join(dir, "remote.bin")), data);
+  t.deepEqual(progress, [
+    [32768n, 80000n],
+    [65536n, 80000n],
+    [80000n, 80000n],
+  ]);
+  await t.throwsAsync(() => sftp.upload(join(dir, "missing"), join(dir, "remote.bin")), {
+    message: /^Failed to read .*missing: /,
+  });
+});
+
+withSftp("Sftp.download replaces the local file once complete", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = Buffer.alloc(70_000, "download");
+  writeFileSync(join(dir, "remote.bin"), data);
+  writeFileSync(join(dir, "local.bin"), "old");
+  const progress = [];
+  await sftp.download(join(dir, "remote.bin"), join(dir, "local.bin"), {
+    onProgress: (transferred, total) => progress.push([transferred, total]),
+  });
+  await new Promise((resolve) => setImmediate(resolve));
+  t.deepEqual(readFileSync(join(dir, "local.bin")), data);
+  t.deepEqual(progress, [
+    [32768n, 70000n],
+    [65536n, 70000n],
+    [70000n, 70000n],
+  ]);
+  await t.throwsAsync(() => sftp.download(join(dir, "missing"), join(dir, "local.bin")));
+  t.deepEqual(readdirSync(dir).sort(), ["local.bin", "remote.bin"]);
+});
+
+withSftp("Sftp.upload and download resume interrupted transfers", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = Buffer.alloc(50_000, "resume");
+  writeFileSync(join(dir, "local.bin"), data);
+  // a part already there is kept, so a different one shows in the result
+  writeFileSync(join(dir, "remote.bin"), Buffer.alloc(20_000, "x"));
+  const progress = [];
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), {
+    resume: true,
+    onProgress: (transferred) => progress.push(transferred),
+  });
+  await new Promise((resolve) => setImmediate(resolve));
+  t.deepEqual(readFileSync(join(dir, "remote.bin")), Buffer.concat([Buffer.alloc(20_000, "x"), data.subarray(20_000)]));
+  t.deepEqual(progress, [50_000n]);
+
+  writeFileSync(join(dir, "local.bin.partial"), Buffer.alloc(40_000, "y"));
+  await sftp.download(join(dir, "remote.bin"), join(dir, "local.bin"), { resume: true });
+  t.deepEqual(readFileSync(join(dir, "local.bin")), Buffer.concat([Buffer.alloc(40_000, "y"), data.subarray(40_000)]));
+  t.false(existsSync(join(dir, "local.bin.partial")));
+
+  // a longer file is not a part of this one, so the transfer starts over
+  writeFileSync(join(dir, "remote.bin"), Buffer.alloc(60_000, "z"));
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), { resume: true });
+  t.deepEqual(readFileSync(join(dir, "remote.bin")), readFileSync(join(dir, "local.bin")));
+});
+
+withSftp("Sftp.upload and download pipeline chunks", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = randomBytes(100_500);
+  writeFileSync(join(dir, "local.bin"), data);
+  const progress = [];
+  const options = { chunkSize: 1000, maxInflight: 8, onProgress: (transferred) => progress.push(transferred) };
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), options);
+  await sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), options);
+  await new Promise((resolve) => setImmediate(resolve));
+  t.deepEqual(readFileSync(join(dir, "remote.bin")), data);
+  t.deepEqual(readFileSync(join(dir, "copy.bin")), data);
+  t.is(progress.length, 202);
+  t.deepEqual(progress.slice(99, 102), [100_000n, 100_500n, 1000n]);
+  await t.throwsAsync(() => sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), { chunkSize: 0 }), {
+    message: "chunkSize and maxInflight must be positive",
+  });
+});
+
+withSftp("Sftp.upload and download honour maxBytesPerSecond", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = randomBytes(30_000);
+  writeFileSync(join(dir, "local.bin"), data);
+  const options = { chunkSize: 10_000, maxBytesPerSecond: 50_000 };
+  // the first chunk goes at once, the other two wait 200 ms each
+  for (const transfer of [
+    () => sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), options),
+    () => sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), options),
+  ]) {
+    const start = Date.now();
+    await transfer();
+    t.true(Date.now() - start >= 380);
+  }
+  t.deepEqual(readFileSync(join(dir, "copy.bin")), data);
+  await t.throwsAsync(() => sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), { maxBytesPerSecond: 0 }), {
+    message: "maxBytesPerSecond must be positive",
+  });
+});
+
+withSftp("Sftp.upload and download split a file over parallel channels", async (t) => {
+  const { sftp, dir, children } = await openSftp(t, { onExec: pipeCommand });
+  const data = randomBytes(1_000_123);
+  writeFileSync(join(dir, "local.bin"), data);
+  const progress = [];
+  const options = {
+    chunkSize: 10_000,
+    maxInflight: 4,
+    parallelism: 4,
+    verifyChecksum: true,
+    onProgress: (transferred, total) => progress.push([transferred, total]),
+  };
+  // every range after the first gets a session of its own
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), options);
+  t.is(children.length, 4);
+  t.deepEqual(readFileSync(join(dir, "remote.bin")), data);
+  await sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), options);
+  t.is(children.length, 7);
+  t.deepEqual(readFileSync(join(dir, "copy.bin")), data);
+  await new Promise((resolve) => setImmediate(resolve));
+  t.is(progress.length, 2 * 101);
+  // the ranges report their bytes together
+  t.is(progress.filter(([transferred]) => transferred === 1_000_123n).length, 2);
+  t.true(progress.every(([, total]) => total === 1_000_123n));
+  // a range shorter than maxInflight chunks is not worth a channel
+  writeFileSync(join(dir, "small.bin"), data.subarray(0, 50_000));
+  await sftp.upload(join(dir, "small.bin"), join(dir, "remote-small.bin"), options);
+  await sftp.download(join(dir, "remote-small.bin"), join(dir, "copy-small.bin"), options);
+  t.is(children.length, 7);
+  t.deepEqual(readFileSync(join(dir, "copy-small.bin")), data.subarray(0, 50_000));
+});
+
+withSftp("Sftp.upload with atomic renames a temporary file into place", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "local.bin"), "new");
+  writeFileSync(join(dir, "remote.bin"), "old");
+  const names = [];
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), {
+    atomic: true,
+    // the remote path keeps the old content until the upload completes
+    onProgress: () => names.push(readdirSync(dir).sort(), readFileSync(join(dir, "remote.bin"), "utf8")),
+  });
+  t.is(readFileSync(join(dir, "remote.bin"), "utf8"), "new");
+  t.deepEqual(readdirSync(dir).sort(), ["local.bin", "remote.bin"]);
+  t.regex(names[0][2], /^remote\.bin\.partial-[0-9a-f]{16}$/);
+  t.is(names[1], "old");
+  await t.throwsAsync(() => sftp.upload(join(dir, "local.bin"), join(dir, "missing/remote.bin"), { atomic: true }));
+  await t.throwsAsync(() => sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), { atomic: true, resume: true }), {
+    message: "resume and atomic can not be combined",
+  });
+  t.deepEqual(readdirSync(dir).sort(), ["local.bin", "remote.bin"]);
+});
+
+withSftp("Sftp.upload and download with preserve copy permissions and times", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "local.bin"), "data", { mode: 0o640 });
+  utimesSync(join(dir, "local.bin"), 1_600_000_000, 1_700_000_000);
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), { preserve: true, atomic: true });
+  const { permissions, atime, mtime } = await sftp.stat(join(dir, "remote.bin"));
+  t.deepEqual([permissions, atime, mtime], [0o640, 1_600_000_000, 1_700_000_000]);
+  await sftp.chmod(join(dir, "remote.bin"), 0o604);
+  await sftp.utimes(join(dir, "remote.bin"), 1_500_000_000, 1_550_000_000);
+  await sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), { preserve: true });
+  const copy = statSync(join(dir, "copy.bin"));
+  t.deepEqual([copy.mode & 0o777, copy.atimeMs, copy.mtimeMs], [0o604, 1_500_000_000_000, 1_550_000_000_000]);
+  await sftp.download(join(dir, "remote.bin"), join(dir, "plain.bin"));
+  t.not(statSync(join(dir, "plain.bin")).mtimeMs, 1_550_000_000_000);
+});
+
+withSftp("Sftp.readdir lists entries with their attributes", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "file"), "hello", { mode: 0o640 });
+  utimesSync(join(dir, "file"), 1_600_000_000, 1_700_000_000);
+  mkdirSync(join(dir, "subdir"));
+  symlinkSync("file", join(dir, "link"));
+  const entries = (await sftp.readdir(dir)).sort((a, b) => a.name.localeCompare(b.name));
+  t.deepEqual(
+    entries.map(({ name, attributes }) => [name, attributes.fileType]),
+    [
+      ["file", FileType.File],
+      ["link", FileType.Symlink],
+      ["subdir", FileType.Directory],
+    ],
+  );
+  const { attributes } = entries[0];
+  t.is(attributes.size, 5n);
+  t.is(attributes.permissions, 0o640);
+  t.is(attributes.uid, process.getuid());
+  t.is(attributes.atime, 1_600_000_000);
+  t.is(attributes.mtime, 1_700_000_000);
+  await t.throwsAsync(() => sftp.readdir(join(dir, "missing")));
+});
+
+withSftp("Sftp.stat, lstat and exists", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "file"), "hello", { mode: 0o600 });
+  symlinkSync("file", join(dir, "link"));
+  symlinkSync("missing", join(dir, "dangling"));
+  const stat = await sftp.stat(join(dir, "link"));
+  t.is(stat.fileType, FileType.File);
+  t.is(stat.size, 5n);
+  t.is(stat.permissions, 0o600);
+  t.is((await sftp.lstat(join(dir, "link"))).fileType, FileType.Symlink);
+  t.is((await sftp.stat(dir)).fileType, FileType.Directory);
+  t.true(await sftp.exists(join(dir, "link")));
+  t.false(await sftp.exists(join(dir, "dangling")));
+  t.false(await sftp.exists(join(dir, "missing")));
+  await t.throwsAsync(() => sftp.stat(join(dir, "missing")));
+});
+
+withSftp("Sftp.mkdir and rmdir work recursively", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  await sftp.mkdir(join(dir, "a/b/c"), { recursive: true, mode: 0o750 });
+  t.true(statSync(join(dir, "a/b/c")).isDirectory());
+  t.is(statSync(join(dir, "a/b")).mode & 0o777, 0o750);
+  await sftp.mkdir(join(dir, "a/b/c"), { recursive: true });
+  await t.throwsAsync(() => sftp.mkdir(join(dir, "a/b/c")));
+  writeFileSync(join(dir, "a/file"), "");
+  await t.throwsAsync(() => sftp.mkdir(join(dir, "a/file/d"), { recursive: true }), {
+    message: `${join(dir, "a/file")} exists and is not a directory`,
+  });
+  mkdirSync(join(dir, "kept"));
+  symlinkSync(join(dir, "kept"), join(dir, "a/b/link"));
+  await t.throwsAsync(() => sftp.rmdir(join(dir, "a")));
+  await sftp.rmdir(join(dir, "a"), { recursive: true });
+  t.false(existsSync(join(dir, "a")));
+  t.true(existsSync(join(dir, "kept")));
+  await sftp.rmdir(join(dir, "kept"));
+  t.false(existsSync(join(dir, "kept")));
+});
+
+withSftp("Sftp.rename replaces the target", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "new"), "new");
+  writeFileSync(join(dir, "current"), "current");
+  await sftp.rename(join(dir, "new"), join(dir, "current"));
+  t.is(readFileSync(join(dir, "current"), "utf8"), "new");
+  t.false(existsSync(join(dir, "new")));
+  await t.throwsAsync(() => sftp.rename(join(dir, "new"), join(dir, "other")));
+});
+
+withSftp("Sftp.unlink and rm remove files", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  for (const name of ["a.log", "b.log", "c.txt", ".hidden.log", "[x].txt"]) {
+    writeFileSync(join(dir, name), "");
+  }
+  mkdirSync(join(dir, "logs"));
+  writeFileSync(join(dir, "logs/old.log"), "");
+  await sftp.unlink(join(dir, "c.txt"));
+  t.deepEqual(await sftp.rm(join(dir, "*.log")), [
+    { path: join(dir, "a.log") },
+    { path: join(dir, "b.log") },
+  ]);
+  t.deepEqual(await sftp.rm(join(dir, "\\[x].txt")), [{ path: join(dir, "[x].txt") }]);
+  const results = await sftp.rm([join(dir, ".hidden.log"), join(dir, "missing"), join(dir, "logs")]);
+  t.deepEqual(
+    results.map(({ path, error }) => [path, error === undefined]),
+    [
+      [join(dir, ".hidden.log"), true],
+      [join(dir, "missing"), false],
+      [join(dir, "logs"), false],
+    ],
+  );
+  t.is(results[2].error, `${join(dir, "logs")} is a directory`);
+  t.deepEqual(await sftp.rm(join(dir, "l[!a]gs"), { recursive: true }), [{ path: join(dir, "logs") }]);
+  t.deepEqual(readdirSync(dir), []);
+});
+
+withSftp("Sftp.symlink and readlink", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  mkdirSync(join(dir, "releases/2"), { recursive: true });
+  await sftp.symlink("releases/2", join(dir, "current"));
+  t.is(await sftp.readlink(join(dir, "current")), "releases/2");
+  t.true(statSync(join(dir, "current")).isDirectory());
+  await t.throwsAsync(() => sftp.readlink(join(dir, "releases")));
+});
+
+withSftp("Sftp.copy duplicates files on the server", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = randomBytes(100_000);
+  writeFileSync(join(dir, "file"), data);
+  writeFileSync(join(dir, "copy"), "longer than nothing");
+  await sftp.copy(join(dir, "file"), join(dir, "copy"));
+  t.deepEqual(readFileSync(join(dir, "copy")), data);
+  await t.throwsAsync(() => sftp.copy(join(dir, "missing"), join(dir, "copy")));
+  t.deepEqual(readFileSync(join(dir, "copy")), data);
+});
+
+withSftp("Sftp.link creates hard links", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "file"), "shared");
+  await sftp.link(join(dir, "file"), join(dir, "link"));
+  t.is(statSync(join(dir, "file")).ino, statSync(join(dir, "link")).ino);
+  t.is(statSync(join(dir, "file")).nlink, 2);
+  await t.throwsAsync(() => sftp.link(join(dir, "file"), join(dir, "link")));
+});
+
+withSftp("Sftp.chmod, chown and utimes", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const path = join(dir, "file");
+  writeFileSync(path, "", { mode: 0o644 });
+  await sftp.chmod(path, 0o600);
+  t.is(statSync(path).mode & 0o777, 0o600);
+  await sftp.chown(path, process.getuid(), process.getgid());
+  await sftp.utimes(path, 1_600_000_000, 1_700_000_000);
+  const { atime, mtime, uid } = await sftp.stat(path);
+  t.deepEqual([atime, mtime, uid], [1_600_000_000, 1_700_000_000, process.getuid()]);
+  await t.throwsAsync(() => sftp.chmod(join(dir, "missing"), 0o600));
+});
+
+withSftp("Sftp.realpath canonicalizes paths", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  mkdirSync(join(dir, "a/b"), { recursive: true });
+  symlinkSync("a/b", join(dir, "link"));
+  const real = realpathSync(dir);
+  t.is(await sftp.realpath(`${dir}/a/../link/.`), join(real, "a/b"));
+  t.is(await sftp.realpath("."), process.cwd());
+  await t.throwsAsync(() => sftp.realpath(join(dir, "missing/x")));
+});
+
+withSftp("Sftp.open reads and writes at offsets", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const path = join(dir, "file");
+  const file = await sftp.open(path, "w+", 0o600);
+  await file.write(0, "hello world");
+  await file.write(6, "there");
+  t.is((await file.read(6, 100)).toString(), "there");
+  await file.setstat({ size: 5, mode: 0o640 });
+  const { size, permissions } = await file.stat();
+  t.deepEqual([size, permissions], [5n, 0o640]);
+  await file.fsync();
+  await t.throwsAsync(() => file.setstat({ uid: 0 }), { message: "uid and gid must be set together" });
+  await file.close();
+  t.is(readFileSync(path, "utf8"), "hello");
+  await t.throwsAsync(() => sftp.open(path, "wx"));
+  await t.throwsAsync(() => sftp.open(path, "rw"), { message: "Unknown file open flags rw" });
+});
+
+withSftp("Sftp takes and gives offsets beyond 4 GiB as bigint", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const path = join(dir, "sparse");
+  const file = await sftp.open(path, "w+");
+  const offset = 5n * 2n ** 30n;
+  await file.write(offset, "end");
+  t.is((await file.stat()).size, offset + 3n);
+  t.is((await file.read(offset + 1n, 10)).toString(), "nd");
+  t.is((await sftp.stat(path)).size, offset + 3n);
+  const tail = await (await sftp.createReadStream(path, { start: offset })).toArray();
+  t.is(Buffer.concat(tail).toString(), "end");
+  await t.throwsAsync(() => file.read(-1, 10), { message: "offset must be a non-negative integer below 2^64" });
+  await t.throwsAsync(() => file.read(2n ** 64n, 10), { message: "offset must be a non-negative integer below 2^64" });
+  await file.close();
+});
+
+withSftp("Sftp.createReadStream and createWriteStream stream remote files", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const data = Buffer.alloc(100_000, "abcdefgh");
+  const output = await sftp.createWriteStream(join(dir, "file"));
+  t.true(output instanceof Writable);
+  await pipeline(Readable.from([data.subarray(0, 70_000), data.subarray(70_000)]), output);
+  t.deepEqual(readFileSync(join(dir, "file")), data);
+  const input = await sftp.createReadStream(join(dir, "file"));
+  t.true(input instanceof Readable);
+  await pipeline(input, createGzip(), await sftp.createWriteStream(join(dir, "file.gz")));
+  t.deepEqual(gunzipSync(readFileSync(join(dir, "file.gz"))), data);
+  const part = await (await sftp.createReadStream(join(dir, "file"), { start: 2, end: 40_001 })).toArray();
+  t.deepEqual(Buffer.concat(part), data.subarray(2, 40_002));
+  const appended = await sftp.createWriteStream(join(dir, "file"), { flags: "a" });
+  appended.end("!");
+  await finished(appended);
+  t.deepEqual(readFileSync(join(dir, "file")), Buffer.concat([data, Buffer.from("!")]));
+  // stopping early closes the file
+  const stopped = await sftp.createReadStream(join(dir, "file"));
+  for await (const chunk of stopped) {
+    t.truthy(chunk.length);
+    break;
+  }
+  t.true(stopped.destroyed);
+  await t.throwsAsync(async () => pipeline(await sftp.createReadStream(join(dir, "missing")), new PassThrough()), {
+    code: "NO_SUCH_FILE",
+  });
+});
+
+withSftp("Sftp transfers with fsync flush the copy to disk", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  writeFileSync(join(dir, "local.bin"), "data");
+  await sftp.writeFile(join(dir, "written.bin"), "written", { fsync: true });
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), { fsync: true });
+  await sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), { fsync: true });
+  t.is(readFileSync(join(dir, "written.bin"), "utf8"), "written");
+  t.is(readFileSync(join(dir, "copy.bin"), "utf8"), "data");
+});
+
+withSftp("Sftp.extensions lists what the server announced", async (t) => {
+  const { sftp } = await openSftp(t);
+  const extensions = sftp.extensions();
+  t.is(extensions["posix-rename@openssh.com"], "1");
+  t.is(extensions["statvfs@openssh.com"], "2");
+});
+
+withSftp("Sftp.limits caps the size of reads and writes", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const { maxReadLength, maxWriteLength } = sftp.limits();
+  t.true(maxReadLength > 0 && maxReadLength < 1_000_000);
+  t.true(maxWriteLength > 0 && maxWriteLength < 1_000_000);
+  const data = randomBytes(1_500_000);
+  writeFileSync(join(dir, "local.bin"), data);
+  const options = { chunkSize: 1_000_000 };
+  await sftp.upload(join(dir, "local.bin"), join(dir, "remote.bin"), options);
+  await sftp.download(join(dir, "remote.bin"), join(dir, "copy.bin"), options);
+  t.deepEqual(readFileSync(join(dir, "copy.bin")), data);
+});
+
+withSftp("Sftp.checksum falls back to running sha256sum", async (t) => {
+  const commands = [];
+  const { sftp, dir } = await openSftp(t, {
+    onExec: (channel, command) => {
+      commands.push(command);
+      execFile("sh", ["-c", command], { encoding: "buffer" }, async (err, stdout) => {
+        await channel.write(stdout);
+        await channel.exit(err ? (err.code ?? 1) : 0);
+      });
+    },
+  });
+  writeFileSync(join(dir, "it's here"), "hello");
+  t.is(
+    await sftp.checksum(join(dir, "it's here")),
+    "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+  );
+  t.is(await sftp.checksum(join(dir, "it's here"), { algorithm: "md5" }), "5d41402abc4b2a76b9719d911017c592");
+  t.is(commands[0], `sha256sum -- '${dir}/it'\\''s here'`);
+  await t.throwsAsync(() => sftp.checksum(join(dir, "missing")), { message: /^NonZeroExit/ });
+  await t.throwsAsync(() => sftp.checksum(join(dir, "it's here"), { algorithm: "crc32" }), {
+    message: "Unknown checksum algorithm crc32",
+  });
+});
+
+withSftp("Sftp.statvfs describes the remote filesystem", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const stats = await sftp.statvfs(dir);
+  t.true(stats.fragmentSize > 0);
+  t.true(stats.blocks >= stats.blocksFree);
+  t.true(stats.blocksFree >= stats.blocksAvailable);
+  t.true(stats.nameMax > 0);
+});
+
+withSftp("Sftp.glob matches remote paths", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  mkdirSync(join(dir, "logs/old/deep"), { recursive: true });
+  for (const name of ["a.gz", "b.txt", ".hidden.gz", "old/c.gz", "old/deep/d.gz"]) {
+    writeFileSync(join(dir, "logs", name), "");
+  }
+  t.deepEqual(await sftp.glob("logs/*.gz", { cwd: dir }), ["logs/a.gz"]);
+  t.deepEqual(await sftp.glob("logs/[ab].*", { cwd: dir }), ["logs/a.gz", "logs/b.txt"]);
+  t.deepEqual(await sftp.glob(`${dir}/logs/**/*.gz`), [
+    `${dir}/logs/a.gz`,
+    `${dir}/logs/old/c.gz`,
+    `${dir}/logs/old/deep/d.gz`,
+  ]);
+  t.deepEqual(await sftp.glob("logs/*/c.gz", { cwd: dir }), ["logs/old/c.gz"]);
+  t.deepEqual(await sftp.glob("logs/b.txt", { cwd: dir }), ["logs/b.txt"]);
+  t.deepEqual(await sftp.glob("logs/missing.txt", { cwd: dir }), []);
+  t.deepEqual(await sftp.glob("missing/*", { cwd: dir }), []);
+});
+
+withSftp("Sftp.walk yields the entries of a tree", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  mkdirSync(join(dir, "a/b"), { recursive: true });
+  writeFileSync(join(dir, "a/b/file"), "");
+  writeFileSync(join(dir, "top"), "");
+  symlinkSync("a", join(dir, "link"));
+  for (let i = 0; i < 300; i++) {
+    writeFileSync(join(dir, "a", `many-${i}`), "");
+  }
+  const entries = [];
+  for await (const { path, attributes } of sftp.walk(dir)) {
+    entries.push([path.slice(dir.length + 1), attributes.fileType]);
+  }
+  t.is(entries.length, 305);
+  const index = (path) => entries.findIndex(([entry]) => entry === path);
+  t.true(index("a") < index("a/b") && index("a/b") < index("a/b/file"));
+  t.deepEqual(entries[index("link")], ["link", FileType.Symlink]);
+  t.deepEqual(entries[index("a/b/file")], ["a/b/file", FileType.File]);
+  for await (const entry of sftp.walk(dir)) {
+    t.truthy(entry.path);
+    break;
+  }
+  await t.throwsAsync(async () => {
+    for await (const entry of sftp.walk(join(dir, "missing"))) {
+      t.fail(entry.path);
+    }
+  });
+});
+
+withSftp("Sftp.transfer runs jobs a few at a time", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const jobs = [];
+  for (let i = 0; i < 5; i++) {
+    writeFileSync(join(dir, `local-${i}`), randomBytes(40_000));
+    jobs.push({ direction: TransferDirection.Upload, localPath: join(dir, `local-${i}`), remotePath: join(dir, `remote-${i}`) });
+  }
+  jobs.push({ direction: TransferDirection.Download, localPath: join(dir, "copy"), remotePath: join(dir, "missing") });
+  const progress = [];
+  const events = [];
+  let running = 0;
+  let mostRunning = 0;
+  const results = await sftp.transfer(jobs, {
+    concurrency: 2,
+    onProgress: (transferred, total) => progress.push([transferred, total]),
+    onJob: ({ index, state }) => {
+      running += state === TransferState.Started ? 1 : -1;
+      mostRunning = Math.max(mostRunning, running);
+      events.push([index, state]);
+    },
+  });
+  await new Promise((resolve) => setImmediate(resolve));
+  t.deepEqual(
+    results.map(({ error }) => !error),
+    [true, true, true, true, true, false],
+  );
+  for (let i = 0; i < 5; i++) {
+    t.deepEqual(readFileSync(join(dir, `remote-${i}`)), readFileSync(join(dir, `local-${i}`)));
+  }
+  t.is(mostRunning, 2);
+  t.deepEqual(events.filter(([index]) => index === 5), [
+    [5, TransferState.Started],
+    [5, TransferState.Failed],
+  ]);
+  t.deepEqual(progress.at(-1), [200_000n, 200_000n]);
+  await t.throwsAsync(() => sftp.transfer(jobs, { concurrency: 0 }), { message: "concurrency must be positive" });
+});
+
+withSftp("Sftp.tail passes the lines of a file as it grows", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const path = join(dir, "log");
+  writeFileSync(path, "one\r\ntwo\nthree");
+  const lines = [];
+  await sftp.tail(path, { onLine: (line) => lines.push(line) });
+  await new Promise((resolve) => setImmediate(resolve));
+  t.deepEqual(lines, ["one", "two", "three"]);
+
+  lines.length = 0;
+  const controller = new AbortController();
+  const tail = sftp.tail(path, {
+    onLine: (line) => lines.push(line),
+    follow: true,
+    pollIntervalMs: 10,
+    signal: controller.signal,
+  });
+  const until = async (count) => {
+    while (lines.length < count) {
+      await new Promise((resolve) => setTimeout(resolve, 10));
+    }
+  };
+  await new Promise((resolve) => setTimeout(resolve, 50));
+  appendFileSync(path, " and more\nfour\nfi");
+  await new Promise((resolve) => setTimeout(resolve, 50));
+  appendFileSync(path, "ve\n");
+  await until(3);
+  writeFileSync(path, "rotated\n");
+  await until(4);
+  controller.abort();
+  await tail;
+  t.deepEqual(lines, [" and more", "four", "five", "rotated"]);
+});
+
+withSftp("Sftp.sync uploads only what changed", async (t) => {
+  // comparing checksums runs sha256sum on the server
+  const { sftp, dir } = await openSftp(t, {
+    onExec: (channel, command) => {
+      execFile("sh", ["-c", command], { encoding: "buffer" }, async (err, stdout) => {
+        await channel.write(stdout);
+        await channel.exit(err ? (err.code ?? 1) : 0);
+      });
+    },
+  });
+  const local = join(dir, "local");
+  const remote = join(dir, "remote");
+  mkdirSync(join(local, "a"), { recursive: true });
+  writeFileSync(join(local, "a/file"), "one");
+  writeFileSync(join(local, "top"), "top");
+  const plan = (changes) => changes.map(({ action, path }) => [action, path]);
+  t.deepEqual(plan(await sftp.sync(local, remote, { dryRun: true })), [
+    [SyncAction.Mkdir, ""],
+    [SyncAction.Mkdir, "a"],
+    [SyncAction.Upload, "a/file"],
+    [SyncAction.Upload, "top"],
+  ]);
+  t.false(existsSync(remote));
+  await sftp.sync(local, remote);
+  t.is(readFileSync(join(remote, "a/file"), "utf8"), "one");
+  t.deepEqual(await sftp.sync(local, remote), []);
+  writeFileSync(join(local, "top"), "two");
+  utimesSync(join(local, "top"), 1000, 1000);
+  mkdirSync(join(remote, "extra/deep"), { recursive: true });
+  t.deepEqual(plan(await sftp.sync(local, remote, { delete: true })), [
+    [SyncAction.Delete, "extra"],
+    [SyncAction.Upload, "top"],
+  ]);
+  t.is(readFileSync(join(remote, "top"), "utf8"), "two");
+  t.false(existsSync(join(remote, "extra")));
+  // same size and time, different content
+  writeFileSync(join(remote, "top"), "owt");
+  utimesSync(join(remote, "top"), 1000, 1000);
+  t.deepEqual(await sftp.sync(local, remote), []);
+  const checked = await sftp.sync(local, remote, { compare: "checksum" });
+  t.deepEqual(plan(checked), [[SyncAction.Upload, "top"]]);
+  t.is(readFileSync(join(remote, "top"), "utf8"), "two");
+});
+
+withSftp("Sftp.sync replaces a file with a directory and the other way round", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  const local = join(dir, "local");
+  const remote = join(dir, "remote");
+  mkdirSync(join(local, "was-file/deep"), { recursive: true });
+  writeFileSync(join(local, "was-file/deep/leaf"), "leaf");
+  writeFileSync(join(local, "was-dir"), "file");
+  mkdirSync(join(remote, "was-dir/old"), { recursive: true });
+  writeFileSync(join(remote, "was-dir/old/stale"), "stale");
+  writeFileSync(join(remote, "was-file"), "file");
+  const plan = (changes) => changes.map(({ action, path }) => [action, path]);
+  await t.throwsAsync(() => sftp.sync(local, remote), {
+    message: `${join(remote, "was-dir")} is not a file, replacing it takes delete`,
+  });
+  t.deepEqual(plan(await sftp.sync(local, remote, { delete: true })), [
+    [SyncAction.Delete, "was-dir"],
+    [SyncAction.Upload, "was-dir"],
+    [SyncAction.Delete, "was-file"],
+    [SyncAction.Mkdir, "was-file"],
+    [SyncAction.Mkdir, "was-file/deep"],
+    [SyncAction.Upload, "was-file/deep/leaf"],
+  ]);
+  t.is(readFileSync(join(remote, "was-dir"), "utf8"), "file");
+  t.is(readFileSync(join(remote, "was-file/deep/leaf"), "utf8"), "leaf");
+  t.deepEqual(await sftp.sync(local, remote, { delete: true }), []);
+});
+
+withSftp("Sftp errors carry the name of the status as their code", async (t) => {
+  const { client, sftp, dir } = await openSftp(t);
+  await t.throwsAsync(() => sftp.stat(join(dir, "missing")), { code: "NO_SUCH_FILE", message: /^NO_SUCH_FILE: / });
+  writeFileSync(join(dir, "locked"), "", { mode: 0o000 });
+  if (process.getuid() !== 0) {
+    await t.throwsAsync(() => sftp.readFile(join(dir, "locked")), { code: "PERMISSION_DENIED" });
+  }
+  mkdirSync(join(dir, "full"));
+  writeFileSync(join(dir, "full/file"), "");
+  await t.throwsAsync(() => sftp.rmdir(join(dir, "full")), { code: "FAILURE", message: /^FAILURE: / });
+  const file = await sftp.open(join(dir, "full/file"), "r");
+  await file.close();
+  await t.throwsAsync(() => file.stat(), { code: "FAILURE" });
+  await t.throwsAsync(
+    async () => {
+      for await (const entry of sftp.walk(join(dir, "missing"))) {
+        t.fail(entry.path);
+      }
+    },
+    { code: "NO_SUCH_FILE" },
+  );
+  // the directory opens, but reading it fails
+  await t.throwsAsync(async () => (await sftp.createReadStream(join(dir, "full"))).toArray(), {
+    code: "FAILURE",
+  });
+  await t.throwsAsync(() => client.downloadFile(join(dir, "missing"), join(dir, "nothing")), {
+    code: "NO_SUCH_FILE",
+  });
+  await t.throwsAsync(() => client.uploadFile(join(dir, "full/file"), join(dir, "missing/file")), {
+    code: "NO_SUCH_FILE",
+  });
+  // other errors keep the napi status
+  await t.throwsAsync(() => sftp.sync(dir, dir, { compare: "size" }), { code: "InvalidArg" });
+});
+
+withSftp("Sftp.expandPath resolves the home directory", async (t) => {
+  const { sftp, dir } = await openSftp(t);
+  t.is(await sftp.expandPath("~"), realpathSync(homedir()));
+  mkdirSync(join(dir, "a"));
+  t.is(await sftp.expandPath(`${dir}/a/..`), realpathSync(dir));
+});
+
+withSftp("Client.sftp keeps the session alive and opens it again", async (t) => {
+  const { sftp, dir, children } = await openSftp(t, {}, { keepaliveIntervalMs: 50, reopen: true });
+  const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+  let received = 0;
+  children[0].stdout.on("data", () => received++);
+  await sleep(300);
+  t.true(received >= 3);
+  children[0].kill();
+  while (children.length < 2) {
+    await sleep(10);
+  }
+  await sleep(100);
+  t.is((await sftp.stat(dir)).fileType, FileType.Directory);
+  await t.throwsAsync(() => openSftp(t, {}, { timeoutMs: 0 }), { message: "timeoutMs must be positive" });
+});
+
+// the tests of the SCP support need scp on the server
+const withScp = existsSync("/usr/bin/scp") ? test : test.skip;
+
+// run the command with the channel as its stdin and stdout
+function pipeCommand(channel, command) {
+  const child = spawn("sh", ["-c", command], { stdio: ["pipe", "pipe", "pipe"] });
+  let written = Promise.resolve();
+  child.stdin.on("error", () => {});
+  channel.onData((data) => child.stdin.write(data));
+  channel.onEof(() => child.stdin.end());
+  child.stdout.on("data", (data) => {
+    written = written.then(() => channel.write(data)).catch(() => {});
+  });
+  child.stderr.on("data", (data) => {
+    written = written.then(() => channel.writeStderr(data)).catch(() => {});
+  });
+  child.on("exit", (code) => written.then(() => channel.exit(code ?? 1)).catch(() => {}));
+}
+
+withScp("Client.scpUpload and scpDownload copy files and trees", async (t) => {
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: pipeCommand,
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const dir = mkdtempSync(join(tmpdir(), "ssh-scp-test-"));
+  t.teardown(() => rmSync(dir, { recursive: true, force: true }));
+  const data = randomBytes(100000);
+  writeFileSync(join(dir, "file"), data, { mode: 0o640 });
+  await client.scpUpload(join(dir, "file"), join(dir, "uploaded"));
+  t.true(readFileSync(join(dir, "uploaded")).equals(data));
+  await client.scpDownload(join(dir, "uploaded"), join(dir, "downloaded"));
+  t.true(readFileSync(join(dir, "downloaded")).equals(data));
+  mkdirSync(join(dir, "tree/deep"), { recursive: true });
+  writeFileSync(join(dir, "tree/deep/leaf"), "leaf");
+  writeFileSync(join(dir, "tree/empty"), "");
+  utimesSync(join(dir, "tree/deep/leaf"), 1000, 2000);
+  await t.throwsAsync(() => client.scpUpload(join(dir, "tree"), join(dir, "copy")), {
+    message: `${join(dir, "tree")} is a directory, copying it takes recursive`,
+  });
+  await client.scpUpload(join(dir, "tree"), join(dir, "copy"), { recursive: true, preserve: true });
+  t.is(readFileSync(join(dir, "copy/deep/leaf"), "utf8"), "leaf");
+  t.is(statSync(join(dir, "copy/deep/leaf")).mtimeMs, 2000000);
+  mkdirSync(join(dir, "into"));
+  await client.scpDownload(join(dir, "copy"), join(dir, "into"), { recursive: true, preserve: true });
+  t.deepEqual(readdirSync(join(dir, "into/copy")).sort(), ["deep", "empty"]);
+  t.is(readFileSync(join(dir, "into/copy/deep/leaf"), "utf8"), "leaf");
+  t.is(statSync(join(dir, "into/copy/deep/leaf")).mtimeMs, 2000000);
+  await client.scpDownload(join(dir, "file"), join(dir, "kept"), { preserve: true });
+  t.is(statSync(join(dir, "kept")).mode & 0o777, 0o640);
+  await t.throwsAsync(() => client.scpDownload(join(dir, "missing"), join(dir, "nothing")), {
+    message: /No such file or directory/,
+  });
+  t.false(existsSync(join(dir, "nothing")));
+});
+
+withScp("Client.uploadFile and downloadFile fall back to SCP without the sftp subsystem", async (t) => {
+  const dir = mkdtempSync(join(tmpdir(), "ssh-transfer-test-"));
+  t.teardown(() => rmSync(dir, { recursive: true, force: true }));
+  const data = randomBytes(100000);
+  writeFileSync(join(dir, "file"), data, { mode: 0o640 });
+  utimesSync(join(dir, "file"), 1000, 2000);
+
+  const server = await SshServer.listen({
+    hostKeys: [KeyPair.generateEd25519()],
+    onAuthPassword: () => true,
+    onExec: pipeCommand,
+  });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  const progress = [];
+  const started = Date.now();
+  const uploaded = await client.uploadFile(join(dir, "file"), join(dir, "over-scp"), {
+    preserve: true,
+    maxBytesPerSecond: 200000,
+    onProgress: (transferred, total) => progress.push([transferred, total]),
+  });
+  t.is(uploaded.transport, FileTransport.Scp);
+  // the bucket starts with a chunk, the rest takes a third of a second at the rate
+  t.true(Date.now() - started >= 250);
+  t.deepEqual(progress.at(-1), [100000n, 100000n]);
+  t.true(readFileSync(join(dir, "over-scp")).equals(data));
+  t.is(statSync(join(dir, "over-scp")).mtimeMs, 2000000);
+  t.is(statSync(join(dir, "over-scp")).mode & 0o777, 0o640);
+  const downloaded = await client.downloadFile(join(dir, "over-scp"), join(dir, "back"), { preserve: true });
+  t.is(downloaded.transport, FileTransport.Scp);
+  t.true(readFileSync(join(dir, "back")).equals(data));
+  t.is(statSync(join(dir, "back")).mtimeMs, 2000000);
+  await t.throwsAsync(() => client.downloadFile(join(dir, "missing"), join(dir, "nothing")), {
+    message: /^The server refused the sftp subsystem request, and over scp: .*No such file or directory/,
+  });
+});
+
+withSftp("Client.uploadFile and downloadFile prefer SFTP", async (t) => {
+  const { client, dir } = await openSftp(t, { onExec: (channel) => channel.exit(127) });
+  const data = randomBytes(100000);
+  writeFileSync(join(dir, "file"), data, { mode: 0o640 });
+  utimesSync(join(dir, "file"), 1000, 2000);
+  const progress = [];
+  const uploaded = await client.uploadFile(join(dir, "file"), join(dir, "over-sftp"), {
+    preserve: true,
+    onProgress: (transferred, total) => progress.push([transferred, total]),
+  });
+  t.is(uploaded.transport, FileTransport.Sftp);
+  t.deepEqual(progress.at(-1), [100000n, 100000n]);
+  t.true(readFileSync(join(dir, "over-sftp")).equals(data));
+  t.is(statSync(join(dir, "over-sftp")).mtimeMs, 2000000);
+  const downloaded = await client.downloadFile(join(dir, "over-sftp"), join(dir, "back"));
+  t.is(downloaded.transport, FileTransport.Sftp);
+  t.true(readFileSync(join(dir, "back")).equals(data));
+});
+
+test("Client.sftp fails without the sftp subsystem", async (t) => {
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPassword: () => true });
+  t.teardown(() => server.close());
+  const client = await connect(`${server.host}:${server.port}`);
+  await client.authenticatePassword("user", "password");
+  await t.throwsAsync(() => client.sftp(), { message: "The server refused the sftp subsystem request" });
+});
+
+test("legacy compatibility and negotiation errors", async (t) => {
+  const server = await SshServer.listen({ hostKeys: [KeyPair.generateEd25519()], onAuthPassword: () => true });
+  t.teardown(() => server.close());
+  const addr = `${server.host}:${server.port}`;
+  const legacy = await connect(addr, { client: { compatibility: "legacy" } });
+  t.true(await legacy.authenticatePassword("user", "password"));
+  await t.throwsAsync(() => connect(addr, { client: { preferredCiphers: ["aes128-cbc", "3des-cbc"] } }), {
+    message: /^No common cipher algorithm\. The client offered aes128-cbc,3des-cbc; the server offered .*aes256-gcm@openssh\.com/,
+  });
+  await t.throwsAsync(() => connect(addr, { client: { compatibility: "ancient" } }), {
+    message: "Unknown compatibility preset: ancient",
+  });
+});
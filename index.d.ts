@@ -1,35 +1,336 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
+/** The local ssh-agent, for managing its keys like `ssh-add` does. */
+export declare class Agent {
+  /**
+   * Connect to the agent at `path`, a Unix socket or a Windows named pipe. Defaults to
+   * `SSH_AUTH_SOCK`, and on Windows then to the Windows OpenSSH agent and Pageant.
+   */
+  static connect(path?: string | undefined | null): Promise<Agent>
+  /** The public keys the agent holds. */
+  listIdentities(): Promise<Array<PublicKey>>
+  /**
+   * # Safety
+   *
+   * `key` is borrowed until the returned promise settles and must not be freed before.
+   *
+   * Add `key` to the agent, replacing the constraints if it already holds the key.
+   */
+  addIdentity(key: KeyPair, options?: AddIdentityOptions | undefined | null): Promise<void>
+  /**
+   * Have the agent load the keys of a PKCS#11 smartcard or HSM through the module at
+   * `provider`, like `ssh-add -s`, so `Client.authenticateAgent` can sign with them.
+   * The agent does the signing; OpenSSH's agent only loads modules from its `-P` allowlist.
+   * Fails if the agent holds no new keys afterwards, as for a refused module or a wrong `pin`.
+   */
+  addSmartcardKey(provider: string, pin?: string | undefined | null, options?: AddIdentityOptions | undefined | null): Promise<void>
+  /**
+   * # Safety
+   *
+   * `key` is borrowed until the returned promise settles and must not be freed before.
+   *
+   * Remove `key` from the agent.
+   */
+  removeIdentity(key: PublicKey): Promise<void>
+  /**
+   * Lock the agent with `passphrase`, like `ssh-add -x`. A locked agent lists no keys and
+   * refuses to sign until unlocked. Fails if the agent is already locked.
+   */
+  lock(passphrase: string): Promise<void>
+  /**
+   * Unlock the agent, like `ssh-add -X`. Fails on a wrong passphrase, leaving it locked, and if
+   * the agent is not locked.
+   */
+  unlock(passphrase: string): Promise<void>
+  /** Remove every key from the agent, like `ssh-add -D`. */
+  removeAll(): Promise<void>
+}
+
+/**
+ * A session channel opened with `Client.openSession`.
+ * 
+ * `for await (const chunk of channel)` reads stdout as Buffers until the channel closes.
+ * Iterating only sees output not handed to an `onData` listener, and skips stderr.
+ *
+ * This type implements JavaScript's async iterable protocol.
+ * It can be used with `for await...of` loops.
+ * 
+ * @see https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Iteration_protocols#the_async_iterator_and_async_iterable_protocols
+ */
+export declare class Channel {
+  get id(): number
+  /**
+   * The SSH flow control of the channel, for telling whether a stalled transfer waits for the
+   * server to adjust the window or for this side to send.
+   */
+  flowStats(): FlowStats
+  /**
+   * Set an environment variable for the command started next. Servers drop the ones they do
+   * not accept, e.g. those missing from OpenSSH's `AcceptEnv`, without an error.
+   */
+  setEnv(name: string, value: string): Promise<void>
+  /** Start `command` on the channel. Only one command can run on a channel. */
+  exec(command: string): Promise<void>
+  /**
+   * Start the subsystem `name`, e.g. `netconf`, instead of a command. Its protocol is then
+   * spoken with `write` and `onData`. A `ChannelOptions.idleTimeoutMs` set here replaces the
+   * one the channel was opened with.
+   */
+  requestSubsystem(name: string, options?: ChannelOptions | undefined | null): Promise<void>
+  /** Write to the command's stdin. */
+  write(data: string | Buffer): Promise<void>
+  /** Signal that no more data will be written to stdin. */
+  eof(): Promise<void>
+  /**
+   * Deliver a signal to the command, named without the `SIG` prefix, e.g. `TERM`.
+   * Many servers, OpenSSH before 7.9 among them, ignore signals.
+   */
+  signal(name: string): Promise<void>
+  /**
+   * Tell the server the terminal was resized, e.g. from an xterm.js `resize` event.
+   * The pixel size defaults to `0`, meaning unknown. Only meaningful on a channel with a PTY.
+   */
+  windowChange(cols: number, rows: number, pixWidth?: number | undefined | null, pixHeight?: number | undefined | null): Promise<void>
+  close(): Promise<void>
+  /**
+   * Wire the channel to the stdin, stdout and stderr of this process, like the `ssh` CLI.
+   * While attached, a terminal stdin is in raw mode and resizes of a terminal stdout are sent
+   * to the server. Resolves with the exit status once the channel closes and stdin is
+   * restored. Takes the place of an `onData` listener.
+   */
+  attachStdio(): Promise<number | null>
+  /**
+   * Listen for output from the command. Output received before a listener is registered is
+   * buffered.
+   */
+  onData(callback: (data: Buffer, stream: 'stdout' | 'stderr') => void): void
+  /**
+   * Listen for the channel closing, with the exit status of the command if the server sent one,
+   * and the `IdleTimeout` error if it was closed by `idleTimeoutMs`.
+   */
+  onClose(callback: (exitStatus: number | null, error: Error | null) => void): void
+  [Symbol.asyncIterator](): AsyncGenerator<Buffer, void, undefined>
+}
+
+/**
+ * The stdout or stderr of a spawned command, read with `for await`, e.g. through
+ * `Readable.from`. Output is buffered until it is read, so read both streams or neither.
+ *
+ * This type implements JavaScript's async iterable protocol.
+ * It can be used with `for await...of` loops.
+ * 
+ * @see https://developer.mozilla.org/en-US/docs/Web/JavaScript/Reference/Iteration_protocols#the_async_iterator_and_async_iterable_protocols
+ */
+export declare class ChildOutput {
+  [Symbol.asyncIterator](): AsyncGenerator<Buffer, void, undefined>
+}
+
+/** A command started with `Client.spawn`, shaped like Node's `ChildProcess`. */
+export declare class ChildProcess {
+  get stdin(): ChildStdin
+  get stdout(): ChildOutput
+  get stderr(): ChildOutput
+  /** Resolves once the channel has closed. */
+  get exit(): Promise<SpawnExit>
+  /**
+   * Deliver a signal to the command, `TERM` by default. Many servers, OpenSSH before 7.9
+   * among them, ignore signals.
+   */
+  kill(signal?: string | undefined | null): Promise<void>
+}
+
+/** The stdin of a spawned command. */
+export declare class ChildStdin {
+  write(data: string | Buffer): Promise<void>
+  /** Write `data`, if any, then signal that no more data will be written. */
+  end(data?: string | Buffer | undefined | null): Promise<void>
+}
+
 export declare class Client {
+  /** The user from the `ssh://` URL passed to `connect`, if there was one. */
+  get defaultUser(): string | null
   isClosed(): boolean
+  /**
+   * Resolve once the connection has terminated, immediately if it already has.
+   * The promise does not keep the Node.js process alive.
+   */
+  waitClosed(): Promise<CloseInfo>
+  /**
+   * The banner the server sent before authentication, or `null` if the first authentication
+   * attempt completed without one.
+   */
+  authBanner(): Promise<string | null>
+  /** When a packet was last sent or received. */
+  lastActivity(): Date
+  /**
+   * Check that the server still answers, without opening a channel.
+   * Resolves to `false` instead of throwing when the connection is gone or the server is silent.
+   */
+  isAlive(options?: IsAliveOptions | undefined | null): Promise<boolean>
   /**
    * # Safety
    *
    * close can not be called concurrently.
+   *
+   * `password` may be a function, called only once the password is about to be sent, e.g. to
+   * fetch it from a credential store.
    */
-  authenticatePassword(user: string, password: string): Promise<boolean>
+  authenticatePassword(user: string, password: string | (() => string | Promise<string>)): Promise<boolean>
+  /**
+   * # Safety
+   *
+   * Attempt the `none` authentication method, which servers accept for accounts that need no
+   * credentials. The methods the server lists on failure are not exposed by russh, so they can
+   * not be returned.
+   */
+  authenticateNone(user: string): Promise<boolean>
   /**
    * # Safety
    *
    * Perform public key-based SSH authentication.
-   * The key can be omitted to use the default private key.
-   * The key can be a path to a private key file.
+   * The key can be omitted to try the default keys, as `authenticateDefaultKeys` does.
+   * The key can be a path to a private key file, which `Config.passphrase` is asked to
+   * decrypt if needed.
+   */
+  authenticateKeyPair(user: string, key: string | KeyPair | undefined, options?: KeyAuthOptions | undefined | null): Promise<boolean>
+  /**
+   * # Safety
+   *
+   * Perform public key-based SSH authentication with the keys `ssh` uses by default,
+   * `id_ed25519`, `id_ecdsa` and `id_rsa` in `~/.ssh`, skipping missing ones and, like `ssh`,
+   * ones that can not be loaded or decrypted. A key with an `-cert.pub` certificate next to it
+   * is offered with the certificate first.
+   * Resolves with the path of the accepted key, or `null` if none was.
+   */
+  authenticateDefaultKeys(user: string, options?: KeyAuthOptions | undefined | null): Promise<string | null>
+  /**
+   * # Safety
+   *
+   * Perform public key-based SSH authentication with the identities of the ssh-agent, in the
+   * order the agent lists them, until one is accepted. Resolves with the accepted key, or
+   * `null` if none was.
    */
-  authenticateKeyPair(user: string, key: string | KeyPair | undefined): Promise<boolean>
+  authenticateAgent(user: string, options?: AgentAuthOptions | undefined | null): Promise<PublicKey | null>
   /**
    * # Safety
    *
-   * exec can not be called concurrently.
-   * The caller in Node.js must ensure that.
+   * Try the credentials in `options` method by method, like `ssh` does, until the server
+   * lets the user in. Servers that need several methods, e.g. a key and then a one-time
+   * password, reject all but the last, so a rejected method does not stop the sequence.
+   * Resolves with the method that completed the authentication, or `null`.
    */
-  exec(command: string): Promise<ExecOutput>
+  authenticate(options: AuthenticateOptions): Promise<string | null>
+  /**
+   * # Safety
+   *
+   * Perform keyboard-interactive SSH authentication, answering the server's prompts from
+   * `options`.
+   */
+  authenticateKeyboardInteractive(user: string, options: KeyboardInteractiveOptions): Promise<boolean>
+  /**
+   * Run `command` on its own session channel and collect its output. Calls may overlap, every
+   * command runs on a separate channel of the one connection.
+   *
+   * `command` can be an argv array, in which case every element is quoted for the remote
+   * shell (POSIX `sh` unless `options.shell` says otherwise) and the results are joined.
+   */
+  exec(command: string | Array<string>, options?: ExecOptions | undefined | null): Promise<ExecOutput>
+  /**
+   * Run `script` by piping it into an interpreter's stdin, so nothing is written to the
+   * server's disk and no quoting of the script is needed. The script can not read stdin itself.
+   */
+  execScript(script: string | Buffer, options?: ExecScriptOptions | undefined | null): Promise<ExecOutput>
+  /**
+   * Open a session channel, for running a command while writing its stdin and reading its
+   * output.
+   */
+  openSession(options?: ChannelOptions | undefined | null): Promise<Channel>
+  /**
+   * Copy the local file or directory at `local_path` to `remote_path` by running `scp` on the
+   * server, for servers without the `sftp` subsystem.
+   */
+  scpUpload(localPath: string, remotePath: string, options?: ScpOptions | undefined | null): Promise<void>
+  /**
+   * Copy the remote file or directory at `remote_path` to `local_path` by running `scp` on the
+   * server. Into an existing local directory, the copy keeps its name.
+   */
+  scpDownload(remotePath: string, localPath: string, options?: ScpOptions | undefined | null): Promise<void>
+  /**
+   * Copy the local file at `local_path` to `remote_path` over SFTP, or over SCP when the server
+   * refuses the `sftp` subsystem, e.g. on embedded devices and git-only servers. Resolves with
+   * the transport that was used.
+   */
+  uploadFile(localPath: string, remotePath: string, options?: FileTransferOptions | undefined | null): Promise<FileTransferResult>
+  /**
+   * Copy the remote file at `remote_path` to `local_path` over SFTP, or over SCP when the server
+   * refuses the `sftp` subsystem. Resolves with the transport that was used.
+   */
+  downloadFile(remotePath: string, localPath: string, options?: FileTransferOptions | undefined | null): Promise<FileTransferResult>
+  /** Start the `sftp` subsystem on a new session channel, for transferring and managing files. */
+  sftp(options?: SftpOptions | undefined | null): Promise<Sftp>
+  /**
+   * Start `command` on a new session channel, like Node's `child_process.spawn`.
+   * Resolves once the server has accepted the command.
+   */
+  spawn(command: string, options?: SpawnOptions | undefined | null): Promise<ChildProcess>
+  /**
+   * Start the login shell on a new session channel with a pseudo-terminal, for interactive
+   * use. Write keystrokes with `Channel.write` and follow terminal resizes with
+   * `Channel.windowChange`.
+   */
+  shell(options?: PtyOptions | undefined | null): Promise<Channel>
+  /**
+   * Start `command` on a new session channel with a pseudo-terminal, for interactive programs
+   * such as `top` or `vim`. Use `Channel.attachStdio` to drive it from this process's terminal.
+   */
+  execPty(command: string, options?: PtyOptions | undefined | null): Promise<Channel>
+  /**
+   * Run several commands, each on its own session channel.
+   * The channel opens are pipelined rather than waiting for every confirmation in turn,
+   * and the results are returned in the same order as `commands`. A command that fails, e.g.
+   * because its channel could not be opened, is reported in its result rather than rejecting
+   * the whole batch.
+   */
+  execBatch(commands: Array<string>): Promise<Array<ExecBatchResult>>
+  /**
+   * Run `command`, handing its output to the callbacks in `options` as it arrives.
+   * Resolves with the exit status.
+   */
+  execStream(command: string, options: ExecStreamOptions): Promise<number>
+  /**
+   * Run `command` through `sudo` and `sh -c` on a PTY, answering its password prompt once.
+   * The prompt is removed from the returned output. Fails if sudo rejects the password.
+   */
+  execSudo(command: string, options: SudoOptions): Promise<ExecOutput>
   disconnect(reason: DisconnectReason, description: string, languageTag: string): Promise<void>
 }
 
+/**
+ * A remote file opened with `Sftp.open`. The server keeps it open until `close` or the end of
+ * the session.
+ */
+export declare class FileHandle {
+  /** Read `length` bytes starting at `offset`. Fewer come back only when the file ends first. */
+  read(offset: number | bigint, length: number): Promise<Buffer>
+  /** Write `data` starting at `offset`. Files opened for appending get it at their end instead. */
+  write(offset: number | bigint, data: string | Buffer): Promise<void>
+  stat(): Promise<FileAttributes>
+  /** Change the size, owner, permissions or times of the open file. */
+  setstat(attributes: SetStatOptions): Promise<void>
+  /** Have the server flush the file to disk, with the `fsync@openssh.com` extension. */
+  fsync(): Promise<void>
+  close(): Promise<void>
+}
+
 export declare class KeyPair {
   static generateEd25519(): KeyPair
   static generateRsa(bits: number, signatureHash: SignatureHash): KeyPair
   constructor(path: string, password?: string | undefined | null)
+  /**
+   * Decode a private key from its contents rather than a file, e.g. one kept in an
+   * environment variable or a secret store. Takes the same formats as the constructor.
+   */
+  static fromPem(content: string | Buffer, passphrase?: string | undefined | null): KeyPair
   clonePublicKey(): PublicKey
   name(): string
   /** Sign a slice using this algorithm. */
@@ -37,6 +338,13 @@ export declare class KeyPair {
 }
 
 export declare class PublicKey {
+  /**
+   * Parse a key in the OpenSSH format, `ssh-ed25519 AAAA... comment`.
+   * The key type and the comment are optional.
+   */
+  static parse(key: string): PublicKey
+  /** Format the key as `type base64`, the OpenSSH format without a comment. */
+  toOpenSsh(): string
   name(): string
   verifyDetached(data: Array<number>, signature: Array<number>): boolean
   /** Compute the key fingerprint, hashed with sha2-256. */
@@ -45,12 +353,323 @@ export declare class PublicKey {
   setAlgorithm(algorithm: SignatureHash): void
 }
 
+/** A session channel opened by a client. */
+export declare class ServerChannel {
+  get id(): number
+  /** The environment variables the client set on the channel. */
+  get env(): Record<string, string>
+  /** The terminal the client asked for, with its current size. `null` without a PTY. */
+  get terminal(): Terminal | null
+  /** Write to the client's stdout. */
+  write(data: string | Buffer): Promise<void>
+  /** Write to the client's stderr. */
+  writeStderr(data: string | Buffer): Promise<void>
+  /** Send the exit status of the command, then EOF, then close the channel. */
+  exit(status: number): Promise<void>
+  /**
+   * Report that the command was killed by `signal`, named without the `SIG` prefix, then send
+   * EOF and close the channel.
+   */
+  exitSignal(signal: string, coreDumped?: boolean | undefined | null, message?: string | undefined | null): Promise<void>
+  /** Signal that no more data will be sent. */
+  eof(): Promise<void>
+  close(): Promise<void>
+  /** Listen for data sent by the client. Data received before a listener is registered is buffered. */
+  onData(callback: ((arg: Buffer) => void)): void
+  /** Listen for the client signalling EOF or closing the channel. */
+  onEof(callback: (() => void)): void
+}
+
+/** The client connection a callback was invoked for. */
+export declare class ServerConnection {
+  get remoteAddress(): string
+  get remotePort(): number
+  /** The identification string sent by the client, e.g. `SSH-2.0-OpenSSH_9.6`. */
+  get clientVersion(): string | null
+  /** Disconnect this client. */
+  disconnect(reason: DisconnectReason, message: string): Promise<void>
+}
+
+/**
+ * An SFTP session, opened with `Client.sftp`. File operations are sent as they are called,
+ * so several may be in flight on the one channel.
+ * 
+ * When the server fails a request, the error's `code` is the name of its status, which its
+ * message also starts with, e.g. `NO_SUCH_FILE: No such file`: one of `EOF`, `NO_SUCH_FILE`,
+ * `PERMISSION_DENIED`, `FAILURE`, `BAD_MESSAGE`, `NO_CONNECTION`, `CONNECTION_LOST` or
+ * `OP_UNSUPPORTED`. A request the server does not answer in time fails with `TIMEOUT`.
+ */
+export declare class Sftp {
+  /** The protocol version the server answered with, `3` for OpenSSH. */
+  get version(): number
+  /**
+   * The extensions the server announced when the session started, with their versions, e.g.
+   * `{ "posix-rename@openssh.com": "1" }`.
+   */
+  extensions(): Record<string, string>
+  /**
+   * What the server accepts, as it announced when the session started. Reads and writes are
+   * kept within these limits.
+   */
+  limits(): SftpLimits
+  /** Read the whole remote file at `path` into memory. */
+  readFile(path: string): Promise<Buffer>
+  /** Replace the remote file at `path` with `data`, creating it if needed. */
+  writeFile(path: string, data: string | Buffer, options?: WriteFileOptions | undefined | null): Promise<void>
+  /** Copy the local file at `local_path` to `remote_path`, replacing any file there. */
+  upload(localPath: string, remotePath: string, options?: TransferOptions | undefined | null): Promise<void>
+  /**
+   * Copy the remote file at `remote_path` to `local_path`. The data is written to a temporary
+   * file next to `local_path` that replaces it once complete, so `local_path` never holds a
+   * partial download. With `resume`, that file is `local_path` with `.partial` appended, and it
+   * is kept when the download fails.
+   */
+  download(remotePath: string, localPath: string, options?: TransferOptions | undefined | null): Promise<void>
+  /**
+   * Run many uploads and downloads, `concurrency` of them at a time. Resolves once all of them
+   * ended, with their outcomes in the order of `jobs`; a failed job does not stop the others.
+   */
+  transfer(jobs: Array<TransferJob>, options?: TransferQueueOptions | undefined | null): Promise<Array<TransferResult>>
+  /**
+   * Make the remote directory `remote_dir` hold what the local directory `local_dir` does,
+   * creating the missing directories and uploading only the files that are missing or changed,
+   * with their permissions and times like `preserve` gives them. Resolves with the changes in
+   * the order they are made, parents before their contents.
+   */
+  sync(localDir: string, remoteDir: string, options?: SyncOptions | undefined | null): Promise<Array<SyncChange>>
+  /** List the remote directory at `path`, without its `.` and `..` entries. */
+  readdir(path: string): Promise<Array<DirEntry>>
+  /**
+   * Walk the remote directory tree at `path` with `for await`, yielding every entry below it,
+   * each directory before its contents. Directories are read a batch at a time as the loop
+   * asks for entries, and symlinks are not followed. Stopping the loop early closes the
+   * directories the walk has open.
+   */
+  walk(path: string): AsyncGenerator<WalkEntry, void, undefined>
+  /** The attributes of the file at `path`, following symlinks. */
+  stat(path: string): Promise<FileAttributes>
+  /** The attributes of the file at `path`, of the link itself if it is a symlink. */
+  lstat(path: string): Promise<FileAttributes>
+  /**
+   * Whether a file exists at `path`, following symlinks. Errors other than a missing file,
+   * such as a denied permission, reject.
+   */
+  exists(path: string): Promise<boolean>
+  /** Create the remote directory `path`. */
+  mkdir(path: string, options?: MkdirOptions | undefined | null): Promise<void>
+  /** Remove the remote directory `path`, which must be empty unless `recursive` is set. */
+  rmdir(path: string, options?: RmdirOptions | undefined | null): Promise<void>
+  /**
+   * Rename `from` to `to`. With the `posix-rename@openssh.com` extension an existing `to` is
+   * replaced atomically; without it, most servers refuse to overwrite.
+   */
+  rename(from: string, to: string): Promise<void>
+  /**
+   * Copy the remote file at `from` to `to`, replacing any file there, without the data leaving
+   * the server. Takes the `copy-data` extension.
+   */
+  copy(from: string, to: string): Promise<void>
+  /** Remove the remote file at `path`. */
+  unlink(path: string): Promise<void>
+  /**
+   * The paths matching the glob `pattern`, sorted, e.g. `logs/*.gz`. `*` and `?` match any
+   * characters and any one character of a name, `[...]` one of a set of characters and `**`
+   * any number of directories. Names starting with `.` only match a pattern that does too.
+   * Only the directories a wildcard applies to are listed.
+   */
+  glob(pattern: string, options?: GlobOptions | undefined | null): Promise<Array<string>>
+  /**
+   * Remove every path in `paths`, or every path matching the glob pattern `paths`, carrying
+   * on past failures. Resolves with the outcome for each path.
+   */
+  rm(paths: Array<string> | string, options?: RmOptions | undefined | null): Promise<Array<RmResult>>
+  /** Create a symlink at `link_path` pointing to `target`. */
+  symlink(target: string, linkPath: string): Promise<void>
+  /**
+   * Create a hard link at `new_path` to the file at `existing_path`. Takes the
+   * `hardlink@openssh.com` extension.
+   */
+  link(existingPath: string, newPath: string): Promise<void>
+  /** The target of the symlink at `path`. */
+  readlink(path: string): Promise<string>
+  /**
+   * The absolute, canonical form of `path`, resolved by the server. Relative paths start from
+   * the directory the session started in, usually the home directory, so `realpath(".")`
+   * finds it.
+   */
+  realpath(path: string): Promise<string>
+  /**
+   * Describe the filesystem holding `path`, e.g. to check for free space before an upload.
+   * Takes the `statvfs@openssh.com` extension.
+   */
+  statvfs(path: string): Promise<FilesystemStats>
+  /**
+   * The hash of the remote file at `path`, in hex, computed on the server to check a transfer
+   * without reading the file back. Servers announcing the `check-file` extension compute it
+   * themselves; on others, OpenSSH among them, it comes from running e.g. `sha256sum` over an
+   * exec channel.
+   */
+  checksum(path: string, options?: ChecksumOptions | undefined | null): Promise<string>
+  /**
+   * `path` with a leading `~` or `~user` replaced by the home directory, made absolute and
+   * canonical like `realpath` does. Without the `expand-path@openssh.com` extension, `~` is
+   * taken to be the directory the session started in, and `~user` fails.
+   */
+  expandPath(path: string): Promise<string>
+  /** Change the permission bits of the file at `path`. */
+  chmod(path: string, mode: number): Promise<void>
+  /** Change the owner and group of the file at `path`, by their numeric ids. */
+  chown(path: string, uid: number, gid: number): Promise<void>
+  /**
+   * Change the access and modification times of the file at `path`, in seconds since the
+   * Unix epoch.
+   */
+  utimes(path: string, atime: number, mtime: number): Promise<void>
+  /**
+   * Open the remote file at `path` for reading and writing at any offset. `flags` are those of
+   * Node's `fs.open`, `r` by default, and `mode` the permissions of a file the open creates.
+   */
+  open(path: string, flags?: string | undefined | null, mode?: number | undefined | null): Promise<FileHandle>
+  /**
+   * Open the remote file at `path` as a Node `Readable`, for `pipeline`. A chunk is requested
+   * each time the stream wants more, so a slow consumer holds the reads back. The file is
+   * closed once it has been read to the end, or when the stream is destroyed.
+   */
+  createReadStream(path: string, options?: ReadStreamOptions | undefined | null): Promise<import('node:stream').Readable>
+  /**
+   * Open the remote file at `path` as a Node `Writable` that writes it from the start, or from
+   * `start`, in order. The stream finishes once the server has stored every chunk and the file
+   * is closed.
+   */
+  createWriteStream(path: string, options?: WriteStreamOptions | undefined | null): Promise<import('node:stream').Writable>
+  /**
+   * Pass the lines of the remote file at `path` to `onLine`, for following a log where running
+   * `tail -f` is not allowed. The file is polled by its size: when it shrinks, it is taken to be
+   * truncated or replaced and read again from the start, and while it is missing, as during a
+   * rotation, the next one to appear is read from the start. Resolves once the lines are passed,
+   * or once `signal` aborts when following.
+   */
+  tail(path: string, options: TailOptions): Promise<void>
+  /** End the session and close its channel. Requests still in flight fail. */
+  close(): Promise<void>
+}
+
+/**
+ * A remote file opened by `Sftp.createReadStream`, which reads it with `read` until it returns
+ * `null`. The file is closed once it has been read to the end, or by `close`.
+ */
+export declare class SftpReadStream {
+  /** The next chunk of the file, or `null` once it has been read. */
+  read(): Promise<Buffer | null>
+  /** Close the file, unless it has been read to the end already. */
+  close(): Promise<void>
+}
+
+/**
+ * A walk over a remote directory tree, started with `Sftp.walk`, which takes its entries with
+ * `read` until it returns `null`. `close` closes the directories it has open.
+ */
+export declare class SftpWalk {
+  /** The next entry of the walk, or `null` once it is done. */
+  read(): Promise<WalkEntry | null>
+  /** Stop the walk, closing the directories it has open. */
+  close(): Promise<void>
+}
+
+/**
+ * A remote file opened by `Sftp.createWriteStream`. Each write is sent at once after the
+ * previous ones and resolves when the server has stored it, so awaiting writes bounds the data
+ * in flight.
+ */
+export declare class SftpWriteStream {
+  write(data: string | Buffer): Promise<void>
+  /** Write `data`, if any, then close the file once every write has finished. */
+  end(data?: string | Buffer | undefined | null): Promise<void>
+}
+
 export declare class Signature {
   toBase64(): string
 }
 
+/** An SSH server running on the Node.js tokio runtime, mostly useful as a test fixture. */
+export declare class SshServer {
+  /** Bind `host:port` and start accepting connections. */
+  static listen(options: ServerOptions): Promise<SshServer>
+  get host(): string
+  /** The bound port, useful when listening on port `0`. */
+  get port(): number
+  /** Stop accepting connections and disconnect every connected client. */
+  close(): void
+}
+
+export interface AddIdentityOptions {
+  /** Remove the key from the agent after this many seconds, like `ssh-add -t`. */
+  lifetime?: number
+  /** Ask for confirmation every time the key is used, like `ssh-add -c`. */
+  confirm?: boolean
+}
+
+export interface AgentAuthOptions {
+  /**
+   * Try at most this many identities. Servers disconnect after a few failed attempts,
+   * OpenSSH after its `MaxAuthTries` of 6.
+   */
+  maxAttempts?: number
+}
+
+/** The credentials `Client.authenticate` may use. */
+export interface AuthenticateOptions {
+  /** Defaults to the user of the `ssh://` URL passed to `connect`. */
+  user?: string
+  /**
+   * Which methods to try, in order, out of `publickey`, `keyboard-interactive` and
+   * `password`. A method without credentials is skipped. Defaults to all three in that order.
+   */
+  methods?: Array<string>
+  /** A private key file or a `KeyPair`, tried first for `publickey`. */
+  key?: string | KeyPair
+  /** Offer the identities of the ssh-agent for `publickey`, after `key`. */
+  agent?: boolean
+  keyboardInteractive?: KeyboardInteractiveOptions
+  /** A password, or a function returning it, as for `authenticatePassword`. */
+  password?: string | (() => string | Promise<string>)
+}
+
+/**
+ * Quote every element of `argv` for `shell`, `ShellDialect.Posix` by default, and join them
+ * into one command line, as `Client.exec` does with an argv array.
+ */
+export declare function buildCommand(argv: Array<string>, shell?: ShellDialect | undefined | null): string
+
+/** Options of the channel returned by `Client.openSession`, or set by `Channel.requestSubsystem`. */
+export interface ChannelOptions {
+  /**
+   * Close the channel when no data flows on it in either direction for this long, in
+   * milliseconds. `onClose` is then called with an `IdleTimeout` error, and pending and later
+   * requests as well as iterating the channel fail with it. The rest of the connection is
+   * unaffected.
+   */
+  idleTimeoutMs?: number
+}
+
 export declare function checkKnownHosts(host: string, port: number, pubkey: PublicKey, path?: string | undefined | null): boolean
 
+export interface ChecksumOptions {
+  /** `md5`, `sha1`, `sha224`, `sha256`, `sha384` or `sha512`, `sha256` by default. */
+  algorithm?: string
+}
+
+/** Whether this CPU accelerates the symmetric ciphers used by SSH. */
+export interface CipherAcceleration {
+  /** AES and carry-less multiplication instructions (AES-NI/PCLMULQDQ on x86, AES/PMULL on ARMv8) are available. */
+  aes: boolean
+  /** The cipher preference order offered by default on this CPU. */
+  ciphers: Array<string>
+}
+
+/** Report whether AES is hardware accelerated here and which cipher order `connect` offers by default. */
+export declare function cipherAcceleration(): CipherAcceleration
+
 /** The configuration of clients. */
 export interface ClientConfig {
   /** The client ID string sent at the beginning of the protocol. */
@@ -63,8 +682,29 @@ export interface ClientConfig {
   maximumPacketSize?: number
   /** Time after which the connection is garbage-collected. In milliseconds. */
   inactivityTimeout?: number
+  /**
+   * Send a `keepalive@openssh.com` request whenever nothing was received from the server for
+   * this long, to notice connections that died silently, e.g. behind a NAT. In milliseconds.
+   */
+  keepaliveInterval?: number
+  /**
+   * Close the connection once this many keepalives in a row went unanswered, reporting
+   * `Keepalive timeout` to `onClose`. `0` never gives up. Defaults to `3`.
+   */
+  keepaliveMax?: number
   /** Whether to expect and wait for an authentication call. */
   anonymous?: boolean
+  /**
+   * Symmetric ciphers to offer, most preferred first.
+   * Defaults to the order returned by `cipherAcceleration()`.
+   */
+  preferredCiphers?: Array<string>
+  /**
+   * `'legacy'` additionally offers what old devices need, after the modern algorithms:
+   * the SHA-1 Diffie-Hellman key exchanges, CBC ciphers and `ssh-rsa` host keys.
+   * RSA user keys still sign with their own `SignatureHash`. Defaults to `'modern'`.
+   */
+  compatibility?: 'modern' | 'legacy'
 }
 
 export interface ClientId {
@@ -80,14 +720,87 @@ export declare const enum ClientIdType {
   Raw = 1
 }
 
+/** Why a connection terminated. */
+export interface CloseInfo {
+  /** Whether `disconnect` was called on this side. */
+  local: boolean
+  /** The reason sent with the SSH disconnect message, if there was one. */
+  reason?: DisconnectReason
+  /** The disconnect message, or the error that ended the connection. */
+  message?: string
+}
+
 export interface Config {
   client?: ClientConfig
-  checkServerKey?: ((arg: PublicKey) => boolean | Promise<boolean> | unknown)
+  checkServerKey?: ((arg0: PublicKey, arg1?: SshfpStatus | undefined | null) => boolean | Promise<boolean> | unknown)
+  /**
+   * How long to wait for `checkServerKey` to settle before failing the handshake. In milliseconds.
+   * This only bounds the wait: russh runs the check inline, so the connection processes nothing
+   * else, keepalives and timers included, until the callback settles or this expires.
+   */
+  checkServerKeyTimeout?: number
+  /**
+   * Look the host key up in DNS. Without `checkServerKey`, only a `Matched` key is accepted;
+   * with it, the outcome is passed as its second argument and the callback decides.
+   */
+  sshfp?: SshfpOptions
+  /**
+   * Check the host key against keys kept by the application. A known key is accepted and a
+   * changed or revoked one rejected without asking `sshfp` or `checkServerKey`; those decide
+   * about keys the store does not know, which are then passed to `store`.
+   */
+  hostKeyStore?: HostKeyStore
   authBanner?: ((arg: string) => void)
+  /** Called once the connection has terminated. Does not keep the Node.js process alive. */
+  onClose?: ((arg: CloseInfo) => void)
+  /**
+   * Abort the connection attempt, rejecting with an `AbortError`. Has no effect once `connect`
+   * has resolved.
+   */
+  signal?: AbortSignal
+  /**
+   * The ssh-agent used by `authenticateAgent`: the path of its Unix socket or Windows named
+   * pipe, or `false` to use none. By default the agent at `SSH_AUTH_SOCK` is used if it can
+   * be reached, on Windows then the Windows OpenSSH agent or Pageant.
+   */
+  agent?: boolean | string
+  /**
+   * Asked for the passphrase when `authenticateKeyPair` loads an encrypted key file, again
+   * after a wrong one up to three attempts. Return `null` to give up. Does not keep the
+   * Node.js process alive.
+   */
+  passphrase?: (path: string, attempt: number) => string | null | Promise<string | null>
+  /**
+   * How long to wait for the server to answer a password, key or `none` attempt. In
+   * milliseconds. On expiry the attempt rejects and the connection is closed, as a late answer
+   * would otherwise be taken for the answer to the next attempt.
+   */
+  authTimeout?: number
+  /**
+   * How often `authenticatePassword` and `authenticateKeyPair` repeat an attempt the server
+   * rejected, for servers that turn users away while overloaded. Defaults to `0`. Every retry
+   * counts against the server's `MaxAuthTries`, 6 by default in OpenSSH, which disconnects
+   * once it is used up, and may get the address banned by tools like fail2ban, so keep it low.
+   */
+  authRetries?: number
+  /**
+   * How long to wait before the first retry, doubled before every further one. In
+   * milliseconds. Defaults to `1000`.
+   */
+  authRetryDelay?: number
 }
 
+/** Connect to `addr`, either `host:port` or an `ssh://[user@]host[:port]` URL. */
 export declare function connect(addr: string, config?: Config | undefined | null): Promise<Client>
 
+/** An entry of a remote directory. */
+export interface DirEntry {
+  name: string
+  /** The line `ls -l` would print for the entry, as formatted by the server. */
+  longname: string
+  attributes: FileAttributes
+}
+
 /** A reason for disconnection. */
 export declare const enum DisconnectReason {
   HostNotAllowedToConnect = 1,
@@ -107,9 +820,292 @@ export declare const enum DisconnectReason {
   IllegalUserName = 15
 }
 
+/**
+ * Quote `arg` for `shell`, `ShellDialect.Posix` by default, so it is passed through as one
+ * argument when interpolated into a command.
+ */
+export declare function escapeShellArg(arg: string, shell?: ShellDialect | undefined | null): string
+
+/** The outcome of one command of `Client.execBatch`. */
+export interface ExecBatchResult {
+  /** What the command produced, unset if it could not be run. */
+  output?: ExecOutput
+  /** Why the command could not be run, unset if it was. */
+  error?: string
+}
+
+export interface ExecOptions {
+  /** How an argv `command` is quoted. Defaults to `ShellDialect.Posix`. */
+  shell?: ShellDialect
+  /**
+   * Close the channel and fail with an `IdleTimeout` error when no data flows on it in
+   * either direction for this long. In milliseconds. The rest of the connection is unaffected.
+   */
+  idleTimeoutMs?: number
+  /** Written to the command's stdin, followed by EOF. Without it stdin is left open. */
+  stdin?: string | Buffer
+  /** Close the channel and reject with an `AbortError` when aborted. */
+  signal?: AbortSignal
+  /**
+   * Environment variables for the command. Servers drop the ones they do not accept, e.g. those
+   * missing from OpenSSH's `AcceptEnv`, without an error.
+   */
+  env?: Record<string, string>
+  /**
+   * Stop buffering once stdout and stderr together reach this many bytes, and set
+   * `ExecOutput.truncated`. The command keeps running unless `closeOnTruncate` is set.
+   */
+  maxOutputBytes?: number
+  /** Close the channel as soon as the output is truncated, instead of waiting for the command. */
+  closeOnTruncate?: boolean
+  /**
+   * Reject with an error whose `code` is `NonZeroExit` when the command exits with a non-zero
+   * status or is killed by a signal. The error has the `status`, `exitSignal`, `stdout` and
+   * `stderr` of the command, which its message also includes.
+   */
+  throwOnNonZero?: boolean
+}
+
 export interface ExecOutput {
   status: number
+  /** What the command wrote to stdout. */
   output: Buffer
+  /**
+   * What the command wrote to stderr. Always empty for `execSudo`, whose PTY merges stderr
+   * into `output`.
+   */
+  stderr: Buffer
+  /**
+   * The signal that killed the command, without the `SIG` prefix, e.g. `KILL`. `status` is
+   * `0` in that case, as the server sends no exit status.
+   */
+  exitSignal?: string
+  /** Whether the command dumped core when it was killed. */
+  coreDumped: boolean
+  /** The message the server sent along with `exitSignal`, if any. */
+  errorMessage?: string
+  /** Whether output was dropped because it exceeded `ExecOptions.maxOutputBytes`. */
+  truncated: boolean
+}
+
+export interface ExecScriptOptions {
+  /**
+   * The argv of a program that reads the script from stdin, e.g. `["python3", "-"]`.
+   * Defaults to `["sh", "-s", "--"]`.
+   */
+  interpreter?: Array<string>
+  /** Arguments passed to the script after the interpreter argv. */
+  args?: Array<string>
+  /** How the interpreter argv is quoted. Defaults to `ShellDialect.Posix`. */
+  shell?: ShellDialect
+  /** See `ExecOptions.idleTimeoutMs`. */
+  idleTimeoutMs?: number
+  /** Close the channel and reject with an `AbortError` when aborted. */
+  signal?: AbortSignal
+  /** Environment variables for the interpreter, see `ExecOptions.env`. */
+  env?: Record<string, string>
+  /** See `ExecOptions.throwOnNonZero`. */
+  throwOnNonZero?: boolean
+}
+
+export interface ExecStreamOptions {
+  /** Called with every chunk of output as it arrives. */
+  onData?: (data: Buffer, stream: 'stdout' | 'stderr') => void
+  /**
+   * Called with every line of output, without its LF or CRLF terminator.
+   * Invalid UTF-8 is replaced with U+FFFD, and a trailing partial line is flushed at close.
+   */
+  onLine?: (line: string, stream: 'stdout' | 'stderr') => void
+  /**
+   * Longer runs of output without a newline are split into lines of this many bytes.
+   * Defaults to `65536`.
+   */
+  maxLineLength?: number
+  /** As `ExecOptions.idleTimeoutMs`. */
+  idleTimeoutMs?: number
+}
+
+/** What the server reported about a file. SFTP servers may leave any attribute out. */
+export interface FileAttributes {
+  fileType: FileType
+  size?: bigint
+  uid?: number
+  gid?: number
+  /** The permission bits, e.g. `0o644`. */
+  permissions?: number
+  /** The last access, in seconds since the Unix epoch. */
+  atime?: number
+  /** The last modification, in seconds since the Unix epoch. */
+  mtime?: number
+}
+
+/**
+ * The filesystem holding a remote path, as `statvfs` describes it. Block counts are in units of
+ * `fragmentSize`, so the bytes free for unprivileged users are `blocksAvailable * fragmentSize`.
+ */
+export interface FilesystemStats {
+  blockSize: number
+  fragmentSize: number
+  blocks: number
+  blocksFree: number
+  /** The free blocks unprivileged users may use. */
+  blocksAvailable: number
+  files: number
+  filesFree: number
+  /** The free inodes unprivileged users may use. */
+  filesAvailable: number
+  /** The longest file name the filesystem allows. */
+  nameMax: number
+}
+
+/**
+ * Options of `Client.uploadFile` and `Client.downloadFile`, which apply the same whichever
+ * transport is used.
+ */
+export interface FileTransferOptions {
+  /** Called after every chunk with the bytes transferred so far and the size of the file. */
+  onProgress?: (transferred: bigint, total: bigint) => void
+  /**
+   * Give the copy the permissions and the access and modification times of the original, like
+   * `scp -p`.
+   */
+  preserve?: boolean
+  /** Keep the transfer below this rate, averaged over about a chunk. */
+  maxBytesPerSecond?: number
+  /** See `TransferOptions.parallelism`. Over SCP, the file always goes in a single stream. */
+  parallelism?: number
+}
+
+/** What `Client.uploadFile` and `Client.downloadFile` resolve with. */
+export interface FileTransferResult {
+  transport: FileTransport
+}
+
+/** How `Client.uploadFile` or `Client.downloadFile` moved the file. */
+export declare const enum FileTransport {
+  Sftp = 0,
+  Scp = 1
+}
+
+export declare const enum FileType {
+  File = 0,
+  Directory = 1,
+  Symlink = 2,
+  /** A device, FIFO or socket, or a file the server reported no type for. */
+  Other = 3
+}
+
+/**
+ * The SSH flow control of a channel, as returned by `Channel.flowStats`. It is recorded from the
+ * messages the server sends, so reading it sends nothing.
+ */
+export interface FlowStats {
+  /**
+   * The outbound window left after the last WindowAdjust from the server was applied, or the
+   * window the server granted when the channel was opened. Data sent since is not subtracted:
+   * russh keeps the running window private.
+   */
+  outboundWindow: number
+  /**
+   * Whether data was still queued waiting for window after the last WindowAdjust was applied.
+   * The size of that queue is private to russh, so it can not be reported in bytes.
+   */
+  waitingForWindow: boolean
+  /** The WindowAdjust messages received from the server. */
+  windowAdjusts: number
+  /** The largest packet the server accepts on the channel, as it announced when opening it. */
+  maxPacketSize: number
+}
+
+export interface GlobOptions {
+  /**
+   * The remote directory relative patterns are matched in, and the paths found are relative
+   * to. By default the directory the session started in.
+   */
+  cwd?: string
+}
+
+/** How a host key compares to the keys stored for the host. */
+export declare const enum HostKeyStatus {
+  /** The key is stored for the host. */
+  Known = 0,
+  /** No key is stored for the host. */
+  Unknown = 1,
+  /** Other keys are stored for the host. */
+  Changed = 2,
+  /** The key is marked revoked. */
+  Revoked = 3
+}
+
+/**
+ * Host keys kept somewhere other than a `known_hosts` file, e.g. a central database.
+ * The store only looks keys up and saves them; deciding whether a key is known, changed or
+ * revoked is done by `connect`.
+ */
+export interface HostKeyStore {
+  /**
+   * The keys recorded for `host` and `port`. Entries marked `revoked` are rejected even if no
+   * other key is recorded.
+   */
+  lookup: (host: string, port: number) => Array<PublicKey | { key: PublicKey; revoked?: boolean }> | Promise<Array<PublicKey | { key: PublicKey; revoked?: boolean }>>
+  /** Record a key that was accepted for a host without a stored key. */
+  store?: (host: string, port: number, key: PublicKey) => void | Promise<void>
+  /**
+   * Accept and store the key of a host the store has no key for. Otherwise such a key needs
+   * `checkServerKey` or a matching SSHFP record to be accepted.
+   */
+  trustOnFirstUse?: boolean
+}
+
+export interface IsAliveOptions {
+  /** How long to wait for the server to answer. In milliseconds. Defaults to `5000`. */
+  timeoutMs?: number
+}
+
+export interface KeyAuthOptions {
+  /**
+   * The signature algorithm of an RSA key: `SHA2_512` (`rsa-sha2-512`), `SHA2_256`
+   * (`rsa-sha2-256`) or `SHA1` (`ssh-rsa`) for devices that reject SHA-2 signatures.
+   * Defaults to the algorithm the key was generated or loaded with, `SHA2_512` for key files.
+   */
+  hashAlg?: SignatureHash
+}
+
+export interface KeyboardInteractiveAnswer {
+  /**
+   * A string matches prompts containing it. A `RegExp` is evaluated with Rust's `regex`
+   * syntax, honoring the `i`, `m` and `s` flags.
+   */
+  promptMatch: string | RegExp
+  /** The answer, or a function computing it from the prompt, e.g. a TOTP code. */
+  response: string | ((arg: string) => string | Promise<string> | unknown)
+}
+
+/** A round of keyboard-interactive prompts. */
+export interface KeyboardInteractiveChallenge {
+  name?: string
+  instructions?: string
+  prompts: Array<KeyboardInteractivePrompt>
+}
+
+export interface KeyboardInteractiveOptions {
+  /** Rules answering prompts by their text. The first rule that matches a prompt answers it. */
+  answers?: Array<KeyboardInteractiveAnswer>
+  /** Answer the prompts no rule matches, in the order they are given. */
+  onPrompts?: ((arg: KeyboardInteractiveChallenge) => Array<string> | Promise<Array<string>> | unknown)
+  /**
+   * Fail when a prompt has no answer, instead of answering it with an empty string.
+   * Only applies without `onPrompts`. Defaults to `true`.
+   */
+  failOnUnmatchedPrompt?: boolean
+  /** The submethods hint sent to the server, e.g. `pam`. */
+  submethods?: string
+}
+
+export interface KeyboardInteractivePrompt {
+  prompt: string
+  /** Whether the client should echo the answer as it is typed. */
+  echo?: boolean
 }
 
 export declare function learnKnownHosts(host: string, port: number, pubkey: PublicKey, path?: string | undefined | null): void
@@ -127,6 +1123,173 @@ export interface Limits {
   rekeyTimeLimit?: number
 }
 
+export interface MkdirOptions {
+  /** The permissions of the created directories. By default the server picks them. */
+  mode?: number
+  /**
+   * Create the missing parent directories too, and accept an existing directory, like
+   * `mkdir -p`.
+   */
+  recursive?: boolean
+}
+
+/** The pseudo-terminal requested by `Client.shell` and `Client.execPty`. */
+export interface PtyOptions {
+  /** The `TERM` of the remote shell. Defaults to `xterm-256color`. */
+  term?: string
+  /** Defaults to `80`. */
+  cols?: number
+  /** Defaults to `24`. */
+  rows?: number
+  /** The terminal width in pixels. Defaults to `0`, meaning unknown. */
+  pixWidth?: number
+  /** The terminal height in pixels. Defaults to `0`, meaning unknown. */
+  pixHeight?: number
+  /** Terminal modes by their RFC 4254 names, e.g. `{ ECHO: 0, VINTR: 3 }`. */
+  modes?: Record<string, number>
+  /** See `ChannelOptions.idleTimeoutMs`. */
+  idleTimeoutMs?: number
+}
+
+export interface ReadStreamOptions {
+  /** The offset to start reading at, `0` by default. */
+  start?: number | bigint
+  /**
+   * The offset of the last byte to read, inclusive like in Node's `fs.createReadStream`. By
+   * default the stream reads to the end of the file.
+   */
+  end?: number | bigint
+}
+
+export interface RmdirOptions {
+  /**
+   * Remove the contents of the directory first, like `rm -r`. Symlinks are removed, not
+   * followed.
+   */
+  recursive?: boolean
+}
+
+export interface RmOptions {
+  /** Remove directories with their contents. Without it, directories are reported as errors. */
+  recursive?: boolean
+}
+
+/** The outcome of removing one path with `Sftp.rm`. */
+export interface RmResult {
+  path: string
+  /** Why the path could not be removed, unset if it was. */
+  error?: string
+}
+
+export interface ScpOptions {
+  /**
+   * Give the copy the permissions and the access and modification times of the original, like
+   * `scp -p`.
+   */
+  preserve?: boolean
+  /** Copy directories with their contents, like `scp -r`. Without it, copying a directory fails. */
+  recursive?: boolean
+  /** Called after every chunk with the bytes of the current file copied so far and its size. */
+  onProgress?: (transferred: bigint, total: bigint) => void
+  /** Keep the copy below this rate, averaged over about a chunk. */
+  maxBytesPerSecond?: number
+}
+
+export interface ServerOptions {
+  /** The address to bind. Defaults to `127.0.0.1`. */
+  host?: string
+  /** The port to bind. Defaults to `0`, which picks a free port. */
+  port?: number
+  /**
+   * The host keys offered to clients, e.g. an ed25519 and an RSA key.
+   * The client picks the first key type it prefers among them.
+   */
+  hostKeys: Array<KeyPair>
+  /** A message shown to clients before they authenticate. */
+  banner?: string
+  /** Accept the `none` authentication method, letting any client in without credentials. */
+  noneAllowed?: boolean
+  /** How many rejected authentication attempts a client gets before it is disconnected. Defaults to `6`. */
+  maxAuthAttempts?: number
+  /** Check a password. The `password` method is only offered when this is set. */
+  onAuthPassword?: ((arg0: string, arg1: string, arg2: ServerConnection) => boolean | Promise<boolean> | unknown)
+  /**
+   * Check a public key. Called both when the client asks whether a key would be accepted and
+   * when it signs with it. The `publickey` method is only offered when this is set.
+   */
+  onAuthPublicKey?: ((arg0: string, arg1: PublicKey, arg2: ServerConnection) => boolean | Promise<boolean> | unknown)
+  /**
+   * Run a keyboard-interactive round. `responses` is `null` on the first round and holds the
+   * answers to the previous challenge afterwards. Return a challenge to ask more questions,
+   * or a boolean to accept or reject the client.
+   * The `keyboard-interactive` method is only offered when this is set.
+   */
+  onAuthKeyboardInteractive?: ((arg0: string, arg1: string, arg2: Array<string> | null, arg3: ServerConnection) => boolean | Promise<boolean | KeyboardInteractiveChallenge> | KeyboardInteractiveChallenge | unknown)
+  /** Called when a client runs a command on a session channel. */
+  onExec?: ((arg0: ServerChannel, arg1: string, arg2: ServerConnection) => void)
+  /** Called when a client starts a shell on a session channel. */
+  onShell?: ((arg0: ServerChannel, arg1: ServerConnection) => void)
+  /** Called when a client starts a subsystem on a session channel, with its name. */
+  onSubsystem?: ((arg0: ServerChannel, arg1: string, arg2: ServerConnection) => void)
+}
+
+/** The attributes `FileHandle.setstat` changes. Unset ones are left as they are. */
+export interface SetStatOptions {
+  /** Truncate or extend the file to this many bytes. */
+  size?: number | bigint
+  /** Set together with `gid`. */
+  uid?: number
+  gid?: number
+  /** The permission bits, e.g. `0o644`. */
+  mode?: number
+  /** Set together with `mtime`, in seconds since the Unix epoch. */
+  atime?: number
+  mtime?: number
+}
+
+/**
+ * The limits a server announced with `limits@openssh.com`. Each is unset when the server did
+ * not announce the extension or put no limit on it.
+ */
+export interface SftpLimits {
+  /** The longest packet the server accepts, in bytes. */
+  maxPacketLength?: number
+  /** The most bytes one read returns. */
+  maxReadLength?: number
+  /** The most bytes one write accepts. */
+  maxWriteLength?: number
+  /** How many files may be open at once. */
+  maxOpenHandles?: number
+}
+
+export interface SftpOptions {
+  /**
+   * How long to wait for the server to answer a request before failing it with `TIMEOUT`. In
+   * milliseconds, rounded up to whole seconds. Defaults to `10000`.
+   */
+  timeoutMs?: number
+  /**
+   * Send a request whenever nothing was received from the server for this long, for servers
+   * and middleboxes that drop idle sessions. In milliseconds.
+   */
+  keepaliveIntervalMs?: number
+  /**
+   * Start the subsystem again on a new channel when the server closes this one. Requests in
+   * flight and open files fail, and later requests go to the new channel.
+   */
+  reopen?: boolean
+}
+
+/** The shell that runs commands on the server, which decides how arguments are quoted. */
+export declare const enum ShellDialect {
+  /** `sh` and compatible shells, as on most Unix servers. */
+  Posix = 0,
+  /** `cmd.exe`, the default shell of Windows OpenSSH. */
+  Cmd = 1,
+  /** PowerShell, when configured as the Windows OpenSSH default shell. */
+  PowerShell = 2
+}
+
 /** The hash function used for signing with RSA keys. */
 export declare const enum SignatureHash {
   /** SHA2, 256 bits. */
@@ -136,3 +1299,250 @@ export declare const enum SignatureHash {
   /** SHA1 */
   SHA1 = 2
 }
+
+/** How a spawned command ended, like the arguments of Node's `exit` event. */
+export interface SpawnExit {
+  /** The exit status, `null` if the server sent none, e.g. because the command was killed. */
+  code?: number
+  /** The signal that killed the command, without the `SIG` prefix. */
+  signal?: string
+}
+
+/** Options of `Client.spawn`. */
+export interface SpawnOptions {
+  /**
+   * Environment variables sent before the command starts. Servers drop the ones they do not
+   * accept, see `Channel.setEnv`.
+   */
+  env?: Record<string, string>
+}
+
+/**
+ * Verify host keys against SSHFP records published in DNS (RFC 4255).
+ * 
+ * The DNSSEC status is taken from the AD flag of the resolver's answer, so the resolver and
+ * the path to it have to be trusted, as with OpenSSH's `VerifyHostKeyDNS`.
+ */
+export interface SshfpOptions {
+  /** The name to look up. Defaults to the host part of the address passed to `connect`. */
+  hostname?: string
+  /**
+   * The resolver to ask, as `ip` or `ip:port`. Defaults to the first `nameserver` in
+   * `/etc/resolv.conf`.
+   */
+  nameserver?: string
+  /** Trust a matching record even if the answer was not DNSSEC-authenticated. */
+  allowInsecureDns?: boolean
+}
+
+/** The outcome of checking a host key against its SSHFP records. */
+export declare const enum SshfpStatus {
+  /** A record matches the host key, in an answer that can be trusted. */
+  Matched = 0,
+  /** The host has SSHFP records, but none of them match the host key. */
+  Unmatched = 1,
+  /** The host has no SSHFP records. */
+  NoRecords = 2,
+  /** A record matches the host key, but the answer was not DNSSEC-authenticated. */
+  Unverified = 3,
+  /** The records could not be looked up. */
+  LookupFailed = 4
+}
+
+export interface SudoOptions {
+  password: string
+  /**
+   * The prompt sudo is told to print, which is watched for in the output. A `%` in it is
+   * printed as is rather than expanded by sudo. Defaults to a unique marker.
+   */
+  prompt?: string
+}
+
+/** What `Sftp.sync` does to a remote path. */
+export declare const enum SyncAction {
+  /** Create the directory. */
+  Mkdir = 0,
+  /** Upload the local file, missing or changed on the server. */
+  Upload = 1,
+  /** Remove the path, with its contents if it is a directory. */
+  Delete = 2
+}
+
+/** A change `Sftp.sync` makes to the remote directory. */
+export interface SyncChange {
+  action: SyncAction
+  /**
+   * The path relative to the synchronized directories, with `/` between components, empty for
+   * the remote directory itself.
+   */
+  path: string
+}
+
+export interface SyncOptions {
+  /**
+   * Remove the remote files and directories the local directory does not have, like
+   * `rsync --delete`, and replace a remote directory by a local file of the same name or the
+   * other way around. Without it, such a mismatch fails the sync.
+   */
+  delete?: boolean
+  /**
+   * How to tell a file changed. `'mtime-size'`, the default, compares the sizes and
+   * modification times; `'checksum'` compares the sizes and the sha256 hashes, see
+   * `Sftp.checksum`.
+   */
+  compare?: 'mtime-size' | 'checksum'
+  /** Only work out the changes, without making them. */
+  dryRun?: boolean
+}
+
+export interface TailOptions {
+  /** Called with each line, without its line ending. */
+  onLine: (line: string) => void
+  /** How often to check the file for appended data, in milliseconds, 1000 by default. */
+  pollIntervalMs?: number
+  /**
+   * Start at the end of the file and keep passing the lines appended to it, like `tail -f`,
+   * until `signal` aborts. Without it, the lines already in the file are passed.
+   */
+  follow?: boolean
+  /** Stop following the file, resolving `Sftp.tail`. */
+  signal?: AbortSignal
+}
+
+/** A pseudo-terminal requested by the client. */
+export interface Terminal {
+  term: string
+  cols: number
+  rows: number
+}
+
+export declare const enum TransferDirection {
+  Upload = 0,
+  Download = 1
+}
+
+/** A job of `Sftp.transfer` starting or ending. */
+export interface TransferEvent {
+  /** The position of the job among those passed to `Sftp.transfer`. */
+  index: number
+  state: TransferState
+  /** Why the job failed, for `Failed`. */
+  error?: string
+}
+
+/** One upload or download of `Sftp.transfer`. */
+export interface TransferJob {
+  direction: TransferDirection
+  localPath: string
+  remotePath: string
+  /** As for `Sftp.upload` and `Sftp.download`, with `onProgress` called for this job alone. */
+  options?: TransferOptions
+}
+
+export interface TransferOptions {
+  /** Called after every chunk with the bytes transferred so far and the size of the file. */
+  onProgress?: (transferred: bigint, total: bigint) => void
+  /**
+   * Continue an interrupted transfer instead of starting over. Uploads keep the part of the
+   * remote file already there, downloads the `.partial` file a failed download left next to
+   * the local path. Only the length of that part is checked, not its content.
+   */
+  resume?: boolean
+  /**
+   * How many bytes one read or write request carries, 32768 by default. It is lowered to what
+   * the server accepts when it announces limits, see `Sftp.limits`.
+   */
+  chunkSize?: number
+  /**
+   * How many requests may wait for the server's answer at once, 64 by default. More keep a
+   * connection with a long round trip busy.
+   */
+  maxInflight?: number
+  /** Keep the transfer below this rate, averaged over about a chunk. */
+  maxBytesPerSecond?: number
+  /**
+   * Upload to a temporary file next to the remote path, named after it with `.partial-` and a
+   * random suffix, then rename it into place, so the remote path never holds a partial upload.
+   * Replacing an existing file this way takes the `posix-rename@openssh.com` extension.
+   */
+  atomic?: boolean
+  /**
+   * Give the copy the permissions and the access and modification times of the original, like
+   * `scp -p`. Windows has no permissions to take or give.
+   */
+  preserve?: boolean
+  /**
+   * Flush the copy to disk before the transfer resolves. Uploads take the `fsync@openssh.com`
+   * extension.
+   */
+  fsync?: boolean
+  /**
+   * Split the file into up to this many ranges, each transferred over an SFTP session on a
+   * channel of its own, for fast links where the flow control of one channel is the bottleneck.
+   * A range gets at least `maxInflight` chunks, as a shorter one could not keep its channel
+   * busy, and the ranges only go to as many channels as the server opens, e.g. within
+   * OpenSSH's `MaxSessions`; with one range left, the file goes in a single stream. The size of
+   * the copy is checked once all ranges are done. Ignored with `resume`. Defaults to `1`.
+   */
+  parallelism?: number
+  /**
+   * Compare the sha256 hash of the copy with that of the original before the transfer
+   * resolves, see `Sftp.checksum`.
+   */
+  verifyChecksum?: boolean
+}
+
+export interface TransferQueueOptions {
+  /** How many jobs run at once, 4 by default. */
+  concurrency?: number
+  /**
+   * Called as any job moves a chunk, with the bytes all jobs transferred so far and the size of
+   * all their files.
+   */
+  onProgress?: (transferred: bigint, total: bigint) => void
+  /** Called as each job starts and ends. */
+  onJob?: (event: TransferEvent) => void
+}
+
+/** The outcome of one job of `Sftp.transfer`. */
+export interface TransferResult {
+  localPath: string
+  remotePath: string
+  /** Why the job failed, unset if it succeeded. */
+  error?: string
+}
+
+export declare const enum TransferState {
+  Started = 0,
+  Finished = 1,
+  Failed = 2
+}
+
+/** An entry found by `Sftp.walk`. */
+export interface WalkEntry {
+  /**
+   * The path of the entry, the walked path joined with the names of the directories down to
+   * it.
+   */
+  path: string
+  attributes: FileAttributes
+}
+
+export interface WriteFileOptions {
+  /** The permissions of the file if the write creates it. By default the server picks them. */
+  mode?: number
+  /**
+   * Have the server flush the file to disk before the write resolves. Takes the
+   * `fsync@openssh.com` extension.
+   */
+  fsync?: boolean
+}
+
+export interface WriteStreamOptions {
+  /** How to open the file, as in `Sftp.open`, `w` by default. */
+  flags?: string
+  /** The permissions of the file if the open creates it. */
+  mode?: number
+  /** The offset to start writing at, `0` by default. */
+  start?: number | bigint
+}
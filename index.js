@@ -1,376 +1,96 @@
-// prettier-ignore
-/* eslint-disable */
-// @ts-nocheck
-/* auto-generated by NAPI-RS */
-
-const { createRequire } = require('node:module')
-require = createRequire(__filename)
-
-const { readFileSync } = require('node:fs')
-let nativeBinding = null
-const loadErrors = []
-
-const isMusl = () => {
-  let musl = false
-  if (process.platform === 'linux') {
-    musl = isMuslFromFilesystem()
-    if (musl === null) {
-      musl = isMuslFromReport()
+// The generated binding in `binding.js`, with the iterators and Node streams napi-rs can not
+// express built around the primitives it exports: an async iterator of the binding can only
+// reject with a napi status as the code, while the `read` methods reject with the SFTP status.
+const { Readable, Writable } = require('node:stream')
+
+const { Sftp } = require('./binding.js')
+
+// Yield what `source.read()` resolves with until it resolves with `null`, closing `source` when
+// the loop stops early.
+async function* readAll(source) {
+  let item
+  let stopped = false
+  try {
+    while ((item = await source.read()) !== null) {
+      stopped = true
+      yield item
+      stopped = false
     }
-    if (musl === null) {
-      musl = isMuslFromChildProcess()
+  } finally {
+    if (stopped) {
+      await source.close()
     }
   }
-  return musl
 }
 
-const isFileMusl = (f) => f.includes('libc.musl-') || f.includes('ld-musl-')
-
-const isMuslFromFilesystem = () => {
-  try {
-    return readFileSync('/usr/bin/ldd', 'utf-8').includes('musl')
-  } catch {
-    return null
+// A remote file read with `Sftp.createReadStream`, a chunk each time the stream wants more.
+class SftpReadable extends Readable {
+  constructor(file) {
+    super()
+    this._file = file
   }
-}
 
-const isMuslFromReport = () => {
-  const report = typeof process.report.getReport === 'function' ? process.report.getReport() : null
-  if (!report) {
-    return null
-  }
-  if (report.header && report.header.glibcVersionRuntime) {
-    return false
+  _read() {
+    this._file.read().then(
+      (chunk) => this.push(chunk),
+      (err) => this.destroy(err),
+    )
   }
-  if (Array.isArray(report.sharedObjects)) {
-    if (report.sharedObjects.some(isFileMusl)) {
-      return true
-    }
-  }
-  return false
-}
 
-const isMuslFromChildProcess = () => {
-  try {
-    return require('child_process').execSync('ldd --version', { encoding: 'utf8' }).includes('musl')
-  } catch (e) {
-    // If we reach this case, we don't know if the system is musl or not, so is better to just fallback to false
-    return false
+  _destroy(err, callback) {
+    this._file.close().then(
+      () => callback(err),
+      (closeErr) => callback(err || closeErr),
+    )
   }
 }
 
-function requireNative() {
-  if (process.platform === 'android') {
-    if (process.arch === 'arm64') {
-      try {
-        return require('./ssh.android-arm64.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-android-arm64')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else if (process.arch === 'arm') {
-      try {
-        return require('./ssh.android-arm-eabi.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-android-arm-eabi')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else {
-      loadErrors.push(new Error(`Unsupported architecture on Android ${process.arch}`))
-    }
-  } else if (process.platform === 'win32') {
-    if (process.arch === 'x64') {
-      try {
-        return require('./ssh.win32-x64-msvc.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-win32-x64-msvc')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else if (process.arch === 'ia32') {
-      try {
-        return require('./ssh.win32-ia32-msvc.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-win32-ia32-msvc')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else if (process.arch === 'arm64') {
-      try {
-        return require('./ssh.win32-arm64-msvc.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-win32-arm64-msvc')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else {
-      loadErrors.push(new Error(`Unsupported architecture on Windows: ${process.arch}`))
-    }
-  } else if (process.platform === 'darwin') {
-    try {
-        return require('./ssh.darwin-universal.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-darwin-universal')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    if (process.arch === 'x64') {
-      try {
-        return require('./ssh.darwin-x64.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-darwin-x64')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else if (process.arch === 'arm64') {
-      try {
-        return require('./ssh.darwin-arm64.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-darwin-arm64')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else {
-      loadErrors.push(new Error(`Unsupported architecture on macOS: ${process.arch}`))
-    }
-  } else if (process.platform === 'freebsd') {
-    if (process.arch === 'x64') {
-      try {
-        return require('./ssh.freebsd-x64.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-freebsd-x64')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else if (process.arch === 'arm64') {
-      try {
-        return require('./ssh.freebsd-arm64.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-freebsd-arm64')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-    } else {
-      loadErrors.push(new Error(`Unsupported architecture on FreeBSD: ${process.arch}`))
-    }
-  } else if (process.platform === 'linux') {
-    if (process.arch === 'x64') {
-      if (isMusl()) {
-        try {
-        return require('./ssh.linux-x64-musl.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-x64-musl')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-      } else {
-        try {
-        return require('./ssh.linux-x64-gnu.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-x64-gnu')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-      }
-    } else if (process.arch === 'arm64') {
-      if (isMusl()) {
-        try {
-        return require('./ssh.linux-arm64-musl.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-arm64-musl')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-      } else {
-        try {
-        return require('./ssh.linux-arm64-gnu.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-arm64-gnu')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-      }
-    } else if (process.arch === 'arm') {
-      if (isMusl()) {
-        try {
-        return require('./ssh.linux-arm-musleabihf.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-arm-musleabihf')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-      } else {
-        try {
-        return require('./ssh.linux-arm-gnueabihf.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-arm-gnueabihf')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-
-      }
-    } else if (process.arch === 'riscv64') {
-      if (isMusl()) {
-        try {
-        return require('./ssh.linux-riscv64-musl.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-riscv64-musl')
-      } catch (e) {
-        loadErrors.push(e)
-      }
+// A remote file written with `Sftp.createWriteStream`. Chunks that queue up while a write is in
+// flight are sent together, without waiting for the server to store each one.
+class SftpWritable extends Writable {
+  constructor(file) {
+    super()
+    this._file = file
+  }
 
-      } else {
-        try {
-        return require('./ssh.linux-riscv64-gnu.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-riscv64-gnu')
-      } catch (e) {
-        loadErrors.push(e)
-      }
+  _write(chunk, encoding, callback) {
+    this._file.write(chunk).then(() => callback(), callback)
+  }
 
-      }
-    } else if (process.arch === 'ppc64') {
-      try {
-        return require('./ssh.linux-ppc64-gnu.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-ppc64-gnu')
-      } catch (e) {
-        loadErrors.push(e)
-      }
+  _writev(chunks, callback) {
+    Promise.all(chunks.map(({ chunk }) => this._file.write(chunk))).then(() => callback(), callback)
+  }
 
-    } else if (process.arch === 's390x') {
-      try {
-        return require('./ssh.linux-s390x-gnu.node')
-      } catch (e) {
-        loadErrors.push(e)
-      }
-      try {
-        return require('@napi-rs/ssh-linux-s390x-gnu')
-      } catch (e) {
-        loadErrors.push(e)
-      }
+  _final(callback) {
+    this._file.end().then(() => callback(), callback)
+  }
 
-    } else {
-      loadErrors.push(new Error(`Unsupported architecture on Linux: ${process.arch}`))
+  _destroy(err, callback) {
+    if (this.writableFinished) {
+      callback(err)
+      return
     }
-  } else {
-    loadErrors.push(new Error(`Unsupported OS: ${process.platform}, architecture: ${process.arch}`))
+    // closes the file once the writes in flight are done
+    this._file.end().then(
+      () => callback(err),
+      () => callback(err),
+    )
   }
 }
 
-nativeBinding = requireNative()
+const { walk, createReadStream, createWriteStream } = Sftp.prototype
 
-if (!nativeBinding || process.env.NAPI_RS_FORCE_WASI) {
-  try {
-    nativeBinding = require('./ssh.wasi.cjs')
-  } catch (err) {
-    if (process.env.NAPI_RS_FORCE_WASI) {
-      loadErrors.push(err)
-    }
-  }
-  if (!nativeBinding) {
-    try {
-      nativeBinding = require('@napi-rs/ssh-wasm32-wasi')
-    } catch (err) {
-      if (process.env.NAPI_RS_FORCE_WASI) {
-        loadErrors.push(err)
-      }
-    }
-  }
+Sftp.prototype.walk = function (...args) {
+  return readAll(walk.apply(this, args))
 }
 
-if (!nativeBinding) {
-  if (loadErrors.length > 0) {
-    // TODO Link to documentation with potential fixes
-    //  - The package owner could build/publish bindings for this arch
-    //  - The user may need to bundle the correct files
-    //  - The user may need to re-install node_modules to get new packages
-    throw new Error('Failed to load native binding', { cause: loadErrors })
-  }
-  throw new Error(`Failed to load native binding`)
+Sftp.prototype.createReadStream = async function (...args) {
+  return new SftpReadable(await createReadStream.apply(this, args))
+}
+
+Sftp.prototype.createWriteStream = async function (...args) {
+  return new SftpWritable(await createWriteStream.apply(this, args))
 }
 
-module.exports.Client = nativeBinding.Client
-module.exports.KeyPair = nativeBinding.KeyPair
-module.exports.PublicKey = nativeBinding.PublicKey
-module.exports.Signature = nativeBinding.Signature
-module.exports.checkKnownHosts = nativeBinding.checkKnownHosts
-module.exports.ClientIdType = nativeBinding.ClientIdType
-module.exports.connect = nativeBinding.connect
-module.exports.DisconnectReason = nativeBinding.DisconnectReason
-module.exports.learnKnownHosts = nativeBinding.learnKnownHosts
-module.exports.SignatureHash = nativeBinding.SignatureHash
+// in this form, for the named exports to be found when imported from an ES module
+module.exports = require('./binding.js')
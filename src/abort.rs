@@ -0,0 +1,113 @@
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use tokio::sync::watch;
+
+/// A Node.js `AbortSignal`, watched from the tokio side.
+///
+/// napi's own `AbortSignal` only works with `AsyncTask`, so this listens for the `abort` event
+/// itself while the value is converted on the JavaScript thread. The listener is removed again
+/// when the `Abort` is dropped, so a long-lived signal passed to many calls does not collect one
+/// per call.
+pub struct Abort {
+  aborted: watch::Receiver<bool>,
+  /// `signal.removeEventListener('abort', listener)`, bound to its arguments.
+  remove_listener: ThreadsafeFunction<(), (), (), Status, false, true>,
+}
+
+impl Drop for Abort {
+  fn drop(&mut self) {
+    self
+      .remove_listener
+      .call((), ThreadsafeFunctionCallMode::NonBlocking);
+  }
+}
+
+impl Abort {
+  pub(crate) fn is_aborted(&self) -> bool {
+    *self.aborted.borrow()
+  }
+
+  /// Resolves once the signal is aborted, never if it is dropped without aborting.
+  pub(crate) async fn wait(&mut self) {
+    if self.aborted.wait_for(|aborted| *aborted).await.is_err() {
+      std::future::pending::<()>().await;
+    }
+  }
+
+  /// Resolves once `abort`, if any, is aborted.
+  pub(crate) async fn wait_for(abort: &mut Option<Abort>) {
+    match abort {
+      Some(abort) => abort.wait().await,
+      None => std::future::pending().await,
+    }
+  }
+}
+
+/// The error an aborted operation is rejected with.
+pub(crate) fn aborted() -> Error {
+  Error::new(
+    Status::Cancelled,
+    "AbortError: The operation was aborted".to_owned(),
+  )
+}
+
+impl TypeName for Abort {
+  fn type_name() -> &'static str {
+    "AbortSignal"
+  }
+
+  fn value_type() -> ValueType {
+    ValueType::Object
+  }
+}
+
+impl ValidateNapiValue for Abort {}
+
+impl FromNapiValue for Abort {
+  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+    let signal = Object::from_napi_value(env, napi_val)?;
+    let (tx, aborted) = watch::channel(signal.get_named_property::<bool>("aborted")?);
+    let env = Env::from_raw(env);
+    let listener = env.create_function_from_closure::<(), (), _>("onabort", move |_| {
+      tx.send_replace(true);
+      Ok(())
+    })?;
+    let not_a_signal = |_| {
+      Error::new(
+        Status::InvalidArg,
+        "signal must be an AbortSignal".to_owned(),
+      )
+    };
+    let add_event_listener = signal
+      .get_named_property::<Function<FnArgs<(&str, Function<(), ()>, Object)>, Unknown>>(
+        "addEventListener",
+      )
+      .map_err(not_a_signal)?;
+    let remove_event_listener = signal
+      .get_named_property::<Function<FnArgs<(&str, Function<(), ()>)>, Unknown>>(
+        "removeEventListener",
+      )
+      .map_err(not_a_signal)?;
+    let mut options = Object::new(&env)?;
+    options.set_named_property("once", true)?;
+    add_event_listener.apply(signal, FnArgs::from(("abort", listener, options)))?;
+    let remove_listener = remove_event_listener
+      .get_named_property::<Function<FnArgs<(Object, &str, Function<(), ()>)>, Function<(), ()>>>(
+        "bind",
+      )?
+      .apply(
+        remove_event_listener,
+        FnArgs::from((signal, "abort", listener)),
+      )?
+      .build_threadsafe_function()
+      .callee_handled::<false>()
+      .weak::<true>()
+      .build()?;
+    Ok(Self {
+      aborted,
+      remove_listener,
+    })
+  }
+}
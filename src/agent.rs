@@ -0,0 +1,257 @@
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use russh_keys::agent::{
+  client::{AgentClient, AgentStream},
+  Constraint,
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  sync::Mutex,
+};
+
+use crate::{
+  err::IntoError,
+  keypair::{KeyPair, PublicKey},
+};
+
+/// A Unix socket, a Windows named pipe or Pageant.
+pub(crate) type SshAgentClient = AgentClient<Box<dyn AgentStream + Send + Unpin + 'static>>;
+
+/// The pipe of the agent that comes with Windows OpenSSH.
+#[cfg(windows)]
+const OPENSSH_AGENT_PIPE: &str = r"\\.\pipe\openssh-ssh-agent";
+
+/// Connect to the agent at `path`, a Unix socket or a Windows named pipe.
+pub(crate) async fn connect_agent_at(path: &str) -> Result<SshAgentClient> {
+  #[cfg(unix)]
+  let agent = AgentClient::connect_uds(path).await;
+  #[cfg(windows)]
+  let agent = AgentClient::connect_named_pipe(path).await;
+  agent.map(AgentClient::dynamic).map_err(|err| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to connect to the ssh-agent at {path}: {err}"),
+    )
+  })
+}
+
+/// Connect to the agent at `SSH_AUTH_SOCK`. On Windows, fall back to the Windows OpenSSH agent
+/// and then Pageant when it is not set.
+pub(crate) async fn connect_default_agent() -> Result<SshAgentClient> {
+  if let Ok(path) = std::env::var("SSH_AUTH_SOCK") {
+    return connect_agent_at(&path).await;
+  }
+  #[cfg(windows)]
+  {
+    Ok(match connect_agent_at(OPENSSH_AGENT_PIPE).await {
+      Ok(agent) => agent,
+      Err(_) => AgentClient::connect_pageant().await.dynamic(),
+    })
+  }
+  #[cfg(not(windows))]
+  Err(Error::new(
+    Status::GenericFailure,
+    "SSH_AUTH_SOCK is not set".to_owned(),
+  ))
+}
+
+/// The agent protocol's `SSH_AGENTC_LOCK`, `SSH_AGENTC_UNLOCK` and `SSH_AGENT_SUCCESS`.
+const LOCK: u8 = 22;
+const UNLOCK: u8 = 23;
+const SUCCESS: u8 = 6;
+
+/// Send the agent a `kind` request with the string `data` and tell whether it answered with
+/// success. russh-keys reads but drops the reply of lock and unlock requests, so this talks to
+/// the stream itself.
+async fn request_succeeds(client: &mut SshAgentClient, kind: u8, data: &[u8]) -> Result<bool> {
+  let mut request = Vec::with_capacity(9 + data.len());
+  request.extend_from_slice(&(5 + data.len() as u32).to_be_bytes());
+  request.push(kind);
+  request.extend_from_slice(&(data.len() as u32).to_be_bytes());
+  request.extend_from_slice(data);
+  let placeholder: SshAgentClient = AgentClient::connect(Box::new(tokio::io::duplex(1).0));
+  let mut stream = std::mem::replace(client, placeholder).into_inner();
+  let reply = async {
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+    let mut length = [0; 4];
+    stream.read_exact(&mut length).await?;
+    let mut reply = vec![0; u32::from_be_bytes(length) as usize];
+    stream.read_exact(&mut reply).await?;
+    std::io::Result::Ok(reply)
+  }
+  .await;
+  *client = AgentClient::connect(stream);
+  let reply = reply.map_err(|err| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to talk to the ssh-agent: {err}"),
+    )
+  })?;
+  Ok(reply.first() == Some(&SUCCESS))
+}
+
+#[napi(object)]
+pub struct AddIdentityOptions {
+  /// Remove the key from the agent after this many seconds, like `ssh-add -t`.
+  pub lifetime: Option<u32>,
+  /// Ask for confirmation every time the key is used, like `ssh-add -c`.
+  pub confirm: Option<bool>,
+}
+
+fn constraints(options: Option<AddIdentityOptions>) -> Vec<Constraint> {
+  let mut constraints = Vec::new();
+  if let Some(options) = options {
+    if let Some(seconds) = options.lifetime {
+      constraints.push(Constraint::KeyLifetime { seconds });
+    }
+    if options.confirm.unwrap_or(false) {
+      constraints.push(Constraint::Confirm);
+    }
+  }
+  constraints
+}
+
+#[napi]
+/// The local ssh-agent, for managing its keys like `ssh-add` does.
+pub struct Agent {
+  client: Mutex<SshAgentClient>,
+}
+
+#[napi]
+impl Agent {
+  #[napi]
+  /// Connect to the agent at `path`, a Unix socket or a Windows named pipe. Defaults to
+  /// `SSH_AUTH_SOCK`, and on Windows then to the Windows OpenSSH agent and Pageant.
+  pub async fn connect(path: Option<String>) -> Result<Agent> {
+    let client = match path {
+      Some(path) => connect_agent_at(&path).await?,
+      None => connect_default_agent().await?,
+    };
+    Ok(Agent {
+      client: Mutex::new(client),
+    })
+  }
+
+  #[napi]
+  /// The public keys the agent holds.
+  pub async fn list_identities(&self) -> Result<Vec<PublicKey>> {
+    let identities = self
+      .client
+      .lock()
+      .await
+      .request_identities()
+      .await
+      .into_error()?;
+    Ok(identities.into_iter().map(PublicKey::new).collect())
+  }
+
+  #[napi]
+  /// # Safety
+  ///
+  /// `key` is borrowed until the returned promise settles and must not be freed before.
+  ///
+  /// Add `key` to the agent, replacing the constraints if it already holds the key.
+  pub async unsafe fn add_identity(
+    &self,
+    key: &KeyPair,
+    options: Option<AddIdentityOptions>,
+  ) -> Result<()> {
+    self
+      .client
+      .lock()
+      .await
+      .add_identity(&key.inner, &constraints(options))
+      .await
+      .into_error()
+  }
+
+  #[napi]
+  /// Have the agent load the keys of a PKCS#11 smartcard or HSM through the module at
+  /// `provider`, like `ssh-add -s`, so `Client.authenticateAgent` can sign with them.
+  /// The agent does the signing; OpenSSH's agent only loads modules from its `-P` allowlist.
+  /// Fails if the agent holds no new keys afterwards, as for a refused module or a wrong `pin`.
+  pub async fn add_smartcard_key(
+    &self,
+    provider: String,
+    pin: Option<String>,
+    options: Option<AddIdentityOptions>,
+  ) -> Result<()> {
+    let mut client = self.client.lock().await;
+    let before = client.request_identities().await.into_error()?;
+    client
+      .add_smartcard_key(
+        &provider,
+        pin.unwrap_or_default().as_bytes(),
+        &constraints(options),
+      )
+      .await
+      .into_error()?;
+    let after = client.request_identities().await.into_error()?;
+    if !after.iter().any(|key| !before.contains(key)) {
+      return Err(Error::new(
+        Status::GenericFailure,
+        format!(
+          "The ssh-agent added no keys from {provider}; it may not allow the module, or the pin is wrong"
+        ),
+      ));
+    }
+    Ok(())
+  }
+
+  #[napi]
+  /// # Safety
+  ///
+  /// `key` is borrowed until the returned promise settles and must not be freed before.
+  ///
+  /// Remove `key` from the agent.
+  pub async unsafe fn remove_identity(&self, key: &PublicKey) -> Result<()> {
+    self
+      .client
+      .lock()
+      .await
+      .remove_identity(&key.inner)
+      .await
+      .into_error()
+  }
+
+  #[napi]
+  /// Lock the agent with `passphrase`, like `ssh-add -x`. A locked agent lists no keys and
+  /// refuses to sign until unlocked. Fails if the agent is already locked.
+  pub async fn lock(&self, passphrase: String) -> Result<()> {
+    let mut client = self.client.lock().await;
+    if !request_succeeds(&mut client, LOCK, passphrase.as_bytes()).await? {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "The ssh-agent refused to lock".to_owned(),
+      ));
+    }
+    Ok(())
+  }
+
+  #[napi]
+  /// Unlock the agent, like `ssh-add -X`. Fails on a wrong passphrase, leaving it locked, and if
+  /// the agent is not locked.
+  pub async fn unlock(&self, passphrase: String) -> Result<()> {
+    let mut client = self.client.lock().await;
+    if !request_succeeds(&mut client, UNLOCK, passphrase.as_bytes()).await? {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "The ssh-agent refused to unlock; the passphrase may be wrong".to_owned(),
+      ));
+    }
+    Ok(())
+  }
+
+  #[napi]
+  /// Remove every key from the agent, like `ssh-add -D`.
+  pub async fn remove_all(&self) -> Result<()> {
+    self
+      .client
+      .lock()
+      .await
+      .remove_all_identities()
+      .await
+      .into_error()
+  }
+}
@@ -0,0 +1,831 @@
+use std::{
+  collections::{HashMap, VecDeque},
+  future::Future,
+  sync::{Arc, Mutex, MutexGuard, PoisonError},
+  time::Duration,
+};
+
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use napi_derive::napi;
+use russh::{client, ChannelId, ChannelMsg, Pty, Sig};
+use tokio::sync::{mpsc, oneshot, Notify};
+
+use crate::{err::IntoError, server::into_bytes};
+
+#[napi(object)]
+#[derive(Default)]
+/// The pseudo-terminal requested by `Client.shell` and `Client.execPty`.
+pub struct PtyOptions {
+  /// The `TERM` of the remote shell. Defaults to `xterm-256color`.
+  pub term: Option<String>,
+  /// Defaults to `80`.
+  pub cols: Option<u32>,
+  /// Defaults to `24`.
+  pub rows: Option<u32>,
+  /// The terminal width in pixels. Defaults to `0`, meaning unknown.
+  pub pix_width: Option<u32>,
+  /// The terminal height in pixels. Defaults to `0`, meaning unknown.
+  pub pix_height: Option<u32>,
+  /// Terminal modes by their RFC 4254 names, e.g. `{ ECHO: 0, VINTR: 3 }`.
+  pub modes: Option<HashMap<String, u32>>,
+  /// See `ChannelOptions.idleTimeoutMs`.
+  pub idle_timeout_ms: Option<u32>,
+}
+
+#[napi(object)]
+#[derive(Default)]
+/// Options of the channel returned by `Client.openSession`, or set by `Channel.requestSubsystem`.
+pub struct ChannelOptions {
+  /// Close the channel when no data flows on it in either direction for this long, in
+  /// milliseconds. `onClose` is then called with an `IdleTimeout` error, and pending and later
+  /// requests as well as iterating the channel fail with it. The rest of the connection is
+  /// unaffected.
+  pub idle_timeout_ms: Option<u32>,
+}
+
+#[napi(object)]
+#[derive(Clone, Default)]
+/// The SSH flow control of a channel, as returned by `Channel.flowStats`. It is recorded from the
+/// messages the server sends, so reading it sends nothing.
+pub struct FlowStats {
+  /// The outbound window left after the last WindowAdjust from the server was applied, or the
+  /// window the server granted when the channel was opened. Data sent since is not subtracted:
+  /// russh keeps the running window private.
+  pub outbound_window: u32,
+  /// Whether data was still queued waiting for window after the last WindowAdjust was applied.
+  /// The size of that queue is private to russh, so it can not be reported in bytes.
+  pub waiting_for_window: bool,
+  /// The WindowAdjust messages received from the server.
+  pub window_adjusts: u32,
+  /// The largest packet the server accepts on the channel, as it announced when opening it.
+  pub max_packet_size: u32,
+}
+
+/// The flow control of the channels of a connection, recorded by the session handler.
+#[derive(Default)]
+pub(crate) struct FlowControl(Mutex<HashMap<ChannelId, Arc<Mutex<FlowStats>>>>);
+
+impl FlowControl {
+  /// The stats of channel `id`, shared between the session handler and the `Channel`. russh
+  /// hands the channel over before the handler hears of it, so either one may create them.
+  pub(crate) fn channel(&self, id: ChannelId) -> Arc<Mutex<FlowStats>> {
+    self
+      .0
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .entry(id)
+      .or_default()
+      .clone()
+  }
+
+  pub(crate) fn update(&self, id: ChannelId, update: impl FnOnce(&mut FlowStats)) {
+    update(
+      &mut self
+        .channel(id)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner),
+    );
+  }
+
+  /// Forget channel `id` once it is closed. A `Channel` keeps its last stats.
+  pub(crate) fn remove(&self, id: ChannelId) {
+    self
+      .0
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .remove(&id);
+  }
+}
+
+/// Open a PTY on a new session channel and start `command` on it, or the login shell without one.
+pub(crate) async fn open_pty(
+  mut channel: russh::Channel<client::Msg>,
+  flow: &FlowControl,
+  options: PtyOptions,
+  command: Option<String>,
+) -> Result<Channel> {
+  let modes = options
+    .modes
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(name, value)| {
+      // the opcodes are sparse, so look the name up among every one russh knows
+      (1..=u8::MAX)
+        .filter_map(Pty::from_u8)
+        .find(|mode| format!("{mode:?}") == name)
+        .map(|mode| (mode, value))
+        .ok_or_else(|| Error::new(Status::InvalidArg, format!("Unknown terminal mode {name}")))
+    })
+    .collect::<Result<Vec<_>>>()?;
+  channel
+    .request_pty(
+      true,
+      options.term.as_deref().unwrap_or("xterm-256color"),
+      options.cols.unwrap_or(80),
+      options.rows.unwrap_or(24),
+      options.pix_width.unwrap_or(0),
+      options.pix_height.unwrap_or(0),
+      &modes,
+    )
+    .await
+    .into_error()?;
+  wait_for_reply(&mut channel, "PTY").await?;
+  match command {
+    Some(command) => {
+      channel.exec(true, command).await.into_error()?;
+      wait_for_reply(&mut channel, "exec").await?;
+    }
+    None => {
+      channel.request_shell(true).await.into_error()?;
+      wait_for_reply(&mut channel, "shell").await?;
+    }
+  }
+  Ok(Channel::new(channel, flow, options.idle_timeout_ms))
+}
+
+pub(crate) async fn wait_for_reply(
+  channel: &mut russh::Channel<client::Msg>,
+  request: &str,
+) -> Result<()> {
+  loop {
+    match channel.wait().await {
+      Some(ChannelMsg::Success) => return Ok(()),
+      Some(ChannelMsg::Failure) => {
+        return Err(Error::new(
+          Status::GenericFailure,
+          format!("The server refused the {request} request"),
+        ))
+      }
+      Some(_) => {}
+      None => {
+        return Err(Error::new(
+          Status::GenericFailure,
+          "Channel is closed".to_owned(),
+        ))
+      }
+    }
+  }
+}
+
+/// The name of a signal, without the `SIG` prefix.
+pub(crate) fn signal_name(signal: Sig) -> String {
+  match signal {
+    Sig::Custom(name) => name,
+    // the other variants are named after the signal
+    name => format!("{name:?}"),
+  }
+}
+
+/// Parse a signal name, with or without the `SIG` prefix.
+pub(crate) fn parse_signal(name: &str) -> Result<Sig> {
+  let name = name.strip_prefix("SIG").unwrap_or(name);
+  if name.is_empty() {
+    return Err(Error::new(
+      Status::InvalidArg,
+      "The signal name can not be empty".to_owned(),
+    ));
+  }
+  Ok(Sig::Custom(name.to_owned()))
+}
+
+/// Closes a channel that has gone without data in either direction for too long.
+pub(crate) struct IdleTimeout {
+  limit: Option<Duration>,
+  deadline: tokio::time::Instant,
+}
+
+impl IdleTimeout {
+  pub(crate) fn new(limit_ms: Option<u32>) -> Self {
+    let limit = limit_ms.map(|limit| Duration::from_millis(limit as u64));
+    Self {
+      limit,
+      deadline: tokio::time::Instant::now() + limit.unwrap_or_default(),
+    }
+  }
+
+  /// Push the deadline back, on data sent or received.
+  pub(crate) fn reset(&mut self) {
+    if let Some(limit) = self.limit {
+      self.deadline = tokio::time::Instant::now() + limit;
+    }
+  }
+
+  /// The next message on `channel`. Only data resets the timer, so keepalives, window
+  /// adjustments and requests do not keep an abandoned channel open.
+  pub(crate) async fn wait(
+    &mut self,
+    channel: &mut russh::Channel<client::Msg>,
+  ) -> Result<Option<ChannelMsg>> {
+    let Some(limit) = self.limit else {
+      return Ok(channel.wait().await);
+    };
+    match tokio::time::timeout_at(self.deadline, channel.wait()).await {
+      Ok(msg) => {
+        if matches!(
+          msg,
+          Some(ChannelMsg::Data { .. } | ChannelMsg::ExtendedData { .. })
+        ) {
+          self.reset();
+        }
+        Ok(msg)
+      }
+      Err(_) => {
+        let _ = channel.close().await;
+        Err(Error::new(
+          Status::GenericFailure,
+          format!(
+            "IdleTimeout: no data on the channel for {} ms",
+            limit.as_millis()
+          ),
+        ))
+      }
+    }
+  }
+}
+
+/// Output received from the server, buffered until a listener is registered or it is read by
+/// iterating the channel.
+#[derive(Default)]
+struct ChannelEvents {
+  state: Mutex<ChannelEventsState>,
+  /// Wakes the readers when output is buffered or the channel closes.
+  readable: Notify,
+}
+
+type OnClose = ThreadsafeFunction<
+  FnArgs<(Option<u32>, Option<Error<&'static str>>)>,
+  (),
+  FnArgs<(Option<u32>, Option<Error<&'static str>>)>,
+  Status,
+  false,
+>;
+
+#[derive(Default)]
+struct ChannelEventsState {
+  on_data: Option<
+    ThreadsafeFunction<FnArgs<(Buffer, String)>, (), FnArgs<(Buffer, String)>, Status, false>,
+  >,
+  on_close: Option<OnClose>,
+  pending: VecDeque<(Vec<u8>, &'static str)>,
+  exit_status: Option<u32>,
+  exit_signal: Option<String>,
+  /// Why this side closed the channel, the message of an `IdleTimeout` error.
+  idle_timeout: Option<String>,
+  closed: bool,
+}
+
+impl ChannelEventsState {
+  fn close_args(&self) -> FnArgs<(Option<u32>, Option<Error<&'static str>>)> {
+    let error = self
+      .idle_timeout
+      .clone()
+      .map(|message| Error::new("IdleTimeout", message));
+    FnArgs::from((self.exit_status, error))
+  }
+}
+
+impl ChannelEvents {
+  fn state(&self) -> MutexGuard<'_, ChannelEventsState> {
+    self.state.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  fn message(&self, msg: ChannelMsg) {
+    let (data, stream) = match msg {
+      ChannelMsg::Data { data } => (data, "stdout"),
+      ChannelMsg::ExtendedData { data, ext: 1 } => (data, "stderr"),
+      ChannelMsg::ExitStatus { exit_status } => {
+        self.state().exit_status = Some(exit_status);
+        return;
+      }
+      ChannelMsg::ExitSignal {
+        signal_name: signal,
+        ..
+      } => {
+        self.state().exit_signal = Some(signal_name(signal));
+        return;
+      }
+      _ => return,
+    };
+    let mut state = self.state();
+    match &state.on_data {
+      Some(on_data) => {
+        on_data.call(
+          FnArgs::from((data.to_vec().into(), stream.to_owned())),
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+      None => {
+        state.pending.push_back((data.to_vec(), stream));
+        self.readable.notify_waiters();
+      }
+    }
+  }
+
+  fn close(&self) {
+    let mut state = self.state();
+    state.closed = true;
+    state.on_data = None;
+    if let Some(on_close) = state.on_close.take() {
+      on_close.call(state.close_args(), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+    self.readable.notify_waiters();
+  }
+
+  /// Wait until `ready` returns `Some` for the state, checking again whenever output is
+  /// buffered or the channel closes.
+  async fn wait_until<T>(&self, mut ready: impl FnMut(&mut ChannelEventsState) -> Option<T>) -> T {
+    loop {
+      let notified = self.readable.notified();
+      tokio::pin!(notified);
+      // `notify_waiters` only wakes registered futures, so register before checking
+      notified.as_mut().enable();
+      if let Some(value) = ready(&mut self.state()) {
+        return value;
+      }
+      notified.await;
+    }
+  }
+
+  /// The next buffered chunk of `stream`, `None` once the channel has closed and everything
+  /// was read. With `skip_others` the chunks of the other stream before it are dropped.
+  async fn next_output(&self, stream: &str, skip_others: bool) -> Option<Vec<u8>> {
+    self
+      .wait_until(|state| {
+        if skip_others {
+          state.pending.retain(|(_, pending)| *pending == stream);
+        }
+        match state
+          .pending
+          .iter()
+          .position(|(_, pending)| *pending == stream)
+        {
+          Some(position) => Some(state.pending.remove(position).map(|(data, _)| data)),
+          None => state.closed.then_some(None),
+        }
+      })
+      .await
+  }
+
+  /// The `IdleTimeout` error, once the channel was closed for being idle.
+  fn idle_timeout(&self) -> Option<Error> {
+    self
+      .state()
+      .idle_timeout
+      .clone()
+      .map(|message| Error::new(Status::GenericFailure, message))
+  }
+
+  /// The exit status and signal once the channel has closed.
+  async fn closed(&self) -> (Option<u32>, Option<String>) {
+    self
+      .wait_until(|state| {
+        state
+          .closed
+          .then(|| (state.exit_status, state.exit_signal.clone()))
+      })
+      .await
+  }
+
+  fn set_on_data(
+    &self,
+    on_data: ThreadsafeFunction<
+      FnArgs<(Buffer, String)>,
+      (),
+      FnArgs<(Buffer, String)>,
+      Status,
+      false,
+    >,
+  ) {
+    let mut state = self.state();
+    for (data, stream) in std::mem::take(&mut state.pending) {
+      on_data.call(
+        FnArgs::from((data.into(), stream.to_owned())),
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }
+    if !state.closed {
+      state.on_data = Some(on_data);
+    }
+  }
+
+  fn set_on_close(&self, on_close: OnClose) {
+    let mut state = self.state();
+    if state.closed {
+      on_close.call(state.close_args(), ThreadsafeFunctionCallMode::NonBlocking);
+    } else {
+      state.on_close = Some(on_close);
+    }
+  }
+}
+
+pub(crate) enum Request {
+  SetEnv(String, String),
+  Exec(String),
+  /// With the idle timeout to apply from then on, if one is set.
+  Subsystem(String, Option<u32>),
+  Data(Vec<u8>),
+  Eof,
+  Signal(Sig),
+  WindowChange {
+    cols: u32,
+    rows: u32,
+    pix_width: u32,
+    pix_height: u32,
+  },
+  Close,
+}
+
+#[napi(async_iterator)]
+/// A session channel opened with `Client.openSession`.
+///
+/// `for await (const chunk of channel)` reads stdout as Buffers until the channel closes.
+/// Iterating only sees output not handed to an `onData` listener, and skips stderr.
+#[derive(Clone)]
+pub struct Channel {
+  id: ChannelId,
+  requests: mpsc::UnboundedSender<(Request, oneshot::Sender<bool>)>,
+  events: Arc<ChannelEvents>,
+  flow: Arc<Mutex<FlowStats>>,
+}
+
+#[napi]
+impl Channel {
+  pub(crate) fn new(
+    mut channel: russh::Channel<client::Msg>,
+    flow: &FlowControl,
+    idle_timeout_ms: Option<u32>,
+  ) -> Self {
+    let id = channel.id();
+    let flow = flow.channel(id);
+    let events = Arc::new(ChannelEvents::default());
+    let channel_events = events.clone();
+    // one task owns the channel, sending requests in the order they were issued from
+    // JavaScript while handing incoming messages to the listeners
+    let (requests, mut queue) = mpsc::unbounded_channel::<(Request, oneshot::Sender<bool>)>();
+    tokio::spawn(async move {
+      let mut queue_open = true;
+      let mut idle = IdleTimeout::new(idle_timeout_ms);
+      loop {
+        tokio::select! {
+          request = queue.recv(), if queue_open => {
+            let Some((request, sent)) = request else {
+              queue_open = false;
+              continue;
+            };
+            let result = match request {
+              Request::SetEnv(name, value) => channel.set_env(false, name, value).await,
+              Request::Exec(command) => channel.exec(true, command).await,
+              Request::Subsystem(name, idle_timeout_ms) => {
+                if idle_timeout_ms.is_some() {
+                  idle = IdleTimeout::new(idle_timeout_ms);
+                }
+                channel.request_subsystem(true, name).await
+              }
+              Request::Data(data) => {
+                idle.reset();
+                channel.data(&data[..]).await
+              }
+              Request::Eof => channel.eof().await,
+              Request::Signal(signal) => channel.signal(signal).await,
+              Request::WindowChange {
+                cols,
+                rows,
+                pix_width,
+                pix_height,
+              } => {
+                channel
+                  .window_change(cols, rows, pix_width, pix_height)
+                  .await
+              }
+              Request::Close => channel.close().await,
+            };
+            let _ = sent.send(result.is_ok());
+          }
+          msg = idle.wait(&mut channel) => match msg {
+            Ok(Some(msg)) => channel_events.message(msg),
+            Ok(None) => break,
+            Err(err) => {
+              channel_events.state().idle_timeout = Some(err.reason);
+              break;
+            }
+          },
+        }
+      }
+      channel_events.close();
+    });
+    Self {
+      id,
+      requests,
+      events,
+      flow,
+    }
+  }
+
+  /// Queue `request`, resolving once it was sent.
+  pub(crate) fn request(
+    &self,
+    request: Request,
+  ) -> impl Future<Output = Result<()>> + Send + 'static {
+    let (tx, rx) = oneshot::channel();
+    let _ = self.requests.send((request, tx));
+    let events = self.events.clone();
+    async move {
+      if rx.await != Ok(true) {
+        if let Some(err) = events.idle_timeout() {
+          return Err(err);
+        }
+        return Err(Error::new(
+          Status::GenericFailure,
+          "Channel is closed".to_owned(),
+        ));
+      }
+      Ok(())
+    }
+  }
+
+  fn send<'env>(&self, env: &'env Env, request: Request) -> Result<PromiseRaw<'env, ()>> {
+    env.spawn_future(self.request(request))
+  }
+
+  pub(crate) async fn next_output(&self, stream: &str) -> Option<Vec<u8>> {
+    self.events.next_output(stream, false).await
+  }
+
+  pub(crate) async fn closed(&self) -> (Option<u32>, Option<String>) {
+    self.events.closed().await
+  }
+
+  #[napi(getter)]
+  pub fn id(&self) -> u32 {
+    self.id.into()
+  }
+
+  #[napi]
+  /// The SSH flow control of the channel, for telling whether a stalled transfer waits for the
+  /// server to adjust the window or for this side to send.
+  pub fn flow_stats(&self) -> FlowStats {
+    self
+      .flow
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .clone()
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Set an environment variable for the command started next. Servers drop the ones they do
+  /// not accept, e.g. those missing from OpenSSH's `AcceptEnv`, without an error.
+  pub fn set_env<'env>(
+    &self,
+    env: &'env Env,
+    name: String,
+    value: String,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, Request::SetEnv(name, value))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Start `command` on the channel. Only one command can run on a channel.
+  pub fn exec<'env>(&self, env: &'env Env, command: String) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, Request::Exec(command))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Start the subsystem `name`, e.g. `netconf`, instead of a command. Its protocol is then
+  /// spoken with `write` and `onData`. A `ChannelOptions.idleTimeoutMs` set here replaces the
+  /// one the channel was opened with.
+  pub fn request_subsystem<'env>(
+    &self,
+    env: &'env Env,
+    name: String,
+    options: Option<ChannelOptions>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    let idle_timeout_ms = options.and_then(|options| options.idle_timeout_ms);
+    self.send(env, Request::Subsystem(name, idle_timeout_ms))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Write to the command's stdin.
+  pub fn write<'env>(
+    &self,
+    env: &'env Env,
+    data: Either<String, Buffer>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, Request::Data(into_bytes(data)))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Signal that no more data will be written to stdin.
+  pub fn eof<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, Request::Eof)
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Deliver a signal to the command, named without the `SIG` prefix, e.g. `TERM`.
+  /// Many servers, OpenSSH before 7.9 among them, ignore signals.
+  pub fn signal<'env>(&self, env: &'env Env, name: String) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, Request::Signal(parse_signal(&name)?))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Tell the server the terminal was resized, e.g. from an xterm.js `resize` event.
+  /// The pixel size defaults to `0`, meaning unknown. Only meaningful on a channel with a PTY.
+  pub fn window_change<'env>(
+    &self,
+    env: &'env Env,
+    cols: u32,
+    rows: u32,
+    pix_width: Option<u32>,
+    pix_height: Option<u32>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    self.send(
+      env,
+      Request::WindowChange {
+        cols,
+        rows,
+        pix_width: pix_width.unwrap_or(0),
+        pix_height: pix_height.unwrap_or(0),
+      },
+    )
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  pub fn close<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, Request::Close)
+  }
+
+  #[napi(ts_return_type = "Promise<number | null>")]
+  /// Wire the channel to the stdin, stdout and stderr of this process, like the `ssh` CLI.
+  /// While attached, a terminal stdin is in raw mode and resizes of a terminal stdout are sent
+  /// to the server. Resolves with the exit status once the channel closes and stdin is
+  /// restored. Takes the place of an `onData` listener.
+  pub fn attach_stdio<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, Option<u32>>> {
+    let stdin = process_stream(env, "stdin")?;
+    let stdout = process_stream(env, "stdout")?;
+    let raw_mode = stdin
+      .get_named_property::<Option<bool>>("isTTY")?
+      .unwrap_or(false);
+
+    let channel = self.clone();
+    let on_input = env.create_function_from_closure::<Buffer, (), _>("onInput", move |ctx| {
+      // the request is queued right away, only its result is not waited for
+      drop(channel.request(Request::Data(ctx.first_arg::<Buffer>()?.to_vec())));
+      Ok(())
+    })?;
+    let channel = self.clone();
+    let on_end = env.create_function_from_closure::<(), (), _>("onEnd", move |_| {
+      drop(channel.request(Request::Eof));
+      Ok(())
+    })?;
+    let channel = self.clone();
+    let on_resize = env.create_function_from_closure::<(), (), _>("onResize", move |ctx| {
+      if let Some((cols, rows)) = terminal_size(ctx.env)? {
+        drop(channel.request(Request::WindowChange {
+          cols,
+          rows,
+          pix_width: 0,
+          pix_height: 0,
+        }));
+      }
+      Ok(())
+    })?;
+    let on_output =
+      env.create_function_from_closure::<FnArgs<(Buffer, String)>, (), _>("onOutput", |ctx| {
+        let stream = process_stream(ctx.env, &ctx.get::<String>(1)?)?;
+        call_method(&stream, "write", ctx.get::<Buffer>(0)?)
+      })?;
+
+    self.events.set_on_data(
+      on_output
+        .build_threadsafe_function()
+        .callee_handled::<false>()
+        .build()?,
+    );
+    let listeners = (
+      on_input.create_ref()?,
+      on_end.create_ref()?,
+      on_resize.create_ref()?,
+    );
+    // the PTY was requested with whatever size the caller passed, catch up with the terminal
+    on_resize.call(())?;
+    if raw_mode {
+      call_method(&stdin, "setRawMode", true)?;
+    }
+    call_method(&stdin, "on", FnArgs::from(("data", on_input)))?;
+    call_method(&stdin, "on", FnArgs::from(("end", on_end)))?;
+    call_method(&stdout, "on", FnArgs::from(("resize", on_resize)))?;
+    let events = self.events.clone();
+    env
+      .spawn_future(async move { Ok(events.closed().await.0) })?
+      .then(move |ctx| {
+        let (on_input, on_end, on_resize) = listeners;
+        let stdin = process_stream(&ctx.env, "stdin")?;
+        let stdout = process_stream(&ctx.env, "stdout")?;
+        call_method(
+          &stdin,
+          "removeListener",
+          FnArgs::from(("data", on_input.borrow_back(&ctx.env)?)),
+        )?;
+        call_method(
+          &stdin,
+          "removeListener",
+          FnArgs::from(("end", on_end.borrow_back(&ctx.env)?)),
+        )?;
+        call_method(
+          &stdout,
+          "removeListener",
+          FnArgs::from(("resize", on_resize.borrow_back(&ctx.env)?)),
+        )?;
+        if raw_mode {
+          call_method(&stdin, "setRawMode", false)?;
+        }
+        // stop reading, so stdin no longer keeps the process alive
+        call_method(&stdin, "pause", ())?;
+        Ok(ctx.value)
+      })
+  }
+
+  #[napi]
+  /// Listen for output from the command. Output received before a listener is registered is
+  /// buffered.
+  pub fn on_data(
+    &self,
+    #[napi(ts_arg_type = "(data: Buffer, stream: 'stdout' | 'stderr') => void")]
+    callback: ThreadsafeFunction<
+      FnArgs<(Buffer, String)>,
+      (),
+      FnArgs<(Buffer, String)>,
+      Status,
+      false,
+    >,
+  ) {
+    self.events.set_on_data(callback);
+  }
+
+  #[napi]
+  /// Listen for the channel closing, with the exit status of the command if the server sent one,
+  /// and the `IdleTimeout` error if it was closed by `idleTimeoutMs`.
+  pub fn on_close(
+    &self,
+    #[napi(ts_arg_type = "(exitStatus: number | null, error: Error | null) => void")]
+    callback: OnClose,
+  ) {
+    self.events.set_on_close(callback);
+  }
+}
+
+/// The size of `process.stdout`, if it is a terminal.
+fn terminal_size(env: &Env) -> Result<Option<(u32, u32)>> {
+  let stdout = process_stream(env, "stdout")?;
+  if !stdout
+    .get_named_property::<Option<bool>>("isTTY")?
+    .unwrap_or(false)
+  {
+    return Ok(None);
+  }
+  Ok(Some((
+    stdout.get_named_property("columns")?,
+    stdout.get_named_property("rows")?,
+  )))
+}
+
+fn process_stream<'env>(env: &'env Env, name: &str) -> Result<Object<'env>> {
+  env
+    .get_global()?
+    .get_named_property::<Object>("process")?
+    .get_named_property(name)
+}
+
+/// Call `stream[method](args)`.
+fn call_method<Args: JsValuesTupleIntoVec>(
+  stream: &Object,
+  method: &str,
+  args: Args,
+) -> Result<()> {
+  stream
+    .get_named_property::<Function<Args, Unknown>>(method)?
+    .apply(stream, args)?;
+  Ok(())
+}
+
+#[napi]
+impl AsyncGenerator for Channel {
+  type Yield = Buffer;
+  type Next = ();
+  type Return = ();
+
+  fn next(
+    &mut self,
+    _value: Option<Self::Next>,
+  ) -> impl Future<Output = Result<Option<Self::Yield>>> + Send + 'static {
+    let events = self.events.clone();
+    async move {
+      match events.next_output("stdout", true).await {
+        Some(data) => Ok(Some(data.into())),
+        None => events.idle_timeout().map_or(Ok(None), Err),
+      }
+    }
+  }
+}
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, future::Future};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+
+use crate::{
+  channel::{parse_signal, Channel, Request},
+  server::into_bytes,
+};
+
+#[napi(object)]
+/// Options of `Client.spawn`.
+pub struct SpawnOptions {
+  /// Environment variables sent before the command starts. Servers drop the ones they do not
+  /// accept, see `Channel.setEnv`.
+  pub env: Option<HashMap<String, String>>,
+}
+
+#[napi(object)]
+/// How a spawned command ended, like the arguments of Node's `exit` event.
+pub struct SpawnExit {
+  /// The exit status, `null` if the server sent none, e.g. because the command was killed.
+  pub code: Option<u32>,
+  /// The signal that killed the command, without the `SIG` prefix.
+  pub signal: Option<String>,
+}
+
+#[napi]
+/// A command started with `Client.spawn`, shaped like Node's `ChildProcess`.
+pub struct ChildProcess {
+  channel: Channel,
+}
+
+#[napi]
+impl ChildProcess {
+  pub(crate) fn new(channel: Channel) -> Self {
+    Self { channel }
+  }
+
+  #[napi(getter)]
+  pub fn stdin(&self) -> ChildStdin {
+    ChildStdin {
+      channel: self.channel.clone(),
+    }
+  }
+
+  #[napi(getter)]
+  pub fn stdout(&self) -> ChildOutput {
+    ChildOutput {
+      channel: self.channel.clone(),
+      stream: "stdout",
+    }
+  }
+
+  #[napi(getter)]
+  pub fn stderr(&self) -> ChildOutput {
+    ChildOutput {
+      channel: self.channel.clone(),
+      stream: "stderr",
+    }
+  }
+
+  #[napi(getter, ts_return_type = "Promise<SpawnExit>")]
+  /// Resolves once the channel has closed.
+  pub fn exit<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, SpawnExit>> {
+    let channel = self.channel.clone();
+    env.spawn_future(async move {
+      let (code, signal) = channel.closed().await;
+      Ok(SpawnExit { code, signal })
+    })
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Deliver a signal to the command, `TERM` by default. Many servers, OpenSSH before 7.9
+  /// among them, ignore signals.
+  pub fn kill<'env>(&self, env: &'env Env, signal: Option<String>) -> Result<PromiseRaw<'env, ()>> {
+    let signal = parse_signal(signal.as_deref().unwrap_or("TERM"))?;
+    env.spawn_future(self.channel.request(Request::Signal(signal)))
+  }
+}
+
+#[napi]
+/// The stdin of a spawned command.
+pub struct ChildStdin {
+  channel: Channel,
+}
+
+#[napi]
+impl ChildStdin {
+  #[napi(ts_return_type = "Promise<void>")]
+  pub fn write<'env>(
+    &self,
+    env: &'env Env,
+    data: Either<String, Buffer>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    env.spawn_future(self.channel.request(Request::Data(into_bytes(data))))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Write `data`, if any, then signal that no more data will be written.
+  pub fn end<'env>(
+    &self,
+    env: &'env Env,
+    data: Option<Either<String, Buffer>>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    let written = data.map(|data| self.channel.request(Request::Data(into_bytes(data))));
+    let eof = self.channel.request(Request::Eof);
+    env.spawn_future(async move {
+      if let Some(written) = written {
+        written.await?;
+      }
+      eof.await
+    })
+  }
+}
+
+#[napi(async_iterator)]
+/// The stdout or stderr of a spawned command, read with `for await`, e.g. through
+/// `Readable.from`. Output is buffered until it is read, so read both streams or neither.
+pub struct ChildOutput {
+  channel: Channel,
+  stream: &'static str,
+}
+
+#[napi]
+impl AsyncGenerator for ChildOutput {
+  type Yield = Buffer;
+  type Next = ();
+  type Return = ();
+
+  fn next(
+    &mut self,
+    _value: Option<Self::Next>,
+  ) -> impl Future<Output = Result<Option<Self::Yield>>> + Send + 'static {
+    let channel = self.channel.clone();
+    let stream = self.stream;
+    async move { Ok(channel.next_output(stream).await.map(Buffer::from)) }
+  }
+}
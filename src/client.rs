@@ -1,20 +1,51 @@
-use std::sync::Arc;
+use std::{
+  borrow::Cow,
+  collections::HashMap,
+  io,
+  path::PathBuf,
+  pin::Pin,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+  task::{Context, Poll},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use async_trait::async_trait;
 use napi::{
   bindgen_prelude::*,
   threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode, UnknownReturnValue},
+  JsDate,
 };
 use napi_derive::napi;
-use russh::client::{self, Session};
-use russh_keys::{agent::client::AgentClient, key, load_secret_key};
-use tokio::io::AsyncWriteExt;
-#[cfg(not(windows))]
-use tokio::net::UnixStream as SshAgentStream;
+use russh::{
+  cipher,
+  client::{self, Session},
+  ChannelId, Pty,
+};
+use russh_keys::{key, load_secret_key};
+use tokio::{
+  io::{AsyncRead, AsyncWrite, ReadBuf},
+  net::TcpStream,
+  sync::{watch, RwLock},
+};
 
 use crate::{
-  err::IntoError,
-  keypair::{KeyPair, PublicKey},
+  abort::{aborted, Abort},
+  agent::{connect_agent_at, connect_default_agent, SshAgentClient},
+  channel::{
+    open_pty, signal_name, wait_for_reply, Channel, ChannelOptions, FlowControl, IdleTimeout,
+    PtyOptions,
+  },
+  child_process::{ChildProcess, SpawnOptions},
+  err::{coded, Coded, IntoError},
+  host_key_store::{HostKeyStatus, HostKeyStore},
+  keypair::{KeyPair, PublicKey, SignatureHash},
+  scp::{self, ScpOptions},
+  server::{into_bytes, KeyboardInteractiveChallenge, KeyboardInteractivePrompt},
+  sftp::{self, Sftp, SftpOptions, TransferOptions},
+  sshfp::{Sshfp, SshfpOptions, SshfpStatus},
 };
 
 #[napi]
@@ -44,9 +75,7 @@ impl From<Limits> for russh::Limits {
     Self {
       rekey_write_limit: limits.rekey_write_limit.unwrap_or(1 << 30 /* 1GB */) as usize,
       rekey_read_limit: limits.rekey_read_limit.unwrap_or(1 << 30 /* 1GB */) as usize,
-      rekey_time_limit: std::time::Duration::from_secs(
-        limits.rekey_time_limit.unwrap_or(3600) as u64
-      ),
+      rekey_time_limit: Duration::from_secs(limits.rekey_time_limit.unwrap_or(3600) as u64),
     }
   }
 }
@@ -71,13 +100,29 @@ pub struct ClientConfig {
   pub maximum_packet_size: Option<u32>,
   /// Time after which the connection is garbage-collected. In milliseconds.
   pub inactivity_timeout: Option<u32>,
+  /// Send a `keepalive@openssh.com` request whenever nothing was received from the server for
+  /// this long, to notice connections that died silently, e.g. behind a NAT. In milliseconds.
+  pub keepalive_interval: Option<u32>,
+  /// Close the connection once this many keepalives in a row went unanswered, reporting
+  /// `Keepalive timeout` to `onClose`. `0` never gives up. Defaults to `3`.
+  pub keepalive_max: Option<u32>,
   /// Whether to expect and wait for an authentication call.
   pub anonymous: Option<bool>,
+  /// Symmetric ciphers to offer, most preferred first.
+  /// Defaults to the order returned by `cipherAcceleration()`.
+  pub preferred_ciphers: Option<Vec<String>>,
+  /// `'legacy'` additionally offers what old devices need, after the modern algorithms:
+  /// the SHA-1 Diffie-Hellman key exchanges, CBC ciphers and `ssh-rsa` host keys.
+  /// RSA user keys still sign with their own `SignatureHash`. Defaults to `'modern'`.
+  #[napi(ts_type = "'modern' | 'legacy'")]
+  pub compatibility: Option<String>,
 }
 
-impl From<ClientConfig> for russh::client::Config {
-  fn from(config: ClientConfig) -> Self {
-    let mut russh_config = Self::default();
+impl TryFrom<ClientConfig> for russh::client::Config {
+  type Error = Error;
+
+  fn try_from(config: ClientConfig) -> Result<Self> {
+    let mut russh_config = default_client_config();
     if let Some(client_id) = config.client_id {
       russh_config.client_id = match client_id.kind {
         ClientIdType::Standard => russh::SshId::Standard(client_id.id),
@@ -95,11 +140,170 @@ impl From<ClientConfig> for russh::client::Config {
     }
     russh_config.inactivity_timeout = config
       .inactivity_timeout
-      .map(|timeout| std::time::Duration::from_millis(timeout as u64));
+      .map(|timeout| Duration::from_millis(timeout as u64));
+    russh_config.keepalive_interval = config
+      .keepalive_interval
+      .map(|interval| Duration::from_millis(interval as u64));
+    if let Some(keepalive_max) = config.keepalive_max {
+      russh_config.keepalive_max = keepalive_max as usize;
+    }
     if let Some(anonymous) = config.anonymous {
       russh_config.anonymous = anonymous;
     }
-    russh_config
+    match config.compatibility.as_deref() {
+      None | Some("modern") => {}
+      Some("legacy") => widen_for_legacy_servers(&mut russh_config.preferred),
+      Some(preset) => {
+        return Err(Error::new(
+          Status::InvalidArg,
+          format!("Unknown compatibility preset: {preset}"),
+        ))
+      }
+    }
+    if let Some(preferred_ciphers) = config.preferred_ciphers {
+      russh_config.preferred.cipher = preferred_ciphers
+        .iter()
+        .map(|name| {
+          cipher::Name::try_from(name.as_str())
+            .map_err(|_| Error::new(Status::InvalidArg, format!("Unsupported cipher: {name}")))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into();
+    }
+    Ok(russh_config)
+  }
+}
+
+/// Ciphers offered when the CPU has AES instructions: AES-GCM is then considerably faster than
+/// ChaCha20-Poly1305, which is what OpenSSH prefers too.
+const HARDWARE_AES_CIPHER_ORDER: &[cipher::Name] = &[
+  cipher::AES_256_GCM,
+  cipher::CHACHA20_POLY1305,
+  cipher::AES_256_CTR,
+  cipher::AES_192_CTR,
+  cipher::AES_128_CTR,
+];
+
+/// Ciphers offered on CPUs without AES instructions, where constant-time software AES is slow.
+const SOFTWARE_CIPHER_ORDER: &[cipher::Name] = &[
+  cipher::CHACHA20_POLY1305,
+  cipher::AES_256_GCM,
+  cipher::AES_256_CTR,
+  cipher::AES_192_CTR,
+  cipher::AES_128_CTR,
+];
+
+fn has_aes_acceleration() -> bool {
+  #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+  {
+    std::arch::is_x86_feature_detected!("aes") && std::arch::is_x86_feature_detected!("pclmulqdq")
+  }
+  #[cfg(target_arch = "aarch64")]
+  {
+    std::arch::is_aarch64_feature_detected!("aes")
+      && std::arch::is_aarch64_feature_detected!("pmull")
+  }
+  #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+  {
+    false
+  }
+}
+
+fn default_cipher_order() -> &'static [cipher::Name] {
+  if has_aes_acceleration() {
+    HARDWARE_AES_CIPHER_ORDER
+  } else {
+    SOFTWARE_CIPHER_ORDER
+  }
+}
+
+/// Append the algorithms of old servers to `preferred`, keeping its order in front.
+fn widen_for_legacy_servers(preferred: &mut russh::Preferred) {
+  let mut kex = preferred.kex.to_vec();
+  // the extension markers are not key exchanges, keep them at the end
+  let markers = kex
+    .iter()
+    .position(|name| *name == russh::kex::EXTENSION_SUPPORT_AS_CLIENT)
+    .unwrap_or(kex.len());
+  kex.splice(
+    markers..markers,
+    [russh::kex::DH_G14_SHA1, russh::kex::DH_G1_SHA1],
+  );
+  preferred.kex = kex.into();
+  preferred.key = [&preferred.key[..], &[key::SSH_RSA]].concat().into();
+  preferred.cipher = [
+    &preferred.cipher[..],
+    &[
+      cipher::AES_256_CBC,
+      cipher::AES_192_CBC,
+      cipher::AES_128_CBC,
+      cipher::TRIPLE_DES_CBC,
+    ],
+  ]
+  .concat()
+  .into();
+  if !preferred.mac.contains(&russh::mac::HMAC_SHA1) {
+    preferred.mac = [&preferred.mac[..], &[russh::mac::HMAC_SHA1]]
+      .concat()
+      .into();
+  }
+}
+
+/// Spell out which algorithm category failed to negotiate and what both sides offered,
+/// rather than russh's bare "No common algorithm".
+fn describe_connect_error(err: anyhow::Error) -> Error {
+  let Some(russh::Error::NoCommonAlgo { kind, ours, theirs }) = err.downcast_ref() else {
+    return err.into();
+  };
+  let kind = match kind {
+    russh::AlgorithmKind::Kex => "key exchange",
+    russh::AlgorithmKind::Key => "host key",
+    russh::AlgorithmKind::Cipher => "cipher",
+    russh::AlgorithmKind::Mac => "MAC",
+    russh::AlgorithmKind::Compression => "compression",
+  };
+  let list = |names: &[String]| {
+    names
+      .iter()
+      .filter(|name| !name.starts_with("ext-info-") && !name.starts_with("kex-strict-"))
+      .map(String::as_str)
+      .collect::<Vec<_>>()
+      .join(",")
+  };
+  Error::new(
+    Status::GenericFailure,
+    format!(
+      "No common {kind} algorithm. The client offered {}; the server offered {}",
+      list(ours),
+      list(theirs)
+    ),
+  )
+}
+
+fn default_client_config() -> client::Config {
+  let mut config = client::Config::default();
+  config.preferred.cipher = Cow::Borrowed(default_cipher_order());
+  config
+}
+
+#[napi(object)]
+/// Whether this CPU accelerates the symmetric ciphers used by SSH.
+pub struct CipherAcceleration {
+  /// AES and carry-less multiplication instructions (AES-NI/PCLMULQDQ on x86, AES/PMULL on ARMv8) are available.
+  pub aes: bool,
+  /// The cipher preference order offered by default on this CPU.
+  pub ciphers: Vec<String>,
+}
+
+#[napi]
+/// Report whether AES is hardware accelerated here and which cipher order `connect` offers by default.
+pub fn cipher_acceleration() -> CipherAcceleration {
+  CipherAcceleration {
+    aes: has_aes_acceleration(),
+    ciphers: default_cipher_order()
+      .iter()
+      .map(|name| name.as_ref().to_owned())
+      .collect(),
   }
 }
 
@@ -108,25 +312,258 @@ pub struct Config {
   pub client: Option<ClientConfig>,
   pub check_server_key: Option<
     ThreadsafeFunction<
-      PublicKey,
+      FnArgs<(PublicKey, Option<SshfpStatus>)>,
       Either3<bool, Promise<bool>, UnknownReturnValue>,
-      PublicKey,
+      FnArgs<(PublicKey, Option<SshfpStatus>)>,
+      Status,
       false,
     >,
   >,
-  pub auth_banner: Option<ThreadsafeFunction<String, (), String, false>>,
+  /// How long to wait for `checkServerKey` to settle before failing the handshake. In milliseconds.
+  /// This only bounds the wait: russh runs the check inline, so the connection processes nothing
+  /// else, keepalives and timers included, until the callback settles or this expires.
+  pub check_server_key_timeout: Option<u32>,
+  /// Look the host key up in DNS. Without `checkServerKey`, only a `Matched` key is accepted;
+  /// with it, the outcome is passed as its second argument and the callback decides.
+  pub sshfp: Option<SshfpOptions>,
+  /// Check the host key against keys kept by the application. A known key is accepted and a
+  /// changed or revoked one rejected without asking `sshfp` or `checkServerKey`; those decide
+  /// about keys the store does not know, which are then passed to `store`.
+  pub host_key_store: Option<HostKeyStore>,
+  pub auth_banner: Option<ThreadsafeFunction<String, (), String, Status, false>>,
+  /// Called once the connection has terminated. Does not keep the Node.js process alive.
+  pub on_close: Option<ThreadsafeFunction<CloseInfo, (), CloseInfo, Status, false, true>>,
+  /// Abort the connection attempt, rejecting with an `AbortError`. Has no effect once `connect`
+  /// has resolved.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<Abort>,
+  /// The ssh-agent used by `authenticateAgent`: the path of its Unix socket or Windows named
+  /// pipe, or `false` to use none. By default the agent at `SSH_AUTH_SOCK` is used if it can
+  /// be reached, on Windows then the Windows OpenSSH agent or Pageant.
+  pub agent: Option<Either<bool, String>>,
+  /// Asked for the passphrase when `authenticateKeyPair` loads an encrypted key file, again
+  /// after a wrong one up to three attempts. Return `null` to give up. Does not keep the
+  /// Node.js process alive.
+  #[napi(ts_type = "(path: string, attempt: number) => string | null | Promise<string | null>")]
+  pub passphrase: Option<PassphraseCallback>,
+  /// How long to wait for the server to answer a password, key or `none` attempt. In
+  /// milliseconds. On expiry the attempt rejects and the connection is closed, as a late answer
+  /// would otherwise be taken for the answer to the next attempt.
+  pub auth_timeout: Option<u32>,
+  /// How often `authenticatePassword` and `authenticateKeyPair` repeat an attempt the server
+  /// rejected, for servers that turn users away while overloaded. Defaults to `0`. Every retry
+  /// counts against the server's `MaxAuthTries`, 6 by default in OpenSSH, which disconnects
+  /// once it is used up, and may get the address banned by tools like fail2ban, so keep it low.
+  pub auth_retries: Option<u32>,
+  /// How long to wait before the first retry, doubled before every further one. In
+  /// milliseconds. Defaults to `1000`.
+  pub auth_retry_delay: Option<u32>,
+}
+
+/// The limits `Config.authTimeout`, `authRetries` and `authRetryDelay` put on authentication.
+#[derive(Clone, Copy)]
+struct AuthPolicy {
+  timeout: Option<Duration>,
+  retries: u32,
+  retry_delay: Duration,
+}
+
+impl AuthPolicy {
+  fn new(config: Option<&Config>) -> Self {
+    Self {
+      timeout: config
+        .and_then(|c| c.auth_timeout)
+        .map(|timeout| Duration::from_millis(timeout as u64)),
+      retries: config.and_then(|c| c.auth_retries).unwrap_or(0),
+      retry_delay: Duration::from_millis(
+        config.and_then(|c| c.auth_retry_delay).unwrap_or(1000) as u64
+      ),
+    }
+  }
+}
+
+/// One authentication request, boxed so that it may borrow the handle it is sent on.
+type AuthAttempt<'a> =
+  Pin<Box<dyn std::future::Future<Output = std::result::Result<bool, russh::Error>> + Send + 'a>>;
+
+type PassphraseCallback = ThreadsafeFunction<
+  FnArgs<(String, u32)>,
+  Either3<Option<String>, Promise<Option<String>>, UnknownReturnValue>,
+  FnArgs<(String, u32)>,
+  Status,
+  false,
+  true,
+>;
+
+/// A password, or a function returning it.
+type Password = Either<String, PasswordCallback>;
+
+type PasswordCallback =
+  ThreadsafeFunction<(), Either<String, Promise<String>>, (), Status, false, true>;
+
+/// Call the function, if `password` is one, and wait for the password it returns.
+async fn resolve_password(password: Password) -> Result<String> {
+  match password {
+    Either::A(password) => Ok(password),
+    Either::B(callback) => match callback.call_async(()).await? {
+      Either::A(password) => Ok(password),
+      Either::B(password) => password.await,
+    },
+  }
+}
+
+/// Offer the agent identities in turn until one is accepted, handing the agent back on every
+/// path.
+async fn authenticate_with_agent(
+  handle: &mut client::Handle<ClientHandle>,
+  user: String,
+  mut agent: SshAgentClient,
+  max_attempts: usize,
+) -> (SshAgentClient, Result<Option<PublicKey>>) {
+  let identities = match agent.request_identities().await.into_error() {
+    Ok(identities) => identities,
+    Err(err) => return (agent, Err(err)),
+  };
+  for key in identities.into_iter().take(max_attempts) {
+    // boxed, as rustc otherwise fails to prove the future `Send` for every lifetime
+    let attempt: Pin<Box<dyn std::future::Future<Output = _> + Send + '_>> =
+      Box::pin(handle.authenticate_future(user.clone(), key.clone(), agent));
+    let (returned, accepted) = attempt.await;
+    agent = returned;
+    match accepted.into_error() {
+      Ok(false) => {}
+      Ok(true) => return (agent, Ok(Some(PublicKey::new(key)))),
+      Err(err) => return (agent, Err(err)),
+    }
+  }
+  (agent, Ok(None))
+}
+
+/// Sign with `options.hashAlg` if the key is an RSA key.
+fn with_hash_alg(mut key: key::KeyPair, options: Option<KeyAuthOptions>) -> key::KeyPair {
+  if let (key::KeyPair::RSA { hash, .. }, Some(hash_alg)) =
+    (&mut key, options.and_then(|options| options.hash_alg))
+  {
+    *hash = hash_alg.into();
+  }
+  key
+}
+
+/// The methods `authenticate` knows, in the order `ssh` tries them by default.
+const DEFAULT_AUTH_METHODS: [&str; 3] = ["publickey", "keyboard-interactive", "password"];
+
+/// The keys `authenticateDefaultKeys` tries, in the order `ssh` does.
+const DEFAULT_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// How often `Config.passphrase` is asked for one key, as `ssh` does.
+const PASSPHRASE_ATTEMPTS: u32 = 3;
+
+/// Load the private key at `path`, asking `passphrase` for the passphrase if it is encrypted.
+async fn load_key(path: PathBuf, passphrase: Option<&PassphraseCallback>) -> Result<key::KeyPair> {
+  let describe = |err: russh_keys::Error| Error::new(Status::GenericFailure, format!("{err}"));
+  let loaded = load_secret_key(&path, None);
+  let (Some(passphrase), Err(russh_keys::Error::KeyIsEncrypted)) = (passphrase, &loaded) else {
+    return loaded.map_err(describe);
+  };
+  let mut err = russh_keys::Error::KeyIsEncrypted;
+  for attempt in 1..=PASSPHRASE_ATTEMPTS {
+    let answer = match passphrase
+      .call_async(FnArgs::from((path.to_string_lossy().into_owned(), attempt)))
+      .await?
+    {
+      Either3::A(answer) => answer,
+      Either3::B(answer) => answer.await?,
+      Either3::C(_) => None,
+    };
+    let Some(answer) = answer else {
+      break;
+    };
+    match load_secret_key(&path, Some(&answer)) {
+      Ok(key) => return Ok(key),
+      Err(wrong) => err = wrong,
+    }
+  }
+  Err(describe(err))
+}
+
+#[napi(object)]
+#[derive(Clone)]
+/// Why a connection terminated.
+pub struct CloseInfo {
+  /// Whether `disconnect` was called on this side.
+  pub local: bool,
+  /// The reason sent with the SSH disconnect message, if there was one.
+  pub reason: Option<DisconnectReason>,
+  /// The disconnect message, or the error that ended the connection.
+  pub message: Option<String>,
 }
 
 pub struct ClientHandle {
   check_server_key: Option<
     ThreadsafeFunction<
-      PublicKey,
+      FnArgs<(PublicKey, Option<SshfpStatus>)>,
       Either3<bool, Promise<bool>, UnknownReturnValue>,
-      PublicKey,
+      FnArgs<(PublicKey, Option<SshfpStatus>)>,
+      Status,
       false,
     >,
   >,
-  auth_banner: Option<ThreadsafeFunction<String, (), String, false>>,
+  check_server_key_timeout: Option<Duration>,
+  sshfp: Option<Sshfp>,
+  host_key_store: Option<HostKeyStore>,
+  host: String,
+  port: u16,
+  auth_banner: Arc<AuthBanner>,
+  on_close: Option<ThreadsafeFunction<CloseInfo, (), CloseInfo, Status, false, true>>,
+  /// The reason and message passed to `Client.disconnect`, if it was called.
+  local_disconnect: Arc<std::sync::Mutex<Option<(DisconnectReason, String)>>>,
+  closed: watch::Sender<Option<CloseInfo>>,
+  flow: Arc<FlowControl>,
+}
+
+impl ClientHandle {
+  /// Ask `sshfp` and `checkServerKey` about a key the host key store does not know.
+  async fn ask_server_key(
+    &mut self,
+    server_public_key: &key::PublicKey,
+    has_store: bool,
+  ) -> anyhow::Result<bool> {
+    let sshfp = match &self.sshfp {
+      Some(sshfp) => Some(sshfp.verify(server_public_key).await),
+      None => None,
+    };
+    if let Some(check) = self.check_server_key.take() {
+      let server_public_key = PublicKey::new(server_public_key.clone());
+      let verify = async move {
+        let check_result = check
+          .call_async(FnArgs::from((server_public_key, sshfp)))
+          .await?;
+        match check_result {
+          Either3::A(a) => Ok::<bool, anyhow::Error>(a),
+          Either3::B(b) => {
+            let result = b.await?;
+            Ok(result)
+          }
+          Either3::C(_) => Ok(false),
+        }
+      };
+      // the session driver awaits this inline during key exchange and russh offers no way to
+      // answer later, so its timers stay parked until the callback settles; only the wait is
+      // bounded, so a slow callback can not outlive the server's login grace time
+      match self.check_server_key_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, verify)
+          .await
+          .map_err(|_| anyhow::anyhow!("host key verification callback timed out"))?,
+        None => verify.await,
+      }
+    } else {
+      // with only a store, an unknown key needs `trustOnFirstUse`
+      Ok(match sshfp {
+        Some(status) => status == SshfpStatus::Matched,
+        None => !has_store,
+      })
+    }
+  }
 }
 
 #[async_trait]
@@ -138,9 +575,7 @@ impl russh::client::Handler for ClientHandle {
     banner: &str,
     _session: &mut Session,
   ) -> std::result::Result<(), Self::Error> {
-    if let Some(on_auth_banner) = self.auth_banner.take() {
-      on_auth_banner.call(banner.to_owned(), ThreadsafeFunctionCallMode::NonBlocking);
-    };
+    self.auth_banner.receive(banner);
     Ok(())
   }
 
@@ -148,204 +583,2161 @@ impl russh::client::Handler for ClientHandle {
     &mut self,
     server_public_key: &key::PublicKey,
   ) -> std::result::Result<bool, Self::Error> {
-    // if `auth_banner` isn't called, drop the callback
-    // or it will prevent the Node.js process to exit before GC
-    if self.auth_banner.is_some() {
-      drop(self.auth_banner.take());
-    }
-    if let Some(check) = &self.check_server_key {
-      let check_result = check
-        .call_async(PublicKey::new(server_public_key.clone()))
-        .await?;
-      std::mem::drop(self.check_server_key.take());
-      match check_result {
-        Either3::A(a) => Ok(a),
-        Either3::B(b) => {
-          let result = b.await?;
-          Ok(result)
-        }
-        Either3::C(_) => Ok(false),
+    let known = match &self.host_key_store {
+      Some(store) => Some(
+        store
+          .verify(&self.host, self.port, server_public_key)
+          .await?,
+      ),
+      None => None,
+    };
+    match known {
+      Some(HostKeyStatus::Known) => return Ok(true),
+      Some(HostKeyStatus::Changed) => {
+        anyhow::bail!("The host key of {}:{} has changed", self.host, self.port)
       }
+      Some(HostKeyStatus::Revoked) => {
+        anyhow::bail!(
+          "The host key of {}:{} has been revoked",
+          self.host,
+          self.port
+        )
+      }
+      Some(HostKeyStatus::Unknown) | None => {}
+    }
+    let trusted = self
+      .host_key_store
+      .as_ref()
+      .is_some_and(|store| store.trust_on_first_use.unwrap_or(false));
+    let accepted = if trusted {
+      true
     } else {
-      Ok(true)
+      self
+        .ask_server_key(server_public_key, known.is_some())
+        .await?
+    };
+    if accepted {
+      if let Some(store) = &self.host_key_store {
+        store
+          .learn(&self.host, self.port, server_public_key)
+          .await?;
+      }
+    }
+    Ok(accepted)
+  }
+
+  async fn channel_open_confirmation(
+    &mut self,
+    id: ChannelId,
+    max_packet_size: u32,
+    window_size: u32,
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    self.flow.update(id, |stats| {
+      stats.max_packet_size = max_packet_size;
+      stats.outbound_window = window_size;
+    });
+    Ok(())
+  }
+
+  async fn window_adjusted(
+    &mut self,
+    channel: ChannelId,
+    new_size: u32,
+    session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    // russh has flushed the data queued for the window by now, so `new_size` is what is left
+    self.flow.update(channel, |stats| {
+      stats.outbound_window = new_size;
+      stats.waiting_for_window = session.has_pending_data(channel);
+      stats.window_adjusts += 1;
+    });
+    Ok(())
+  }
+
+  async fn channel_close(
+    &mut self,
+    channel: ChannelId,
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    self.flow.remove(channel);
+    Ok(())
+  }
+
+  async fn disconnected(
+    &mut self,
+    reason: client::DisconnectReason<Self::Error>,
+  ) -> std::result::Result<(), Self::Error> {
+    let local = self
+      .local_disconnect
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner)
+      .take();
+    let (info, result) = match (reason, local) {
+      (client::DisconnectReason::ReceivedDisconnect(info), _) => (
+        CloseInfo {
+          local: false,
+          reason: Some(info.reason_code.into()),
+          message: Some(info.message),
+        },
+        Ok(()),
+      ),
+      // a local disconnect ends the session loop the same way a dropped socket does
+      (client::DisconnectReason::Error(err), Some((reason, message))) => (
+        CloseInfo {
+          local: true,
+          reason: Some(reason),
+          message: Some(message),
+        },
+        Err(err),
+      ),
+      (client::DisconnectReason::Error(err), None) => (
+        CloseInfo {
+          local: false,
+          reason: None,
+          message: Some(err.to_string()),
+        },
+        Err(err),
+      ),
+    };
+    self.auth_banner.settle();
+    if let Some(on_close) = self.on_close.take() {
+      on_close.call(info.clone(), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+    self.closed.send_replace(Some(info));
+    result
+  }
+}
+
+/// The pre-auth banner, shared between the session handler and `Client`.
+/// Servers send it before answering the first authentication request, so it is settled once
+/// an authentication attempt completes.
+struct AuthBanner {
+  callback: std::sync::Mutex<Option<ThreadsafeFunction<String, (), String, Status, false>>>,
+  /// `None` until settled, then the banner if one arrived.
+  banner: watch::Sender<Option<Option<String>>>,
+}
+
+impl AuthBanner {
+  fn new(callback: Option<ThreadsafeFunction<String, (), String, Status, false>>) -> Self {
+    Self {
+      callback: std::sync::Mutex::new(callback),
+      banner: watch::channel(None).0,
+    }
+  }
+
+  fn receive(&self, banner: &str) {
+    if let Some(callback) = self
+      .callback
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner)
+      .as_ref()
+    {
+      callback.call(banner.to_owned(), ThreadsafeFunctionCallMode::NonBlocking);
     }
+    self.banner.send_if_modified(|settled| {
+      let unsettled = settled.is_none();
+      if unsettled {
+        *settled = Some(Some(banner.to_owned()));
+      }
+      unsettled
+    });
+  }
+
+  fn settle(&self) {
+    // drop the callback, or it will prevent the Node.js process from exiting before GC
+    drop(
+      self
+        .callback
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .take(),
+    );
+    self.banner.send_if_modified(|settled| {
+      let unsettled = settled.is_none();
+      if unsettled {
+        *settled = Some(None);
+      }
+      unsettled
+    });
   }
 }
 
-#[cfg(unix)]
-type SshAgentClient = AgentClient<SshAgentStream>;
-#[cfg(windows)]
-type SshAgentClient = AgentClient<pageant::PageantStream>;
+/// The handle of a connection, locked exclusively only to authenticate.
+pub(crate) type SharedHandle = Arc<RwLock<client::Handle<ClientHandle>>>;
 
 #[napi]
 pub struct Client {
-  handle: client::Handle<ClientHandle>,
-  _agent: SshAgentClient,
+  /// Shared with the SFTP sessions, which open channels of their own.
+  handle: SharedHandle,
+  /// `None` without an agent, and while `authenticateAgent` signs with it. Only locked to make
+  /// the client `Sync`, all uses go through `&mut self`.
+  agent: std::sync::Mutex<Option<SshAgentClient>>,
+  /// Milliseconds since the Unix epoch of the last read or write on the socket.
+  last_activity: Arc<AtomicU64>,
+  auth_banner: Arc<AuthBanner>,
+  local_disconnect: Arc<std::sync::Mutex<Option<(DisconnectReason, String)>>>,
+  closed: watch::Receiver<Option<CloseInfo>>,
+  flow: Arc<FlowControl>,
+  default_user: Option<String>,
+  passphrase: Option<PassphraseCallback>,
+  auth_policy: AuthPolicy,
+}
+
+/// Passes the socket through to russh, recording when bytes last moved in either direction.
+struct ActivityStream {
+  inner: TcpStream,
+  last_activity: Arc<AtomicU64>,
+}
+
+impl ActivityStream {
+  fn touch<T>(&self, poll: &Poll<io::Result<T>>, transferred: bool) {
+    if matches!(poll, Poll::Ready(Ok(_))) && transferred {
+      self.last_activity.store(now_millis(), Ordering::Relaxed);
+    }
+  }
+}
+
+impl AsyncRead for ActivityStream {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let filled = buf.filled().len();
+    let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+    self.touch(&poll, buf.filled().len() > filled);
+    poll
+  }
+}
+
+impl AsyncWrite for ActivityStream {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+    self.touch(
+      &poll,
+      matches!(poll, Poll::Ready(Ok(written)) if written > 0),
+    );
+    poll
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_shutdown(cx)
+  }
+}
+
+fn now_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as u64)
+    .unwrap_or_default()
+}
+
+#[napi(object)]
+pub struct IsAliveOptions {
+  /// How long to wait for the server to answer. In milliseconds. Defaults to `5000`.
+  pub timeout_ms: Option<u32>,
 }
 
 #[napi]
+/// Connect to `addr`, either `host:port` or an `ssh://[user@]host[:port]` URL.
 pub async fn connect(addr: String, mut config: Option<Config>) -> Result<Client> {
-  let client_config: client::Config = config
+  let (addr, default_user) = match parse_ssh_url(&addr)? {
+    Some((addr, user)) => (addr, user),
+    None => (addr, None),
+  };
+  let client_config = config
     .as_mut()
     .and_then(|c| c.client.take())
-    .map(|c| c.into())
-    .unwrap_or_default();
+    .map(client::Config::try_from)
+    .transpose()?
+    .unwrap_or_else(default_client_config);
+  let (host, port) = split_host_port(&addr);
   let check_server_key = config.as_mut().and_then(|c| c.check_server_key.take());
-  let auth_banner = config.as_mut().and_then(|c| c.auth_banner.take());
-  #[cfg(unix)]
-  let agent = AgentClient::connect_env().await.into_error()?;
-  #[cfg(windows)]
-  let agent = AgentClient::connect_pageant().await;
-  let handle = client::connect(
-    Arc::new(client_config),
-    addr,
-    ClientHandle {
-      check_server_key,
-      auth_banner,
-    },
+  let check_server_key_timeout = config
+    .as_ref()
+    .and_then(|c| c.check_server_key_timeout)
+    .map(|timeout| Duration::from_millis(timeout as u64));
+  let sshfp = config
+    .as_mut()
+    .and_then(|c| c.sshfp.take())
+    .map(|options| Sshfp::new(options, &host));
+  let host_key_store = config.as_mut().and_then(|c| c.host_key_store.take());
+  let auth_banner = Arc::new(AuthBanner::new(
+    config.as_mut().and_then(|c| c.auth_banner.take()),
+  ));
+  let on_close = config.as_mut().and_then(|c| c.on_close.take());
+  let passphrase = config.as_mut().and_then(|c| c.passphrase.take());
+  let auth_policy = AuthPolicy::new(config.as_ref());
+  let mut abort = config.as_mut().and_then(|c| c.signal.take());
+  if abort.as_ref().is_some_and(Abort::is_aborted) {
+    return Err(aborted());
+  }
+  let local_disconnect = Arc::new(std::sync::Mutex::new(None));
+  let (closed, closed_rx) = watch::channel(None);
+  let flow = Arc::new(FlowControl::default());
+  let agent = match config.as_mut().and_then(|c| c.agent.take()) {
+    Some(Either::A(false)) => None,
+    Some(Either::B(path)) => Some(connect_agent_at(&path).await?),
+    Some(Either::A(true)) | None => connect_default_agent().await.ok(),
+  };
+  let last_activity = Arc::new(AtomicU64::new(now_millis()));
+  let connecting = async {
+    let socket = TcpStream::connect(addr).await?;
+    client::connect_stream(
+      Arc::new(client_config),
+      ActivityStream {
+        inner: socket,
+        last_activity: last_activity.clone(),
+      },
+      ClientHandle {
+        check_server_key,
+        check_server_key_timeout,
+        sshfp,
+        host_key_store,
+        host,
+        port,
+        auth_banner: auth_banner.clone(),
+        on_close,
+        local_disconnect: local_disconnect.clone(),
+        closed,
+        flow: flow.clone(),
+      },
+    )
+    .await
+    .map_err(describe_connect_error)
+  };
+  // dropping the handshake future drops the socket, which ends the attempt
+  let handle = tokio::select! {
+    handle = connecting => handle?,
+    _ = Abort::wait_for(&mut abort) => return Err(aborted()),
+  };
+  Ok(Client {
+    handle: Arc::new(RwLock::new(handle)),
+    agent: std::sync::Mutex::new(agent),
+    last_activity,
+    auth_banner,
+    local_disconnect,
+    closed: closed_rx,
+    flow,
+    default_user,
+    passphrase,
+    auth_policy,
+  })
+}
+
+/// Split a `host:port` address, without the brackets around an IPv6 host. The port defaults
+/// to 22.
+fn split_host_port(addr: &str) -> (String, u16) {
+  let (host, port) = match addr.rsplit_once(':') {
+    // a bare IPv6 address has no port
+    Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+      (host, port.parse().unwrap_or(22))
+    }
+    _ => (addr, 22),
+  };
+  (
+    host
+      .trim_start_matches('[')
+      .trim_end_matches(']')
+      .to_owned(),
+    port,
   )
-  .await?;
-  Ok(Client::new(handle, agent))
+}
+
+/// Split an `ssh://` URL into a `host:port` address and the user, if any.
+/// Returns `None` for anything that is not a URL.
+fn parse_ssh_url(url: &str) -> Result<Option<(String, Option<String>)>> {
+  let invalid =
+    |reason: &str| Error::new(Status::InvalidArg, format!("Invalid URL {url}: {reason}"));
+  let Some((scheme, rest)) = url.split_once("://") else {
+    return Ok(None);
+  };
+  if !scheme.eq_ignore_ascii_case("ssh") {
+    return Err(Error::new(
+      Status::InvalidArg,
+      format!("Unsupported URL scheme {scheme}://, only ssh:// URLs can be connected to"),
+    ));
+  }
+  let authority = match rest.split_once('/') {
+    Some((authority, "")) => authority,
+    Some(_) => return Err(invalid("a path is not allowed")),
+    None => rest,
+  };
+  let (user, host_port) = match authority.rsplit_once('@') {
+    // connection parameters after `;` are defined by the ssh URI draft but not supported
+    Some((userinfo, host_port)) => {
+      let user = userinfo.split(';').next().unwrap_or_default();
+      if user.contains(':') {
+        return Err(invalid("a password is not allowed"));
+      }
+      (
+        Some(percent_decode(user).ok_or_else(|| invalid("bad percent-encoding"))?),
+        host_port,
+      )
+    }
+    None => (None, authority),
+  };
+  let (host, port) = match host_port.strip_prefix('[') {
+    Some(bracketed) => {
+      let (host, port) = bracketed
+        .split_once(']')
+        .ok_or_else(|| invalid("unclosed `[`"))?;
+      match port {
+        "" => (host, None),
+        port => (
+          host,
+          Some(port.strip_prefix(':').ok_or_else(|| invalid("bad port"))?),
+        ),
+      }
+    }
+    None => match host_port.split_once(':') {
+      Some((host, port)) => (host, Some(port)),
+      None => (host_port, None),
+    },
+  };
+  if host.is_empty() {
+    return Err(invalid("the host is missing"));
+  }
+  let port = match port {
+    None | Some("") => 22,
+    Some(port) => port.parse::<u16>().map_err(|_| invalid("bad port"))?,
+  };
+  let addr = if host.contains(':') {
+    format!("[{host}]:{port}")
+  } else {
+    format!("{host}:{port}")
+  };
+  Ok(Some((addr, user.filter(|user| !user.is_empty()))))
+}
+
+fn percent_decode(input: &str) -> Option<String> {
+  let mut bytes = input.bytes();
+  let mut decoded = Vec::with_capacity(input.len());
+  while let Some(byte) = bytes.next() {
+    if byte == b'%' {
+      let hex = [bytes.next()?, bytes.next()?];
+      decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    } else {
+      decoded.push(byte);
+    }
+  }
+  String::from_utf8(decoded).ok()
 }
 
 #[napi]
 impl Client {
-  pub fn new(handle: client::Handle<ClientHandle>, agent: SshAgentClient) -> Self {
-    Self {
-      handle,
-      _agent: agent,
-    }
+  #[napi(getter)]
+  /// The user from the `ssh://` URL passed to `connect`, if there was one.
+  pub fn default_user(&self) -> Option<String> {
+    self.default_user.clone()
   }
 
   #[napi]
   pub fn is_closed(&self) -> bool {
-    self.handle.is_closed()
+    // only authentication holds the handle exclusively, and it ends when the connection does
+    self
+      .handle
+      .try_read()
+      .is_ok_and(|handle| handle.is_closed())
+  }
+
+  #[napi(ts_return_type = "Promise<CloseInfo>")]
+  /// Resolve once the connection has terminated, immediately if it already has.
+  /// The promise does not keep the Node.js process alive.
+  pub fn wait_closed<'env>(&self, env: &'env Env) -> Result<Unknown<'env>> {
+    let closed = std::sync::Mutex::new(Some(self.closed.clone()));
+    let executor = env.create_function_from_closure("waitClosed", move |ctx| {
+      let resolve = ctx.get::<Function<CloseInfo, ()>>(0)?;
+      // a weak threadsafe function, unlike a napi async fn, does not hold the event loop open
+      let resolve = resolve
+        .build_threadsafe_function::<CloseInfo>()
+        .weak::<true>()
+        .build()?;
+      if let Some(mut closed) = closed
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .take()
+      {
+        spawn(async move {
+          let info = match closed.wait_for(Option::is_some).await {
+            Ok(info) => info.clone(),
+            Err(_) => None,
+          };
+          resolve.call(
+            info.unwrap_or(CloseInfo {
+              local: false,
+              reason: None,
+              message: None,
+            }),
+            ThreadsafeFunctionCallMode::NonBlocking,
+          );
+        });
+      }
+      Ok(())
+    })?;
+    env
+      .get_global()?
+      .get_named_property::<Function<Function<CloseInfo, ()>, Unknown>>("Promise")?
+      .new_instance(executor)
+  }
+
+  #[napi(ts_return_type = "Promise<string | null>")]
+  /// The banner the server sent before authentication, or `null` if the first authentication
+  /// attempt completed without one.
+  pub fn auth_banner<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, Option<String>>> {
+    let mut receiver = self.auth_banner.banner.subscribe();
+    env.spawn_future(async move {
+      let banner = match receiver.wait_for(Option::is_some).await {
+        Ok(banner) => banner.clone().flatten(),
+        Err(_) => None,
+      };
+      Ok(banner)
+    })
+  }
+
+  #[napi]
+  /// When a packet was last sent or received.
+  pub fn last_activity<'env>(&self, env: &'env Env) -> Result<JsDate<'env>> {
+    env.create_date(self.last_activity.load(Ordering::Relaxed) as f64)
+  }
+
+  #[napi]
+  /// Check that the server still answers, without opening a channel.
+  /// Resolves to `false` instead of throwing when the connection is gone or the server is silent.
+  pub async fn is_alive(&self, options: Option<IsAliveOptions>) -> bool {
+    let timeout = Duration::from_millis(
+      options
+        .and_then(|options| options.timeout_ms)
+        .unwrap_or(5000) as u64,
+    );
+    // cancelling a forwarding that was never requested is a global request every server
+    // answers, usually with a failure, and has no side effects. Waiting for the handle counts
+    // against the timeout, as an authentication holds it for as long as it takes.
+    let probe = async {
+      let handle = self.handle.read().await;
+      handle
+        .cancel_tcpip_forward("napi-rs-ssh-keepalive.invalid", 0)
+        .await
+    };
+    matches!(
+      tokio::time::timeout(timeout, probe).await,
+      Ok(Ok(()) | Err(russh::Error::RequestDenied))
+    )
   }
 
   #[napi]
   /// # Safety
   ///
   /// close can not be called concurrently.
+  ///
+  /// `password` may be a function, called only once the password is about to be sent, e.g. to
+  /// fetch it from a credential store.
   pub async unsafe fn authenticate_password(
     &mut self,
     user: String,
-    password: String,
+    #[napi(ts_arg_type = "string | (() => string | Promise<string>)")] password: Password,
   ) -> Result<bool> {
-    self
-      .handle
-      .authenticate_password(user, password)
-      .await
-      .into_error()
+    let password = resolve_password(password).await?;
+    let result = self
+      .attempt_auth_retrying(|handle| {
+        Box::pin(handle.authenticate_password(user.clone(), password.clone()))
+      })
+      .await;
+    self.auth_banner.settle();
+    result
+  }
+
+  #[napi]
+  /// # Safety
+  ///
+  /// Attempt the `none` authentication method, which servers accept for accounts that need no
+  /// credentials. The methods the server lists on failure are not exposed by russh, so they can
+  /// not be returned.
+  pub async unsafe fn authenticate_none(&mut self, user: String) -> Result<bool> {
+    let result = self
+      .attempt_auth(|handle| Box::pin(handle.authenticate_none(user)))
+      .await;
+    self.auth_banner.settle();
+    result
   }
 
   #[napi]
   /// # Safety
   ///
   /// Perform public key-based SSH authentication.
-  /// The key can be omitted to use the default private key.
-  /// The key can be a path to a private key file.
+  /// The key can be omitted to try the default keys, as `authenticateDefaultKeys` does.
+  /// The key can be a path to a private key file, which `Config.passphrase` is asked to
+  /// decrypt if needed.
   pub async unsafe fn authenticate_key_pair(
     &mut self,
     user: String,
     key: Either3<String, &KeyPair, Undefined>,
+    options: Option<KeyAuthOptions>,
   ) -> Result<bool> {
     let keypair = match key {
-      Either3::A(path) => load_secret_key(path, None)
-        .map_err(|err| Error::new(Status::GenericFailure, format!("{err}")))?,
+      Either3::A(path) => load_key(path.into(), self.passphrase.as_ref()).await?,
       Either3::B(keypair) => keypair.inner.clone(),
       Either3::C(_) => {
-        let path = {
-          dirs::home_dir()
-            .ok_or_else(|| {
-              Error::new(Status::GenericFailure, "No home directory found".to_owned())
-            })?
-            .join({
-              #[cfg(windows)]
-              {
-                "ssh"
-              }
-              #[cfg(not(windows))]
-              {
-                ".ssh"
-              }
-            })
-            .join("id_rsa")
-        };
-        load_secret_key(path, None)
-          .map_err(|err| Error::new(Status::GenericFailure, format!("{err}")))?
+        return Ok(
+          self
+            .authenticate_default_keys(user, options)
+            .await?
+            .is_some(),
+        )
       }
     };
-    self
-      .handle
-      .authenticate_publickey(user, Arc::new(keypair))
-      .await
-      .into_error()
+    let keypair = Arc::new(with_hash_alg(keypair, options));
+    let result = self
+      .attempt_auth_retrying(|handle| {
+        Box::pin(handle.authenticate_publickey(user.clone(), keypair.clone()))
+      })
+      .await;
+    self.auth_banner.settle();
+    result
   }
 
   #[napi]
   /// # Safety
   ///
-  /// exec can not be called concurrently.
-  /// The caller in Node.js must ensure that.
-  pub async unsafe fn exec(&mut self, command: String) -> Result<ExecOutput> {
-    let mut channel = self.handle.channel_open_session().await.into_error()?;
-    channel.exec(true, command).await.into_error()?;
-    let mut output = Vec::new();
-    let mut status = 0;
-    while let Some(msg) = channel.wait().await {
-      match msg {
-        russh::ChannelMsg::Data { ref data } => {
-          output.write_all(data).await?;
+  /// Perform public key-based SSH authentication with the keys `ssh` uses by default,
+  /// `id_ed25519`, `id_ecdsa` and `id_rsa` in `~/.ssh`, skipping missing ones and, like `ssh`,
+  /// ones that can not be loaded or decrypted. A key with an `-cert.pub` certificate next to it
+  /// is offered with the certificate first.
+  /// Resolves with the path of the accepted key, or `null` if none was.
+  pub async unsafe fn authenticate_default_keys(
+    &mut self,
+    user: String,
+    options: Option<KeyAuthOptions>,
+  ) -> Result<Option<String>> {
+    let dir = dirs::home_dir()
+      .ok_or_else(|| Error::new(Status::GenericFailure, "No home directory found".to_owned()))?
+      .join({
+        #[cfg(windows)]
+        {
+          "ssh"
         }
-        russh::ChannelMsg::ExitStatus { exit_status } => {
-          status = exit_status;
+        #[cfg(not(windows))]
+        {
+          ".ssh"
+        }
+      });
+    let paths = DEFAULT_KEYS
+      .iter()
+      .map(|name| dir.join(name))
+      .filter(|path| path.exists())
+      .collect::<Vec<_>>();
+    if paths.is_empty() {
+      return Err(Error::new(
+        Status::GenericFailure,
+        format!(
+          "None of the default keys {} exist in {}",
+          DEFAULT_KEYS.join(", "),
+          dir.display()
+        ),
+      ));
+    }
+    let result = async {
+      let mut unloadable = Vec::new();
+      let mut offered = false;
+      for path in paths {
+        let key = match load_key(path.clone(), self.passphrase.as_ref()).await {
+          Ok(key) => key,
+          Err(err) => {
+            unloadable.push(format!("{}: {}", path.display(), err.reason));
+            continue;
+          }
+        };
+        offered = true;
+        let key = Arc::new(with_hash_alg(key, options));
+        let mut cert_path = path.clone().into_os_string();
+        cert_path.push("-cert.pub");
+        if let Ok(cert) = russh_keys::load_openssh_certificate(&cert_path) {
+          if self
+            .attempt_auth(|handle| {
+              Box::pin(handle.authenticate_openssh_cert(user.clone(), key.clone(), cert))
+            })
+            .await?
+          {
+            return Ok(Some(path.to_string_lossy().into_owned()));
+          }
+        }
+        if self
+          .attempt_auth(|handle| Box::pin(handle.authenticate_publickey(user.clone(), key)))
+          .await?
+        {
+          return Ok(Some(path.to_string_lossy().into_owned()));
         }
-        _ => {}
       }
+      if !offered {
+        return Err(Error::new(
+          Status::GenericFailure,
+          format!(
+            "None of the default keys could be loaded: {}",
+            unloadable.join(", ")
+          ),
+        ));
+      }
+      Ok(None)
     }
-    Ok(ExecOutput {
-      status,
-      output: output.into(),
-    })
+    .await;
+    self.auth_banner.settle();
+    result
   }
 
   #[napi]
-  pub async fn disconnect(
-    &self,
-    reason: DisconnectReason,
-    description: String,
-    language_tag: String,
-  ) -> Result<()> {
-    self
-      .handle
-      .disconnect(reason.into(), &description, &language_tag)
-      .await
-      .map_err(|err| Error::new(Status::GenericFailure, format!("Disconnect failed: {err}")))?;
-    Ok(())
+  /// # Safety
+  ///
+  /// Perform public key-based SSH authentication with the identities of the ssh-agent, in the
+  /// order the agent lists them, until one is accepted. Resolves with the accepted key, or
+  /// `null` if none was.
+  pub async unsafe fn authenticate_agent(
+    &mut self,
+    user: String,
+    options: Option<AgentAuthOptions>,
+  ) -> Result<Option<PublicKey>> {
+    let max_attempts = options
+      .and_then(|options| options.max_attempts)
+      .map_or(usize::MAX, |max| max as usize);
+    let agent_slot = self
+      .agent
+      .get_mut()
+      .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let agent = agent_slot.take().ok_or_else(|| {
+      Error::new(
+        Status::GenericFailure,
+        "No ssh-agent is connected".to_owned(),
+      )
+    })?;
+    let (agent, result) =
+      authenticate_with_agent(&mut *self.handle.write().await, user, agent, max_attempts).await;
+    *self
+      .agent
+      .get_mut()
+      .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(agent);
+    self.auth_banner.settle();
+    result
   }
-}
 
-/// A reason for disconnection.
-#[napi]
-pub enum DisconnectReason {
-  HostNotAllowedToConnect = 1,
-  ProtocolError = 2,
-  KeyExchangeFailed = 3,
-  #[doc(hidden)]
-  Reserved = 4,
-  MACError = 5,
-  CompressionError = 6,
-  ServiceNotAvailable = 7,
-  ProtocolVersionNotSupported = 8,
-  HostKeyNotVerifiable = 9,
-  ConnectionLost = 10,
-  ByApplication = 11,
-  TooManyConnections = 12,
-  AuthCancelledByUser = 13,
-  NoMoreAuthMethodsAvailable = 14,
+  #[napi]
+  /// # Safety
+  ///
+  /// Try the credentials in `options` method by method, like `ssh` does, until the server
+  /// lets the user in. Servers that need several methods, e.g. a key and then a one-time
+  /// password, reject all but the last, so a rejected method does not stop the sequence.
+  /// Resolves with the method that completed the authentication, or `null`.
+  pub async unsafe fn authenticate(
+    &mut self,
+    options: AuthenticateOptions,
+  ) -> Result<Option<String>> {
+    let user = options
+      .user
+      .or_else(|| self.default_user.clone())
+      .ok_or_else(|| {
+        Error::new(
+          Status::InvalidArg,
+          "No user given and the address has none".to_owned(),
+        )
+      })?;
+    let methods = match options.methods {
+      Some(methods) => methods,
+      None => DEFAULT_AUTH_METHODS.map(str::to_owned).to_vec(),
+    };
+    if let Some(unknown) = methods
+      .iter()
+      .find(|method| !DEFAULT_AUTH_METHODS.contains(&method.as_str()))
+    {
+      return Err(Error::new(
+        Status::InvalidArg,
+        format!("Unknown authentication method {unknown}"),
+      ));
+    }
+    let mut key = options.key;
+    let mut agent = options.agent.unwrap_or(false);
+    let mut interactive = options.keyboard_interactive;
+    let mut password = options.password;
+    let result = async {
+      for method in &methods {
+        let accepted = match method.as_str() {
+          "publickey" => {
+            let mut accepted = false;
+            if let Some(key) = key.take() {
+              let key = match key {
+                AuthKey::Path(path) => load_key(path.into(), self.passphrase.as_ref()).await?,
+                AuthKey::KeyPair(key) => *key,
+              };
+              accepted = self
+                .attempt_auth(|handle| {
+                  Box::pin(handle.authenticate_publickey(user.clone(), Arc::new(key)))
+                })
+                .await?;
+            }
+            if !accepted && std::mem::take(&mut agent) {
+              let slot = self
+                .agent
+                .get_mut()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+              if let Some(client) = slot.take() {
+                let (client, result) = authenticate_with_agent(
+                  &mut *self.handle.write().await,
+                  user.clone(),
+                  client,
+                  usize::MAX,
+                )
+                .await;
+                *self
+                  .agent
+                  .get_mut()
+                  .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(client);
+                accepted = result?.is_some();
+              }
+            }
+            accepted
+          }
+          "keyboard-interactive" => match interactive.take() {
+            Some(options) => {
+              keyboard_interactive(&mut *self.handle.write().await, user.clone(), options).await?
+            }
+            None => false,
+          },
+          _ => match password.take() {
+            Some(password) => {
+              let password = resolve_password(password).await?;
+              self
+                .attempt_auth(|handle| {
+                  Box::pin(handle.authenticate_password(user.clone(), password))
+                })
+                .await?
+            }
+            None => false,
+          },
+        };
+        if accepted {
+          return Ok(Some(method.clone()));
+        }
+      }
+      Ok(None)
+    }
+    .await;
+    self.auth_banner.settle();
+    result
+  }
+
+  #[napi]
+  /// # Safety
+  ///
+  /// Perform keyboard-interactive SSH authentication, answering the server's prompts from
+  /// `options`.
+  pub async unsafe fn authenticate_keyboard_interactive(
+    &mut self,
+    user: String,
+    options: KeyboardInteractiveOptions,
+  ) -> Result<bool> {
+    let result = keyboard_interactive(&mut *self.handle.write().await, user, options).await;
+    self.auth_banner.settle();
+    result
+  }
+
+  #[napi(ts_return_type = "Promise<ExecOutput>")]
+  /// Run `command` on its own session channel and collect its output. Calls may overlap, every
+  /// command runs on a separate channel of the one connection.
+  ///
+  /// `command` can be an argv array, in which case every element is quoted for the remote
+  /// shell (POSIX `sh` unless `options.shell` says otherwise) and the results are joined.
+  pub async fn exec(
+    &self,
+    command: Either<String, Vec<String>>,
+    options: Option<ExecOptions>,
+  ) -> Result<CheckedOutput> {
+    let options = options.unwrap_or(ExecOptions {
+      shell: None,
+      idle_timeout_ms: None,
+      stdin: None,
+      signal: None,
+      env: None,
+      max_output_bytes: None,
+      close_on_truncate: None,
+      throw_on_non_zero: None,
+    });
+    let command = match command {
+      Either::A(command) => command,
+      Either::B(argv) => quote_argv(&argv, options.shell.unwrap_or(ShellDialect::Posix))?,
+    };
+    if options.signal.as_ref().is_some_and(Abort::is_aborted) {
+      return Err(aborted());
+    }
+    let channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    for (name, value) in options.env.unwrap_or_default() {
+      channel.set_env(false, name, value).await.into_error()?;
+    }
+    let stdin = options.stdin.map(into_bytes);
+    let output = exec_on_channel(
+      channel,
+      command,
+      stdin,
+      IdleTimeout::new(options.idle_timeout_ms),
+      OutputLimit {
+        max_bytes: options
+          .max_output_bytes
+          .map_or(usize::MAX, |max| max as usize),
+        close: options.close_on_truncate.unwrap_or(false),
+      },
+      options.signal,
+    )
+    .await?;
+    Ok(CheckedOutput {
+      output,
+      check: options.throw_on_non_zero.unwrap_or(false),
+    })
+  }
+
+  #[napi(ts_return_type = "Promise<ExecOutput>")]
+  /// Run `script` by piping it into an interpreter's stdin, so nothing is written to the
+  /// server's disk and no quoting of the script is needed. The script can not read stdin itself.
+  pub async fn exec_script(
+    &self,
+    script: Either<String, Buffer>,
+    options: Option<ExecScriptOptions>,
+  ) -> Result<CheckedOutput> {
+    let options = options.unwrap_or_default();
+    let mut argv = options
+      .interpreter
+      .unwrap_or_else(|| vec!["sh".to_owned(), "-s".to_owned(), "--".to_owned()]);
+    if argv.is_empty() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "The interpreter can not be empty".to_owned(),
+      ));
+    }
+    argv.extend(options.args.unwrap_or_default());
+    self
+      .exec(
+        Either::B(argv),
+        Some(ExecOptions {
+          shell: options.shell,
+          idle_timeout_ms: options.idle_timeout_ms,
+          stdin: Some(script),
+          signal: options.signal,
+          env: options.env,
+          max_output_bytes: None,
+          close_on_truncate: None,
+          throw_on_non_zero: options.throw_on_non_zero,
+        }),
+      )
+      .await
+  }
+
+  #[napi]
+  /// Open a session channel, for running a command while writing its stdin and reading its
+  /// output.
+  pub async fn open_session(&self, options: Option<ChannelOptions>) -> Result<Channel> {
+    let channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    let idle_timeout_ms = options.and_then(|options| options.idle_timeout_ms);
+    Ok(Channel::new(channel, &self.flow, idle_timeout_ms))
+  }
+
+  #[napi]
+  /// Copy the local file or directory at `local_path` to `remote_path` by running `scp` on the
+  /// server, for servers without the `sftp` subsystem.
+  pub async fn scp_upload(
+    &self,
+    local_path: String,
+    remote_path: String,
+    options: Option<ScpOptions>,
+  ) -> Result<()> {
+    let options = options.unwrap_or_default();
+    let progress = |transferred, total| options.report(transferred, total);
+    scp::upload(&self.handle, local_path, remote_path, &options, &progress).await
+  }
+
+  #[napi]
+  /// Copy the remote file or directory at `remote_path` to `local_path` by running `scp` on the
+  /// server. Into an existing local directory, the copy keeps its name.
+  pub async fn scp_download(
+    &self,
+    remote_path: String,
+    local_path: String,
+    options: Option<ScpOptions>,
+  ) -> Result<()> {
+    let options = options.unwrap_or_default();
+    let progress = |transferred, total| options.report(transferred, total);
+    scp::download(&self.handle, remote_path, local_path, &options, &progress).await
+  }
+
+  #[napi(ts_return_type = "Promise<FileTransferResult>")]
+  /// Copy the local file at `local_path` to `remote_path` over SFTP, or over SCP when the server
+  /// refuses the `sftp` subsystem, e.g. on embedded devices and git-only servers. Resolves with
+  /// the transport that was used.
+  pub async fn upload_file(
+    &self,
+    local_path: String,
+    remote_path: String,
+    options: Option<FileTransferOptions>,
+  ) -> Result<Coded<FileTransferResult>> {
+    coded(async {
+      let options = options.unwrap_or_default();
+      let progress = |transferred, total| options.report(transferred, total);
+      match sftp::request_subsystem(&self.handle).await? {
+        Ok(channel) => {
+          let sftp = Sftp::start_on(self.handle.clone(), channel, None).await?;
+          let uploaded = sftp
+            .upload_file(&local_path, remote_path, &options.sftp(), &progress)
+            .await;
+          let _ = sftp.close().await;
+          uploaded.map(|()| FileTransport::Sftp.into())
+        }
+        Err(refused) => {
+          scp::upload(
+            &self.handle,
+            local_path,
+            remote_path,
+            &options.scp(),
+            &progress,
+          )
+          .await
+          .map_err(|err| scp_fallback_failed(refused, err))?;
+          Ok(FileTransport::Scp.into())
+        }
+      }
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<FileTransferResult>")]
+  /// Copy the remote file at `remote_path` to `local_path` over SFTP, or over SCP when the server
+  /// refuses the `sftp` subsystem. Resolves with the transport that was used.
+  pub async fn download_file(
+    &self,
+    remote_path: String,
+    local_path: String,
+    options: Option<FileTransferOptions>,
+  ) -> Result<Coded<FileTransferResult>> {
+    coded(async {
+      let options = options.unwrap_or_default();
+      let progress = |transferred, total| options.report(transferred, total);
+      match sftp::request_subsystem(&self.handle).await? {
+        Ok(channel) => {
+          let sftp = Sftp::start_on(self.handle.clone(), channel, None).await?;
+          let downloaded = sftp
+            .download_file(remote_path, &local_path, &options.sftp(), &progress)
+            .await;
+          let _ = sftp.close().await;
+          downloaded.map(|()| FileTransport::Sftp.into())
+        }
+        Err(refused) => {
+          scp::download(
+            &self.handle,
+            remote_path,
+            local_path,
+            &options.scp(),
+            &progress,
+          )
+          .await
+          .map_err(|err| scp_fallback_failed(refused, err))?;
+          Ok(FileTransport::Scp.into())
+        }
+      }
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<Sftp>")]
+  /// Start the `sftp` subsystem on a new session channel, for transferring and managing files.
+  pub async fn sftp(&self, options: Option<SftpOptions>) -> Result<Coded<Sftp>> {
+    coded(async { Sftp::start(self.handle.clone(), options).await }).await
+  }
+
+  #[napi]
+  /// Start `command` on a new session channel, like Node's `child_process.spawn`.
+  /// Resolves once the server has accepted the command.
+  pub async fn spawn(
+    &self,
+    command: String,
+    options: Option<SpawnOptions>,
+  ) -> Result<ChildProcess> {
+    let mut channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    for (name, value) in options.and_then(|options| options.env).unwrap_or_default() {
+      channel.set_env(false, name, value).await.into_error()?;
+    }
+    channel.exec(true, command).await.into_error()?;
+    wait_for_reply(&mut channel, "exec").await?;
+    Ok(ChildProcess::new(Channel::new(channel, &self.flow, None)))
+  }
+
+  #[napi]
+  /// Start the login shell on a new session channel with a pseudo-terminal, for interactive
+  /// use. Write keystrokes with `Channel.write` and follow terminal resizes with
+  /// `Channel.windowChange`.
+  pub async fn shell(&self, options: Option<PtyOptions>) -> Result<Channel> {
+    let channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    open_pty(channel, &self.flow, options.unwrap_or_default(), None).await
+  }
+
+  #[napi]
+  /// Start `command` on a new session channel with a pseudo-terminal, for interactive programs
+  /// such as `top` or `vim`. Use `Channel.attachStdio` to drive it from this process's terminal.
+  pub async fn exec_pty(&self, command: String, options: Option<PtyOptions>) -> Result<Channel> {
+    let channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    open_pty(
+      channel,
+      &self.flow,
+      options.unwrap_or_default(),
+      Some(command),
+    )
+    .await
+  }
+
+  #[napi]
+  /// Run several commands, each on its own session channel.
+  /// The channel opens are pipelined rather than waiting for every confirmation in turn,
+  /// and the results are returned in the same order as `commands`. A command that fails, e.g.
+  /// because its channel could not be opened, is reported in its result rather than rejecting
+  /// the whole batch.
+  pub async fn exec_batch(&self, commands: Vec<String>) -> Vec<ExecBatchResult> {
+    futures::future::join_all(commands.into_iter().map(|command| async move {
+      let output = async {
+        let channel = self
+          .handle
+          .read()
+          .await
+          .channel_open_session()
+          .await
+          .into_error()?;
+        exec_on_channel(
+          channel,
+          command,
+          None,
+          IdleTimeout::new(None),
+          OutputLimit::unlimited(),
+          None,
+        )
+        .await
+      };
+      match output.await {
+        Ok(output) => ExecBatchResult {
+          output: Some(output),
+          error: None,
+        },
+        Err(err) => ExecBatchResult {
+          output: None,
+          error: Some(err.reason),
+        },
+      }
+    }))
+    .await
+  }
+
+  #[napi]
+  /// Run `command`, handing its output to the callbacks in `options` as it arrives.
+  /// Resolves with the exit status.
+  pub async fn exec_stream(&self, command: String, options: ExecStreamOptions) -> Result<u32> {
+    let mut channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    channel.exec(true, command).await.into_error()?;
+    let max_line_length = options
+      .max_line_length
+      .map(|max| max.max(1) as usize)
+      .unwrap_or(DEFAULT_MAX_LINE_LENGTH);
+    let mut stdout = LineBuffer::new(max_line_length);
+    let mut stderr = LineBuffer::new(max_line_length);
+    let mut status = 0;
+    let mut idle = IdleTimeout::new(options.idle_timeout_ms);
+    while let Some(msg) = idle.wait(&mut channel).await? {
+      let (data, stream, lines) = match msg {
+        russh::ChannelMsg::Data { ref data } => (data, "stdout", &mut stdout),
+        russh::ChannelMsg::ExtendedData { ref data, ext: 1 } => (data, "stderr", &mut stderr),
+        russh::ChannelMsg::ExitStatus { exit_status } => {
+          status = exit_status;
+          continue;
+        }
+        _ => continue,
+      };
+      if let Some(on_data) = &options.on_data {
+        on_data.call(
+          FnArgs::from((data.to_vec().into(), stream.to_owned())),
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+      if let Some(on_line) = &options.on_line {
+        lines.push(data, |line| {
+          on_line.call(
+            FnArgs::from((line, stream.to_owned())),
+            ThreadsafeFunctionCallMode::NonBlocking,
+          );
+        });
+      }
+    }
+    if let Some(on_line) = &options.on_line {
+      for (lines, stream) in [(stdout, "stdout"), (stderr, "stderr")] {
+        if let Some(line) = lines.finish() {
+          on_line.call(
+            FnArgs::from((line, stream.to_owned())),
+            ThreadsafeFunctionCallMode::NonBlocking,
+          );
+        }
+      }
+    }
+    Ok(status)
+  }
+
+  #[napi]
+  /// Run `command` through `sudo` and `sh -c` on a PTY, answering its password prompt once.
+  /// The prompt is removed from the returned output. Fails if sudo rejects the password.
+  pub async fn exec_sudo(&self, command: String, options: SudoOptions) -> Result<ExecOutput> {
+    let prompt = options.prompt.unwrap_or_else(|| {
+      let nonce = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+      format!("[sudo password {nonce:x}]")
+    });
+    if prompt.is_empty() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "The sudo prompt can not be empty".to_owned(),
+      ));
+    }
+    let mut channel = self
+      .handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    // some sudoers configurations insist on a TTY; disable echo so the password does not show
+    // up in the output
+    channel
+      .request_pty(false, "xterm", 80, 24, 0, 0, &[(Pty::ECHO, 0)])
+      .await
+      .into_error()?;
+    channel
+      .exec(
+        true,
+        // sudo expands `%` escapes such as `%u` in the prompt, `%%` is a literal `%`
+        format!(
+          "sudo -S -p {} -- sh -c {}",
+          shell_quote(&prompt.replace('%', "%%")),
+          shell_quote(&command)
+        ),
+      )
+      .await
+      .into_error()?;
+    let prompt = prompt.as_bytes();
+    let mut output = Vec::new();
+    // everything before this offset has been checked for the prompt
+    let mut scanned = 0;
+    let mut password_sent = false;
+    let mut exit = Exit::default();
+    while let Some(msg) = channel.wait().await {
+      match msg {
+        russh::ChannelMsg::Data { ref data } | russh::ChannelMsg::ExtendedData { ref data, .. } => {
+          output.extend_from_slice(data);
+          // the prompt may be split across reads, so only skip what can no longer be its start
+          let Some(position) = output[scanned..]
+            .windows(prompt.len())
+            .position(|window| window == prompt)
+            .map(|position| scanned + position)
+          else {
+            scanned = output.len().saturating_sub(prompt.len() - 1).max(scanned);
+            continue;
+          };
+          if password_sent {
+            let _ = channel.close().await;
+            return Err(Error::new(
+              Status::GenericFailure,
+              "sudo rejected the password".to_owned(),
+            ));
+          }
+          output.drain(position..position + prompt.len());
+          scanned = position;
+          channel
+            .data(format!("{}\n", options.password).as_bytes())
+            .await
+            .into_error()?;
+          password_sent = true;
+        }
+        msg => {
+          exit.record(msg);
+        }
+      }
+    }
+    Ok(exit.into_output(output, Vec::new()))
+  }
+
+  #[napi]
+  pub async fn disconnect(
+    &self,
+    reason: DisconnectReason,
+    description: String,
+    language_tag: String,
+  ) -> Result<()> {
+    *self
+      .local_disconnect
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner) = Some((reason, description.clone()));
+    self
+      .handle
+      .read()
+      .await
+      .disconnect(reason.into(), &description, &language_tag)
+      .await
+      .map_err(|err| Error::new(Status::GenericFailure, format!("Disconnect failed: {err}")))?;
+    Ok(())
+  }
+}
+
+impl Client {
+  /// Send one authentication request, giving up after `Config.authTimeout`.
+  async fn attempt_auth<F>(&mut self, attempt: F) -> Result<bool>
+  where
+    F: FnOnce(&mut client::Handle<ClientHandle>) -> AuthAttempt<'_>,
+  {
+    let Some(timeout) = self.auth_policy.timeout else {
+      return attempt(&mut *self.handle.write().await).await.into_error();
+    };
+    if let Ok(result) =
+      tokio::time::timeout(timeout, attempt(&mut *self.handle.write().await)).await
+    {
+      return result.into_error();
+    }
+    let description = "Authentication timed out".to_owned();
+    *self
+      .local_disconnect
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner) =
+      Some((DisconnectReason::ByApplication, description.clone()));
+    let _ = self
+      .handle
+      .read()
+      .await
+      .disconnect(russh::Disconnect::ByApplication, &description, "")
+      .await;
+    Err(Error::new(
+      Status::GenericFailure,
+      format!(
+        "The server did not answer the authentication attempt within {} ms",
+        timeout.as_millis()
+      ),
+    ))
+  }
+
+  /// Send the authentication request again after a rejection, up to `Config.authRetries`
+  /// times, waiting longer every time.
+  async fn attempt_auth_retrying<F>(&mut self, mut attempt: F) -> Result<bool>
+  where
+    F: FnMut(&mut client::Handle<ClientHandle>) -> AuthAttempt<'_>,
+  {
+    let mut delay = self.auth_policy.retry_delay;
+    for _ in 0..self.auth_policy.retries {
+      if self.attempt_auth(&mut attempt).await? {
+        return Ok(true);
+      }
+      tokio::time::sleep(delay).await;
+      delay = delay.saturating_mul(2);
+    }
+    self.attempt_auth(attempt).await
+  }
+}
+
+async fn exec_on_channel(
+  mut channel: russh::Channel<client::Msg>,
+  command: String,
+  stdin: Option<Vec<u8>>,
+  idle: IdleTimeout,
+  limit: OutputLimit,
+  mut abort: Option<Abort>,
+) -> Result<ExecOutput> {
+  tokio::select! {
+    output = run_on_channel(&mut channel, command, stdin, idle, limit) => output,
+    _ = Abort::wait_for(&mut abort) => {
+      let _ = channel.close().await;
+      Err(aborted())
+    }
+  }
+}
+
+async fn run_on_channel(
+  channel: &mut russh::Channel<client::Msg>,
+  command: String,
+  stdin: Option<Vec<u8>>,
+  mut idle: IdleTimeout,
+  limit: OutputLimit,
+) -> Result<ExecOutput> {
+  channel.exec(true, command).await.into_error()?;
+  if let Some(stdin) = stdin {
+    // output is queued on the channel while this waits for window space, so a command that
+    // writes before reading everything can not deadlock it
+    channel.data(&stdin[..]).await.into_error()?;
+    channel.eof().await.into_error()?;
+    idle.reset();
+  }
+  let mut output = Vec::new();
+  let mut stderr = Vec::new();
+  let mut exit = Exit::default();
+  // of stdout and stderr together
+  let mut buffered = 0;
+  while let Some(msg) = idle.wait(channel).await? {
+    let (data, buffer) = match msg {
+      russh::ChannelMsg::Data { ref data } => (data, &mut output),
+      // extended data type 1 is SSH_EXTENDED_DATA_STDERR
+      russh::ChannelMsg::ExtendedData { ref data, ext: 1 } => (data, &mut stderr),
+      msg => {
+        exit.record(msg);
+        continue;
+      }
+    };
+    if exit.truncated {
+      continue;
+    }
+    let room = limit.max_bytes - buffered;
+    if data.len() <= room {
+      buffer.extend_from_slice(data);
+      buffered += data.len();
+      continue;
+    }
+    buffer.extend_from_slice(&data[..room]);
+    buffered = limit.max_bytes;
+    exit.truncated = true;
+    if limit.close {
+      // not waiting for the server to confirm, as nothing it sends would be kept anyway
+      let _ = channel.close().await;
+      break;
+    }
+  }
+  Ok(exit.into_output(output, stderr))
+}
+
+/// How much output `exec` buffers before it truncates.
+struct OutputLimit {
+  /// Of stdout and stderr together.
+  max_bytes: usize,
+  /// Close the channel once the output is truncated.
+  close: bool,
+}
+
+impl OutputLimit {
+  fn unlimited() -> Self {
+    Self {
+      max_bytes: usize::MAX,
+      close: false,
+    }
+  }
+}
+
+/// Run `argv` on a new session channel of `handle`, quoted for a POSIX shell, failing unless it
+/// exits with status 0.
+pub(crate) async fn exec_argv(handle: &SharedHandle, argv: &[String]) -> Result<ExecOutput> {
+  let command = quote_argv(argv, ShellDialect::Posix)?;
+  let channel = handle
+    .read()
+    .await
+    .channel_open_session()
+    .await
+    .into_error()?;
+  let output = exec_on_channel(
+    channel,
+    command,
+    None,
+    IdleTimeout::new(None),
+    OutputLimit::unlimited(),
+    None,
+  )
+  .await?;
+  check_exit(&output)?;
+  Ok(output)
+}
+
+/// The message of the `NonZeroExit` error for a command that did not exit with status `0`.
+fn exit_failure(output: &ExecOutput) -> Option<String> {
+  let ended = match (&output.exit_signal, output.status) {
+    (Some(signal), _) => format!("was killed by SIG{signal}"),
+    (None, 0) => return None,
+    (None, status) => format!("exited with status {status}"),
+  };
+  Some(format!(
+    "NonZeroExit: the command {ended}\nstdout: {}\nstderr: {}",
+    String::from_utf8_lossy(&output.output),
+    String::from_utf8_lossy(&output.stderr),
+  ))
+}
+
+/// Fail if the command did not exit with status `0`.
+fn check_exit(output: &ExecOutput) -> Result<()> {
+  match exit_failure(output) {
+    Some(message) => Err(Error::new(Status::GenericFailure, message)),
+    None => Ok(()),
+  }
+}
+
+/// What `exec` resolves with, unless `check` is set and the command failed. The `NonZeroExit`
+/// error is then built as the promise settles, on the JS thread, so it can carry the status and
+/// output as properties: an error returned from an async method only has a message and a napi
+/// status as its code.
+pub struct CheckedOutput {
+  output: ExecOutput,
+  check: bool,
+}
+
+impl ToNapiValue for CheckedOutput {
+  unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+    let Some(message) = val.check.then(|| exit_failure(&val.output)).flatten() else {
+      return ExecOutput::to_napi_value(env, val.output);
+    };
+    let output = val.output;
+    let error = JsError::from(Error::new("NonZeroExit", message)).into_value(env);
+    let mut object = Object::from_raw(env, error);
+    object.set_named_property("status", output.status)?;
+    if let Some(signal) = output.exit_signal {
+      object.set_named_property("exitSignal", signal)?;
+    }
+    object.set_named_property("stdout", output.output)?;
+    object.set_named_property("stderr", output.stderr)?;
+    // an error holding a JS value rejects the promise with that value
+    Err(Error::from(Unknown::from_raw_unchecked(env, error)))
+  }
+}
+
+/// How a command ended, as reported by the server.
+#[derive(Default)]
+struct Exit {
+  status: u32,
+  truncated: bool,
+  signal: Option<String>,
+  core_dumped: bool,
+  error_message: Option<String>,
+}
+
+impl Exit {
+  fn record(&mut self, msg: russh::ChannelMsg) {
+    match msg {
+      russh::ChannelMsg::ExitStatus { exit_status } => self.status = exit_status,
+      russh::ChannelMsg::ExitSignal {
+        signal_name: signal,
+        core_dumped,
+        error_message,
+        ..
+      } => {
+        self.signal = Some(signal_name(signal));
+        self.core_dumped = core_dumped;
+        self.error_message = Some(error_message).filter(|message| !message.is_empty());
+      }
+      _ => {}
+    }
+  }
+
+  fn into_output(self, output: Vec<u8>, stderr: Vec<u8>) -> ExecOutput {
+    ExecOutput {
+      status: self.status,
+      output: output.into(),
+      stderr: stderr.into(),
+      exit_signal: self.signal,
+      core_dumped: self.core_dumped,
+      error_message: self.error_message,
+      truncated: self.truncated,
+    }
+  }
+}
+
+async fn keyboard_interactive(
+  handle: &mut client::Handle<ClientHandle>,
+  user: String,
+  options: KeyboardInteractiveOptions,
+) -> Result<bool> {
+  let mut reply = handle
+    .authenticate_keyboard_interactive_start(user, options.submethods.clone())
+    .await
+    .into_error()?;
+  loop {
+    let (name, instructions, prompts) = match reply {
+      client::KeyboardInteractiveAuthResponse::Success => return Ok(true),
+      client::KeyboardInteractiveAuthResponse::Failure => return Ok(false),
+      client::KeyboardInteractiveAuthResponse::InfoRequest {
+        name,
+        instructions,
+        prompts,
+      } => (name, instructions, prompts),
+    };
+    let responses = match answer_prompts(&options, name, instructions, &prompts).await {
+      Ok(responses) => responses,
+      Err(err) => {
+        // the session ignores everything, even a disconnect, until the round is answered
+        let mut blank = vec![String::new(); prompts.len()];
+        while let Ok(client::KeyboardInteractiveAuthResponse::InfoRequest { prompts, .. }) = handle
+          .authenticate_keyboard_interactive_respond(blank)
+          .await
+        {
+          blank = vec![String::new(); prompts.len()];
+        }
+        return Err(err);
+      }
+    };
+    reply = handle
+      .authenticate_keyboard_interactive_respond(responses)
+      .await
+      .into_error()?;
+  }
+}
+
+async fn answer_prompts(
+  options: &KeyboardInteractiveOptions,
+  name: String,
+  instructions: String,
+  prompts: &[client::Prompt],
+) -> Result<Vec<String>> {
+  let answers = options.answers.as_deref().unwrap_or_default();
+  let mut responses = Vec::with_capacity(prompts.len());
+  for prompt in prompts {
+    let answer = answers
+      .iter()
+      .find(|answer| answer.prompt_match.0.is_match(&prompt.prompt));
+    responses.push(match answer.map(|answer| &answer.response) {
+      Some(Either::A(response)) => Some(response.clone()),
+      Some(Either::B(response)) => match response.call_async(prompt.prompt.clone()).await? {
+        Either3::A(response) => Some(response),
+        Either3::B(response) => Some(response.await?),
+        Either3::C(_) => {
+          return Err(Error::new(
+            Status::InvalidArg,
+            "A keyboard-interactive response function must return a string".to_owned(),
+          ))
+        }
+      },
+      None => None,
+    });
+  }
+  let unmatched = prompts
+    .iter()
+    .zip(&responses)
+    .filter(|(_, response)| response.is_none())
+    .map(|(prompt, _)| prompt)
+    .collect::<Vec<_>>();
+  let mut fallback = if unmatched.is_empty() {
+    Vec::new()
+  } else if let Some(on_prompts) = &options.on_prompts {
+    let challenge = KeyboardInteractiveChallenge {
+      name: Some(name),
+      instructions: Some(instructions),
+      prompts: unmatched
+        .iter()
+        .map(|prompt| KeyboardInteractivePrompt {
+          prompt: prompt.prompt.clone(),
+          echo: Some(prompt.echo),
+        })
+        .collect(),
+    };
+    let fallback = match on_prompts.call_async(challenge).await? {
+      Either3::A(fallback) => fallback,
+      Either3::B(fallback) => fallback.await?,
+      Either3::C(_) => Vec::new(),
+    };
+    if fallback.len() != unmatched.len() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        format!(
+          "onPrompts returned {} answers for {} prompts",
+          fallback.len(),
+          unmatched.len()
+        ),
+      ));
+    }
+    fallback
+  } else if options.fail_on_unmatched_prompt.unwrap_or(true) {
+    return Err(Error::new(
+      Status::GenericFailure,
+      format!(
+        "No answer for the keyboard-interactive prompt {:?}",
+        unmatched[0].prompt
+      ),
+    ));
+  } else {
+    vec![String::new(); unmatched.len()]
+  }
+  .into_iter();
+  Ok(
+    responses
+      .into_iter()
+      .map(|response| response.or_else(|| fallback.next()).unwrap_or_default())
+      .collect(),
+  )
+}
+
+#[napi]
+/// Quote every element of `argv` for `shell`, `ShellDialect.Posix` by default, and join them
+/// into one command line, as `Client.exec` does with an argv array.
+pub fn build_command(argv: Vec<String>, shell: Option<ShellDialect>) -> Result<String> {
+  quote_argv(&argv, shell.unwrap_or(ShellDialect::Posix))
+}
+
+#[napi]
+/// Quote `arg` for `shell`, `ShellDialect.Posix` by default, so it is passed through as one
+/// argument when interpolated into a command.
+pub fn escape_shell_arg(arg: String, shell: Option<ShellDialect>) -> Result<String> {
+  quote_argv(&[arg], shell.unwrap_or(ShellDialect::Posix))
+}
+
+/// Quote `arg` for a POSIX shell.
+fn shell_quote(arg: &str) -> String {
+  format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Quote every element of `argv` for `shell` and join them into one command line.
+pub(crate) fn quote_argv(argv: &[String], shell: ShellDialect) -> Result<String> {
+  if argv.is_empty() {
+    return Err(Error::new(
+      Status::InvalidArg,
+      "The argv array can not be empty".to_owned(),
+    ));
+  }
+  match shell {
+    ShellDialect::Posix => Ok(
+      argv
+        .iter()
+        .map(|arg| {
+          let bare = !arg.is_empty()
+            && arg
+              .bytes()
+              .all(|byte| byte.is_ascii_alphanumeric() || b"_@%+=:,./-".contains(&byte));
+          if bare {
+            arg.clone()
+          } else {
+            shell_quote(arg)
+          }
+        })
+        .collect::<Vec<_>>()
+        .join(" "),
+    ),
+    ShellDialect::Cmd => {
+      let mut quoted = Vec::with_capacity(argv.len());
+      for arg in argv {
+        if arg.contains(['\r', '\n']) {
+          return Err(Error::new(
+            Status::InvalidArg,
+            "cmd.exe can not pass newlines in arguments".to_owned(),
+          ));
+        }
+        quoted.push(cmd_quote(arg));
+      }
+      Ok(quoted.join(" "))
+    }
+    // a quoted program name is only a string to PowerShell, the call operator runs it
+    ShellDialect::PowerShell => Ok(format!(
+      "& {}",
+      argv
+        .iter()
+        .map(|arg| powershell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+    )),
+  }
+}
+
+/// Quote `arg` the way `CommandLineToArgvW` parses it, then escape it from cmd.exe with `^`.
+fn cmd_quote(arg: &str) -> String {
+  let mut argv = String::from("\"");
+  let mut backslashes = 0;
+  for c in arg.chars() {
+    match c {
+      '\\' => backslashes += 1,
+      // backslashes before a quote are doubled, and the quote itself escaped
+      '"' => {
+        argv.extend(std::iter::repeat_n('\\', backslashes + 1));
+        backslashes = 0;
+      }
+      _ => backslashes = 0,
+    }
+    argv.push(c);
+  }
+  // as are the ones before the closing quote
+  argv.extend(std::iter::repeat_n('\\', backslashes));
+  argv.push('"');
+  let mut escaped = String::with_capacity(argv.len() * 2);
+  for c in argv.chars() {
+    if "()%!^\"<>&|".contains(c) {
+      escaped.push('^');
+    }
+    escaped.push(c);
+  }
+  escaped
+}
+
+/// Quote `arg` as a PowerShell verbatim string. PowerShell also treats the typographic
+/// single quotes as quote characters, so those are doubled too.
+fn powershell_quote(arg: &str) -> String {
+  let mut quoted = String::with_capacity(arg.len() + 2);
+  quoted.push('\'');
+  for c in arg.chars() {
+    if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+      quoted.push(c);
+    }
+    quoted.push(c);
+  }
+  quoted.push('\'');
+  quoted
+}
+
+#[napi(object, object_to_js = false)]
+/// The credentials `Client.authenticate` may use.
+pub struct AuthenticateOptions {
+  /// Defaults to the user of the `ssh://` URL passed to `connect`.
+  pub user: Option<String>,
+  /// Which methods to try, in order, out of `publickey`, `keyboard-interactive` and
+  /// `password`. A method without credentials is skipped. Defaults to all three in that order.
+  pub methods: Option<Vec<String>>,
+  /// A private key file or a `KeyPair`, tried first for `publickey`.
+  #[napi(ts_type = "string | KeyPair")]
+  pub key: Option<AuthKey>,
+  /// Offer the identities of the ssh-agent for `publickey`, after `key`.
+  pub agent: Option<bool>,
+  pub keyboard_interactive: Option<KeyboardInteractiveOptions>,
+  /// A password, or a function returning it, as for `authenticatePassword`.
+  #[napi(ts_type = "string | (() => string | Promise<string>)")]
+  pub password: Option<Password>,
+}
+
+/// A private key file or a `KeyPair`.
+pub enum AuthKey {
+  Path(String),
+  KeyPair(Box<key::KeyPair>),
+}
+
+impl TypeName for AuthKey {
+  fn type_name() -> &'static str {
+    "string | KeyPair"
+  }
+
+  fn value_type() -> ValueType {
+    ValueType::Unknown
+  }
+}
+
+impl ValidateNapiValue for AuthKey {}
+
+impl FromNapiValue for AuthKey {
+  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+    if let Ok(path) = String::from_napi_value(env, napi_val) {
+      return Ok(Self::Path(path));
+    }
+    let key = Reference::<KeyPair>::from_napi_value(env, napi_val).map_err(|_| {
+      Error::new(
+        Status::InvalidArg,
+        "key must be a path or a KeyPair".to_owned(),
+      )
+    })?;
+    Ok(Self::KeyPair(Box::new(key.inner.clone())))
+  }
+}
+
+#[napi(object)]
+#[derive(Clone, Copy)]
+pub struct KeyAuthOptions {
+  /// The signature algorithm of an RSA key: `SHA2_512` (`rsa-sha2-512`), `SHA2_256`
+  /// (`rsa-sha2-256`) or `SHA1` (`ssh-rsa`) for devices that reject SHA-2 signatures.
+  /// Defaults to the algorithm the key was generated or loaded with, `SHA2_512` for key files.
+  pub hash_alg: Option<SignatureHash>,
+}
+
+#[napi(object)]
+pub struct AgentAuthOptions {
+  /// Try at most this many identities. Servers disconnect after a few failed attempts,
+  /// OpenSSH after its `MaxAuthTries` of 6.
+  pub max_attempts: Option<u32>,
+}
+
+#[napi(object, object_to_js = false)]
+pub struct ExecOptions {
+  /// How an argv `command` is quoted. Defaults to `ShellDialect.Posix`.
+  pub shell: Option<ShellDialect>,
+  /// Close the channel and fail with an `IdleTimeout` error when no data flows on it in
+  /// either direction for this long. In milliseconds. The rest of the connection is unaffected.
+  pub idle_timeout_ms: Option<u32>,
+  /// Written to the command's stdin, followed by EOF. Without it stdin is left open.
+  pub stdin: Option<Either<String, Buffer>>,
+  /// Close the channel and reject with an `AbortError` when aborted.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<Abort>,
+  /// Environment variables for the command. Servers drop the ones they do not accept, e.g. those
+  /// missing from OpenSSH's `AcceptEnv`, without an error.
+  pub env: Option<HashMap<String, String>>,
+  /// Stop buffering once stdout and stderr together reach this many bytes, and set
+  /// `ExecOutput.truncated`. The command keeps running unless `closeOnTruncate` is set.
+  pub max_output_bytes: Option<u32>,
+  /// Close the channel as soon as the output is truncated, instead of waiting for the command.
+  pub close_on_truncate: Option<bool>,
+  /// Reject with an error whose `code` is `NonZeroExit` when the command exits with a non-zero
+  /// status or is killed by a signal. The error has the `status`, `exitSignal`, `stdout` and
+  /// `stderr` of the command, which its message also includes.
+  pub throw_on_non_zero: Option<bool>,
+}
+
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+pub struct ExecScriptOptions {
+  /// The argv of a program that reads the script from stdin, e.g. `["python3", "-"]`.
+  /// Defaults to `["sh", "-s", "--"]`.
+  pub interpreter: Option<Vec<String>>,
+  /// Arguments passed to the script after the interpreter argv.
+  pub args: Option<Vec<String>>,
+  /// How the interpreter argv is quoted. Defaults to `ShellDialect.Posix`.
+  pub shell: Option<ShellDialect>,
+  /// See `ExecOptions.idleTimeoutMs`.
+  pub idle_timeout_ms: Option<u32>,
+  /// Close the channel and reject with an `AbortError` when aborted.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<Abort>,
+  /// Environment variables for the interpreter, see `ExecOptions.env`.
+  pub env: Option<HashMap<String, String>>,
+  /// See `ExecOptions.throwOnNonZero`.
+  pub throw_on_non_zero: Option<bool>,
+}
+
+/// The shell that runs commands on the server, which decides how arguments are quoted.
+#[napi]
+pub enum ShellDialect {
+  /// `sh` and compatible shells, as on most Unix servers.
+  Posix,
+  /// `cmd.exe`, the default shell of Windows OpenSSH.
+  Cmd,
+  /// PowerShell, when configured as the Windows OpenSSH default shell.
+  PowerShell,
+}
+
+#[napi(object, object_to_js = false)]
+pub struct ExecStreamOptions {
+  /// Called with every chunk of output as it arrives.
+  #[napi(ts_type = "(data: Buffer, stream: 'stdout' | 'stderr') => void")]
+  pub on_data: Option<
+    ThreadsafeFunction<FnArgs<(Buffer, String)>, (), FnArgs<(Buffer, String)>, Status, false>,
+  >,
+  /// Called with every line of output, without its LF or CRLF terminator.
+  /// Invalid UTF-8 is replaced with U+FFFD, and a trailing partial line is flushed at close.
+  #[napi(ts_type = "(line: string, stream: 'stdout' | 'stderr') => void")]
+  pub on_line: Option<
+    ThreadsafeFunction<FnArgs<(String, String)>, (), FnArgs<(String, String)>, Status, false>,
+  >,
+  /// Longer runs of output without a newline are split into lines of this many bytes.
+  /// Defaults to `65536`.
+  pub max_line_length: Option<u32>,
+  /// As `ExecOptions.idleTimeoutMs`.
+  pub idle_timeout_ms: Option<u32>,
+}
+
+const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Splits a byte stream into lines, holding on to a partial line between chunks.
+struct LineBuffer {
+  partial: Vec<u8>,
+  max_line_length: usize,
+}
+
+impl LineBuffer {
+  fn new(max_line_length: usize) -> Self {
+    Self {
+      partial: Vec::new(),
+      max_line_length,
+    }
+  }
+
+  fn push(&mut self, mut data: &[u8], mut emit: impl FnMut(String)) {
+    while let Some(end) = data.iter().position(|&byte| byte == b'\n') {
+      self.partial.extend_from_slice(&data[..end]);
+      data = &data[end + 1..];
+      if self.partial.last() == Some(&b'\r') {
+        self.partial.pop();
+      }
+      self.split_overlong(&mut emit);
+      emit(String::from_utf8_lossy(&self.partial).into_owned());
+      self.partial.clear();
+    }
+    self.partial.extend_from_slice(data);
+    self.split_overlong(&mut emit);
+  }
+
+  /// Emit full-length lines while the buffer is too long, keeping UTF-8 sequences whole
+  /// where possible. A trailing `\r` is not counted, as it may be the start of a CRLF split
+  /// between chunks.
+  fn split_overlong(&mut self, emit: &mut impl FnMut(String)) {
+    while self.partial.len() - usize::from(self.partial.last() == Some(&b'\r'))
+      > self.max_line_length
+    {
+      let mut at = self.max_line_length;
+      while at > 0 && self.max_line_length - at < 3 && self.partial[at] & 0xC0 == 0x80 {
+        at -= 1;
+      }
+      if at == 0 || self.partial[at] & 0xC0 == 0x80 {
+        at = self.max_line_length;
+      }
+      let rest = self.partial.split_off(at);
+      emit(String::from_utf8_lossy(&self.partial).into_owned());
+      self.partial = rest;
+    }
+  }
+
+  fn finish(self) -> Option<String> {
+    (!self.partial.is_empty()).then(|| String::from_utf8_lossy(&self.partial).into_owned())
+  }
+}
+
+#[napi(object, object_to_js = false)]
+pub struct KeyboardInteractiveOptions {
+  /// Rules answering prompts by their text. The first rule that matches a prompt answers it.
+  pub answers: Option<Vec<KeyboardInteractiveAnswer>>,
+  /// Answer the prompts no rule matches, in the order they are given.
+  pub on_prompts: Option<
+    ThreadsafeFunction<
+      KeyboardInteractiveChallenge,
+      Either3<Vec<String>, Promise<Vec<String>>, UnknownReturnValue>,
+      KeyboardInteractiveChallenge,
+      Status,
+      false,
+    >,
+  >,
+  /// Fail when a prompt has no answer, instead of answering it with an empty string.
+  /// Only applies without `onPrompts`. Defaults to `true`.
+  pub fail_on_unmatched_prompt: Option<bool>,
+  /// The submethods hint sent to the server, e.g. `pam`.
+  pub submethods: Option<String>,
+}
+
+#[napi(object, object_to_js = false)]
+pub struct KeyboardInteractiveAnswer {
+  /// A string matches prompts containing it. A `RegExp` is evaluated with Rust's `regex`
+  /// syntax, honoring the `i`, `m` and `s` flags.
+  #[napi(ts_type = "string | RegExp")]
+  pub prompt_match: PromptMatch,
+  /// The answer, or a function computing it from the prompt, e.g. a TOTP code.
+  pub response: Either<
+    String,
+    ThreadsafeFunction<
+      String,
+      Either3<String, Promise<String>, UnknownReturnValue>,
+      String,
+      Status,
+      false,
+    >,
+  >,
+}
+
+pub struct PromptMatch(regex::Regex);
+
+impl FromNapiValue for PromptMatch {
+  unsafe fn from_napi_value(
+    env: napi::sys::napi_env,
+    napi_val: napi::sys::napi_value,
+  ) -> Result<Self> {
+    let invalid = |err: regex::Error| Error::new(Status::InvalidArg, format!("{err}"));
+    if Unknown::from_napi_value(env, napi_val)?.get_type()? == ValueType::String {
+      let literal = String::from_napi_value(env, napi_val)?;
+      return regex::Regex::new(&regex::escape(&literal))
+        .map(Self)
+        .map_err(invalid);
+    }
+    let pattern = Object::from_napi_value(env, napi_val)?;
+    let flags = pattern.get_named_property::<String>("flags")?;
+    regex::RegexBuilder::new(&pattern.get_named_property::<String>("source")?)
+      .case_insensitive(flags.contains('i'))
+      .multi_line(flags.contains('m'))
+      .dot_matches_new_line(flags.contains('s'))
+      .build()
+      .map(Self)
+      .map_err(invalid)
+  }
+}
+
+#[napi(object)]
+pub struct SudoOptions {
+  pub password: String,
+  /// The prompt sudo is told to print, which is watched for in the output. A `%` in it is
+  /// printed as is rather than expanded by sudo. Defaults to a unique marker.
+  pub prompt: Option<String>,
+}
+
+/// A reason for disconnection.
+#[napi]
+#[derive(Clone, Copy)]
+pub enum DisconnectReason {
+  HostNotAllowedToConnect = 1,
+  ProtocolError = 2,
+  KeyExchangeFailed = 3,
+  #[doc(hidden)]
+  Reserved = 4,
+  MACError = 5,
+  CompressionError = 6,
+  ServiceNotAvailable = 7,
+  ProtocolVersionNotSupported = 8,
+  HostKeyNotVerifiable = 9,
+  ConnectionLost = 10,
+  ByApplication = 11,
+  TooManyConnections = 12,
+  AuthCancelledByUser = 13,
+  NoMoreAuthMethodsAvailable = 14,
   IllegalUserName = 15,
 }
 
+impl From<russh::Disconnect> for DisconnectReason {
+  fn from(value: russh::Disconnect) -> Self {
+    match value {
+      russh::Disconnect::HostNotAllowedToConnect => Self::HostNotAllowedToConnect,
+      russh::Disconnect::ProtocolError => Self::ProtocolError,
+      russh::Disconnect::KeyExchangeFailed => Self::KeyExchangeFailed,
+      russh::Disconnect::Reserved => Self::Reserved,
+      russh::Disconnect::MACError => Self::MACError,
+      russh::Disconnect::CompressionError => Self::CompressionError,
+      russh::Disconnect::ServiceNotAvailable => Self::ServiceNotAvailable,
+      russh::Disconnect::ProtocolVersionNotSupported => Self::ProtocolVersionNotSupported,
+      russh::Disconnect::HostKeyNotVerifiable => Self::HostKeyNotVerifiable,
+      russh::Disconnect::ConnectionLost => Self::ConnectionLost,
+      russh::Disconnect::ByApplication => Self::ByApplication,
+      russh::Disconnect::TooManyConnections => Self::TooManyConnections,
+      russh::Disconnect::AuthCancelledByUser => Self::AuthCancelledByUser,
+      russh::Disconnect::NoMoreAuthMethodsAvailable => Self::NoMoreAuthMethodsAvailable,
+      russh::Disconnect::IllegalUserName => Self::IllegalUserName,
+    }
+  }
+}
+
 impl From<DisconnectReason> for russh::Disconnect {
   fn from(value: DisconnectReason) -> Self {
     match value {
@@ -368,8 +2760,109 @@ impl From<DisconnectReason> for russh::Disconnect {
   }
 }
 
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+/// Options of `Client.uploadFile` and `Client.downloadFile`, which apply the same whichever
+/// transport is used.
+pub struct FileTransferOptions {
+  /// Called after every chunk with the bytes transferred so far and the size of the file.
+  #[napi(ts_type = "(transferred: bigint, total: bigint) => void")]
+  pub on_progress: Option<
+    ThreadsafeFunction<FnArgs<(BigInt, BigInt)>, (), FnArgs<(BigInt, BigInt)>, Status, false>,
+  >,
+  /// Give the copy the permissions and the access and modification times of the original, like
+  /// `scp -p`.
+  pub preserve: Option<bool>,
+  /// Keep the transfer below this rate, averaged over about a chunk.
+  pub max_bytes_per_second: Option<u32>,
+  /// See `TransferOptions.parallelism`. Over SCP, the file always goes in a single stream.
+  pub parallelism: Option<u32>,
+}
+
+impl FileTransferOptions {
+  /// Pass the progress of the transfer to `onProgress`, if set.
+  fn report(&self, transferred: u64, total: u64) {
+    if let Some(on_progress) = &self.on_progress {
+      on_progress.call(
+        FnArgs::from((BigInt::from(transferred), BigInt::from(total))),
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }
+  }
+
+  /// The options for a transfer over SFTP. The progress is passed on separately.
+  fn sftp(&self) -> TransferOptions {
+    TransferOptions {
+      preserve: self.preserve,
+      max_bytes_per_second: self.max_bytes_per_second,
+      parallelism: self.parallelism,
+      ..Default::default()
+    }
+  }
+
+  /// The options for a transfer over SCP. The progress is passed on separately.
+  fn scp(&self) -> ScpOptions {
+    ScpOptions {
+      preserve: self.preserve,
+      max_bytes_per_second: self.max_bytes_per_second,
+      ..Default::default()
+    }
+  }
+}
+
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How `Client.uploadFile` or `Client.downloadFile` moved the file.
+pub enum FileTransport {
+  Sftp,
+  Scp,
+}
+
+#[napi(object)]
+/// What `Client.uploadFile` and `Client.downloadFile` resolve with.
+pub struct FileTransferResult {
+  pub transport: FileTransport,
+}
+
+impl From<FileTransport> for FileTransferResult {
+  fn from(transport: FileTransport) -> Self {
+    Self { transport }
+  }
+}
+
+/// The error of a transfer that fell back to SCP after the `sftp` subsystem was `refused`, and
+/// then failed with `err`.
+fn scp_fallback_failed(refused: Error, err: Error) -> Error {
+  Error::new(
+    err.status,
+    format!("{}, and over scp: {}", refused.reason, err.reason),
+  )
+}
+
+#[napi(object)]
+/// The outcome of one command of `Client.execBatch`.
+pub struct ExecBatchResult {
+  /// What the command produced, unset if it could not be run.
+  pub output: Option<ExecOutput>,
+  /// Why the command could not be run, unset if it was.
+  pub error: Option<String>,
+}
+
 #[napi(object)]
 pub struct ExecOutput {
   pub status: u32,
+  /// What the command wrote to stdout.
   pub output: Buffer,
+  /// What the command wrote to stderr. Always empty for `execSudo`, whose PTY merges stderr
+  /// into `output`.
+  pub stderr: Buffer,
+  /// The signal that killed the command, without the `SIG` prefix, e.g. `KILL`. `status` is
+  /// `0` in that case, as the server sends no exit status.
+  pub exit_signal: Option<String>,
+  /// Whether the command dumped core when it was killed.
+  pub core_dumped: bool,
+  /// The message the server sent along with `exitSignal`, if any.
+  pub error_message: Option<String>,
+  /// Whether output was dropped because it exceeded `ExecOptions.maxOutputBytes`.
+  pub truncated: bool,
 }
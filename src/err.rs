@@ -1,3 +1,11 @@
+use std::future::Future;
+
+use napi::{
+  bindgen_prelude::{ToNapiValue, Unknown},
+  sys, JsError,
+};
+use russh_sftp::{client::error::Error as SftpError, protocol::StatusCode};
+
 pub(crate) trait IntoError {
   type Value;
 
@@ -19,3 +27,108 @@ impl<T> IntoError for Result<T, russh_keys::Error> {
     self.map_err(|err| napi::Error::new(napi::Status::GenericFailure, err.to_string()))
   }
 }
+
+impl<T> IntoError for Result<T, russh::AgentAuthError> {
+  type Value = T;
+
+  fn into_error(self) -> napi::Result<Self::Value> {
+    self.map_err(|err| napi::Error::new(napi::Status::GenericFailure, err.to_string()))
+  }
+}
+
+impl<T> IntoError for Result<T, SftpError> {
+  type Value = T;
+
+  fn into_error(self) -> napi::Result<Self::Value> {
+    self.map_err(|err| {
+      let reason = match err {
+        // the message starts with a name for the status, which `Coded` sets as `err.code`
+        SftpError::Status(status) => {
+          let code = status_name(&status.status_code);
+          if status.error_message.is_empty() {
+            format!("{code}: {}", status.status_code)
+          } else {
+            format!("{code}: {}", status.error_message)
+          }
+        }
+        SftpError::Timeout => format!("{TIMEOUT}: The server did not answer in time"),
+        err => err.to_string(),
+      };
+      napi::Error::new(napi::Status::GenericFailure, reason)
+    })
+  }
+}
+
+/// The code of an SFTP request the server did not answer in time.
+const TIMEOUT: &str = "TIMEOUT";
+
+/// The names of the SFTP statuses, as set as `err.code`.
+const STATUS_NAMES: [&str; 9] = [
+  "OK",
+  "EOF",
+  "NO_SUCH_FILE",
+  "PERMISSION_DENIED",
+  "FAILURE",
+  "BAD_MESSAGE",
+  "NO_CONNECTION",
+  "CONNECTION_LOST",
+  "OP_UNSUPPORTED",
+];
+
+fn status_name(status: &StatusCode) -> &'static str {
+  STATUS_NAMES[match status {
+    StatusCode::Ok => 0,
+    StatusCode::Eof => 1,
+    StatusCode::NoSuchFile => 2,
+    StatusCode::PermissionDenied => 3,
+    StatusCode::Failure => 4,
+    StatusCode::BadMessage => 5,
+    StatusCode::NoConnection => 6,
+    StatusCode::ConnectionLost => 7,
+    StatusCode::OpUnsupported => 8,
+  }]
+}
+
+/// The SFTP status `err` was made from by `into_error`, if any.
+fn sftp_code(err: &napi::Error) -> Option<&'static str> {
+  let (code, _) = err.reason.split_once(": ")?;
+  STATUS_NAMES
+    .into_iter()
+    .chain([TIMEOUT])
+    .find(|name| *name == code)
+}
+
+/// What an SFTP method resolves with. A failure is turned into the error as the promise
+/// settles, on the JS thread, so it can carry the SFTP status as its `code`: an error returned
+/// from an async method only has a napi status as its code.
+pub struct Coded<T>(napi::Result<T>);
+
+impl<T> Coded<T> {
+  /// The result, for calling the method from Rust.
+  pub(crate) fn into_result(self) -> napi::Result<T> {
+    self.0
+  }
+}
+
+/// Wait for `call`, keeping its failure for `Coded` to turn into the error.
+pub(crate) async fn coded<T>(
+  call: impl Future<Output = napi::Result<T>>,
+) -> napi::Result<Coded<T>> {
+  Ok(Coded(call.await))
+}
+
+impl<T: ToNapiValue> ToNapiValue for Coded<T> {
+  unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> napi::Result<sys::napi_value> {
+    match val.0 {
+      Ok(value) => T::to_napi_value(env, value),
+      Err(err) => match sftp_code(&err) {
+        Some(code) => {
+          let error = JsError::from(napi::Error::new(code, err.reason)).into_value(env);
+          // an error holding a JS value rejects the promise with that value
+          Err(napi::Error::from(Unknown::from_raw_unchecked(env, error)))
+        }
+        None => Err(err),
+      },
+    }
+  }
+}
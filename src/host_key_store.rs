@@ -0,0 +1,187 @@
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, UnknownReturnValue},
+};
+use napi_derive::napi;
+use russh_keys::{key, PublicKeyBase64};
+
+use crate::keypair::PublicKey;
+
+#[napi(object, object_to_js = false)]
+/// Host keys kept somewhere other than a `known_hosts` file, e.g. a central database.
+/// The store only looks keys up and saves them; deciding whether a key is known, changed or
+/// revoked is done by `connect`.
+pub struct HostKeyStore {
+  /// The keys recorded for `host` and `port`. Entries marked `revoked` are rejected even if no
+  /// other key is recorded.
+  #[napi(
+    ts_type = "(host: string, port: number) => Array<PublicKey | { key: PublicKey; revoked?: boolean }> | Promise<Array<PublicKey | { key: PublicKey; revoked?: boolean }>>"
+  )]
+  pub lookup:
+    ThreadsafeFunction<FnArgs<(String, u32)>, LookupResult, FnArgs<(String, u32)>, Status, false>,
+  /// Record a key that was accepted for a host without a stored key.
+  #[napi(ts_type = "(host: string, port: number, key: PublicKey) => void | Promise<void>")]
+  pub store: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, u32, PublicKey)>,
+      Either3<(), Promise<()>, UnknownReturnValue>,
+      FnArgs<(String, u32, PublicKey)>,
+      Status,
+      false,
+    >,
+  >,
+  /// Accept and store the key of a host the store has no key for. Otherwise such a key needs
+  /// `checkServerKey` or a matching SSHFP record to be accepted.
+  pub trust_on_first_use: Option<bool>,
+}
+
+/// How a host key compares to the keys stored for the host.
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyStatus {
+  /// The key is stored for the host.
+  Known,
+  /// No key is stored for the host.
+  Unknown,
+  /// Other keys are stored for the host.
+  Changed,
+  /// The key is marked revoked.
+  Revoked,
+}
+
+/// A key returned by `lookup`, either a `PublicKey` or `{ key, revoked }`.
+pub struct HostKeyEntry {
+  key: key::PublicKey,
+  revoked: bool,
+}
+
+impl TypeName for HostKeyEntry {
+  fn type_name() -> &'static str {
+    "HostKeyEntry"
+  }
+
+  fn value_type() -> ValueType {
+    ValueType::Object
+  }
+}
+
+impl ValidateNapiValue for HostKeyEntry {}
+
+impl FromNapiValue for HostKeyEntry {
+  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+    if let Ok(key) = Reference::<PublicKey>::from_napi_value(env, napi_val) {
+      return Ok(Self {
+        key: key.inner.clone(),
+        revoked: false,
+      });
+    }
+    let entry = Object::from_napi_value(env, napi_val)?;
+    let key = entry
+      .get_named_property::<Unknown>("key")
+      .and_then(|key| Reference::<PublicKey>::from_napi_value(env, key.raw()))
+      .map_err(|_| {
+        Error::new(
+          Status::InvalidArg,
+          "A host key entry must be a PublicKey or { key: PublicKey, revoked?: boolean }"
+            .to_owned(),
+        )
+      })?;
+    Ok(Self {
+      key: key.inner.clone(),
+      revoked: entry
+        .get_named_property::<Option<bool>>("revoked")?
+        .unwrap_or(false),
+    })
+  }
+}
+
+/// What `lookup` returned. Anything but keys or a promise is kept as an error, as a failed
+/// conversion of a threadsafe function's return value can not be caught.
+pub enum LookupResult {
+  Entries(Vec<HostKeyEntry>),
+  Pending(Promise<Vec<HostKeyEntry>>),
+  Invalid(Error),
+}
+
+impl TypeName for LookupResult {
+  fn type_name() -> &'static str {
+    "LookupResult"
+  }
+
+  fn value_type() -> ValueType {
+    ValueType::Unknown
+  }
+}
+
+impl FromNapiValue for LookupResult {
+  unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+    let mut is_promise = false;
+    check_status!(sys::napi_is_promise(env, napi_val, &mut is_promise))?;
+    if is_promise {
+      return Ok(Self::Pending(Promise::from_napi_value(env, napi_val)?));
+    }
+    Ok(match Vec::<HostKeyEntry>::from_napi_value(env, napi_val) {
+      Ok(entries) => Self::Entries(entries),
+      Err(err) => Self::Invalid(err),
+    })
+  }
+}
+
+impl HostKeyStore {
+  pub(crate) async fn verify(
+    &self,
+    host: &str,
+    port: u16,
+    host_key: &key::PublicKey,
+  ) -> Result<HostKeyStatus> {
+    let entries = match self
+      .lookup
+      .call_async(FnArgs::from((host.to_owned(), port as u32)))
+      .await?
+    {
+      LookupResult::Entries(entries) => Ok(entries),
+      LookupResult::Pending(entries) => entries.await,
+      LookupResult::Invalid(err) => Err(err),
+    }
+    // taking this for no keys would let `trustOnFirstUse` accept and store any key
+    .map_err(|err| {
+      Error::new(
+        Status::InvalidArg,
+        format!(
+          "The host key store lookup returned an invalid value: {}",
+          err.reason
+        ),
+      )
+    })?;
+    // compare the blobs, the signature hash of an RSA key does not change the key
+    let blob = host_key.public_key_bytes();
+    let matches = |entry: &HostKeyEntry| entry.key.public_key_bytes() == blob;
+    Ok(
+      if entries.iter().any(|entry| entry.revoked && matches(entry)) {
+        HostKeyStatus::Revoked
+      } else if entries.iter().any(matches) {
+        HostKeyStatus::Known
+      } else if entries.iter().any(|entry| !entry.revoked) {
+        HostKeyStatus::Changed
+      } else {
+        HostKeyStatus::Unknown
+      },
+    )
+  }
+
+  pub(crate) async fn learn(&self, host: &str, port: u16, host_key: &key::PublicKey) -> Result<()> {
+    if let Some(store) = &self.store {
+      let stored = store
+        .call_async(FnArgs::from((
+          host.to_owned(),
+          port as u32,
+          PublicKey::new(host_key.clone()),
+        )))
+        .await?;
+      if let Either3::B(promise) = stored {
+        promise.await?;
+      }
+    }
+    Ok(())
+  }
+}
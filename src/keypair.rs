@@ -1,12 +1,12 @@
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
-use russh_keys::known_hosts::learn_known_hosts_path;
+use russh_keys::{known_hosts::learn_known_hosts_path, PublicKeyBase64};
 
 use crate::{err::IntoError, signature::Signature};
 
 #[napi]
 /// The hash function used for signing with RSA keys.
-#[derive(Eq, PartialEq, Debug, Hash)]
+#[derive(Eq, PartialEq, Debug, Hash, Clone, Copy)]
 #[allow(non_camel_case_types)]
 pub enum SignatureHash {
   /// SHA2, 256 bits.
@@ -29,7 +29,7 @@ impl From<SignatureHash> for russh_keys::key::SignatureHash {
 
 #[napi]
 pub struct PublicKey {
-  inner: russh_keys::key::PublicKey,
+  pub(crate) inner: russh_keys::key::PublicKey,
 }
 
 #[napi]
@@ -38,6 +38,32 @@ impl PublicKey {
     Self { inner }
   }
 
+  #[napi(factory)]
+  /// Parse a key in the OpenSSH format, `ssh-ed25519 AAAA... comment`.
+  /// The key type and the comment are optional.
+  pub fn parse(key: String) -> Result<Self> {
+    let mut fields = key.split_whitespace();
+    let first = fields.next().unwrap_or_default();
+    let base64 = if first.starts_with("AAAA") {
+      first
+    } else {
+      fields.next().unwrap_or_default()
+    };
+    russh_keys::parse_public_key_base64(base64)
+      .map(Self::new)
+      .map_err(|err| Error::new(Status::InvalidArg, format!("Invalid public key: {err}")))
+  }
+
+  #[napi]
+  /// Format the key as `type base64`, the OpenSSH format without a comment.
+  pub fn to_open_ssh(&self) -> String {
+    let name = match self.inner {
+      russh_keys::key::PublicKey::RSA { .. } => "ssh-rsa",
+      _ => self.inner.name(),
+    };
+    format!("{name} {}", self.inner.public_key_base64())
+  }
+
   #[napi]
   pub fn name(&self) -> String {
     self.inner.name().to_string()
@@ -95,6 +121,20 @@ impl KeyPair {
     })
   }
 
+  #[napi(factory)]
+  /// Decode a private key from its contents rather than a file, e.g. one kept in an
+  /// environment variable or a secret store. Takes the same formats as the constructor.
+  pub fn from_pem(content: Either<String, Buffer>, passphrase: Option<String>) -> Result<Self> {
+    let content = match content {
+      Either::A(content) => content,
+      Either::B(content) => String::from_utf8(content.to_vec())
+        .map_err(|_| Error::new(Status::InvalidArg, "The key is not UTF-8 text".to_owned()))?,
+    };
+    Ok(Self {
+      inner: russh_keys::decode_secret_key(&content, passphrase.as_deref()).into_error()?,
+    })
+  }
+
   #[napi]
   pub fn clone_public_key(&self) -> Result<PublicKey> {
     self
@@ -1,7 +1,17 @@
 #![deny(clippy::all)]
 #![allow(clippy::type_complexity)]
 
+mod abort;
+pub mod agent;
+pub mod channel;
+pub mod child_process;
 pub mod client;
 mod err;
+pub mod host_key_store;
 pub mod keypair;
+pub mod scp;
+pub mod server;
+pub mod sftp;
 pub mod signature;
+pub mod sshfp;
+mod throttle;
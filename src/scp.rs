@@ -0,0 +1,462 @@
+use std::{
+  collections::VecDeque,
+  future::Future,
+  path::{Path, PathBuf},
+  pin::Pin,
+};
+
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use napi_derive::napi;
+use russh::{client, ChannelMsg};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+  client::{quote_argv, SharedHandle, ShellDialect},
+  err::IntoError,
+  sftp::{local_attributes, Progress},
+  throttle::Throttle,
+};
+
+/// How many bytes of a file are sent or written at once.
+const CHUNK_SIZE: usize = 32768;
+
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+pub struct ScpOptions {
+  /// Give the copy the permissions and the access and modification times of the original, like
+  /// `scp -p`.
+  pub preserve: Option<bool>,
+  /// Copy directories with their contents, like `scp -r`. Without it, copying a directory fails.
+  pub recursive: Option<bool>,
+  /// Called after every chunk with the bytes of the current file copied so far and its size.
+  #[napi(ts_type = "(transferred: bigint, total: bigint) => void")]
+  pub on_progress: Option<
+    ThreadsafeFunction<FnArgs<(BigInt, BigInt)>, (), FnArgs<(BigInt, BigInt)>, Status, false>,
+  >,
+  /// Keep the copy below this rate, averaged over about a chunk.
+  pub max_bytes_per_second: Option<u32>,
+}
+
+impl ScpOptions {
+  /// Pass the progress of a file to `onProgress`, if set.
+  pub(crate) fn report(&self, transferred: u64, total: u64) {
+    if let Some(on_progress) = &self.on_progress {
+      on_progress.call(
+        FnArgs::from((BigInt::from(transferred), BigInt::from(total))),
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }
+  }
+}
+
+/// A running `scp` on the other end of a channel, speaking the protocol of `scp -t` and `scp -f`
+/// over its stdin and stdout.
+struct Scp {
+  channel: russh::Channel<client::Msg>,
+  /// What came from stdout and was not read yet.
+  received: VecDeque<u8>,
+  stderr: Vec<u8>,
+  exit_status: Option<u32>,
+  /// Bounds the rate of file contents, in either direction.
+  throttle: Option<Throttle>,
+}
+
+impl Scp {
+  /// Run `scp` with `args` on a new session channel of `handle`.
+  async fn start(handle: &SharedHandle, args: Vec<String>, options: &ScpOptions) -> Result<Self> {
+    let throttle = Throttle::new(options.max_bytes_per_second, CHUNK_SIZE as u32)?;
+    let mut argv = vec!["scp".to_owned()];
+    argv.extend(args);
+    let command = quote_argv(&argv, ShellDialect::Posix)?;
+    let channel = handle
+      .read()
+      .await
+      .channel_open_session()
+      .await
+      .into_error()?;
+    channel.exec(true, command).await.into_error()?;
+    Ok(Self {
+      channel,
+      received: VecDeque::new(),
+      stderr: Vec::new(),
+      exit_status: None,
+      throttle,
+    })
+  }
+
+  /// Wait for more of stdout. False once the channel closed.
+  async fn fill(&mut self) -> Result<bool> {
+    while let Some(msg) = self.channel.wait().await {
+      match msg {
+        ChannelMsg::Data { data } => {
+          self.received.extend(&data[..]);
+          return Ok(true);
+        }
+        ChannelMsg::ExtendedData { data, .. } => self.stderr.extend_from_slice(&data),
+        ChannelMsg::ExitStatus { exit_status } => self.exit_status = Some(exit_status),
+        ChannelMsg::Failure => {
+          return Err(Error::new(
+            Status::GenericFailure,
+            "The server refused to run scp".to_owned(),
+          ))
+        }
+        _ => {}
+      }
+    }
+    Ok(false)
+  }
+
+  /// The error for a channel that closed in the middle of a transfer.
+  fn ended(&self) -> Error {
+    let stderr = String::from_utf8_lossy(&self.stderr);
+    let reason = match self.exit_status {
+      Some(status) => format!("scp exited with status {status}"),
+      None => "scp ended early".to_owned(),
+    };
+    Error::new(
+      Status::GenericFailure,
+      format!("{reason}: {}", stderr.trim_end()),
+    )
+  }
+
+  async fn read_byte(&mut self) -> Result<Option<u8>> {
+    while self.received.is_empty() {
+      if !self.fill().await? {
+        return Ok(None);
+      }
+    }
+    Ok(self.received.pop_front())
+  }
+
+  /// Read up to the next newline, without it.
+  async fn read_line(&mut self) -> Result<String> {
+    let mut line = Vec::new();
+    loop {
+      match self.read_byte().await? {
+        Some(b'\n') => return Ok(String::from_utf8_lossy(&line).into_owned()),
+        Some(byte) => line.push(byte),
+        None => return Err(self.ended()),
+      }
+    }
+  }
+
+  /// Read `length` bytes of a file into `file`, passing the bytes read so far to `progress`.
+  async fn read_into(
+    &mut self,
+    file: &mut tokio::fs::File,
+    length: u64,
+    progress: Progress<'_>,
+  ) -> Result<()> {
+    let mut read = 0;
+    while read < length {
+      if self.received.is_empty() && !self.fill().await? {
+        return Err(self.ended());
+      }
+      let available = self
+        .received
+        .len()
+        .min(CHUNK_SIZE)
+        .min((length - read) as usize);
+      let chunk = self.received.drain(..available).collect::<Vec<_>>();
+      Throttle::take_from(&mut self.throttle, available).await;
+      file.write_all(&chunk).await.map_err(local_error)?;
+      read += available as u64;
+      progress(read, length);
+    }
+    Ok(())
+  }
+
+  async fn write(&self, data: &[u8]) -> Result<()> {
+    self.channel.data(data).await.into_error()
+  }
+
+  /// Wait for the other end to accept what was sent last. It answers with a `0` byte, or with
+  /// `1` for a warning or `2` for an error followed by a message.
+  async fn expect_ok(&mut self) -> Result<()> {
+    match self.read_byte().await? {
+      Some(0) => Ok(()),
+      Some(1 | 2) => {
+        let message = self.read_line().await?;
+        Err(Error::new(Status::GenericFailure, message))
+      }
+      Some(byte) => Err(unexpected(byte)),
+      None => Err(self.ended()),
+    }
+  }
+
+  /// Close stdin and wait for `scp` to exit with status 0.
+  async fn finish(mut self) -> Result<()> {
+    self.channel.eof().await.into_error()?;
+    while self.fill().await? {}
+    match self.exit_status {
+      Some(0) => Ok(()),
+      _ => Err(self.ended()),
+    }
+  }
+}
+
+fn local_error(err: std::io::Error) -> Error {
+  Error::new(Status::GenericFailure, format!("Failed to copy: {err}"))
+}
+
+fn unexpected(byte: u8) -> Error {
+  Error::new(
+    Status::GenericFailure,
+    format!("scp sent an unexpected message starting with {byte:#04x}"),
+  )
+}
+
+/// The arguments asking `scp` for `mode`, `-t` to receive or `-f` to send, on `path`.
+fn scp_args(mode: &str, path: &str, options: &ScpOptions) -> Vec<String> {
+  let mut args = vec![mode.to_owned()];
+  if options.preserve.unwrap_or(false) {
+    args.push("-p".to_owned());
+  }
+  if options.recursive.unwrap_or(false) {
+    args.push("-r".to_owned());
+  }
+  if path.starts_with('-') {
+    args.push("--".to_owned());
+  }
+  args.push(path.to_owned());
+  args
+}
+
+/// Copy the local file or directory at `local_path` to `remote_path` by running `scp -t`,
+/// passing the bytes of the current file sent so far and its size to `progress`.
+pub(crate) async fn upload(
+  handle: &SharedHandle,
+  local_path: String,
+  remote_path: String,
+  options: &ScpOptions,
+  progress: Progress<'_>,
+) -> Result<()> {
+  let metadata = tokio::fs::metadata(&local_path)
+    .await
+    .map_err(local_error)?;
+  if metadata.is_dir() && !options.recursive.unwrap_or(false) {
+    return Err(Error::new(
+      Status::InvalidArg,
+      format!("{local_path} is a directory, copying it takes recursive"),
+    ));
+  }
+  let path = Path::new(&local_path);
+  let name = match path.file_name() {
+    Some(name) => name.to_owned(),
+    None => tokio::fs::canonicalize(path)
+      .await
+      .map_err(local_error)?
+      .file_name()
+      .map(ToOwned::to_owned)
+      .unwrap_or_default(),
+  };
+  let mut scp = Scp::start(handle, scp_args("-t", &remote_path, options), options).await?;
+  scp.expect_ok().await?;
+  send(
+    &mut scp,
+    path.to_owned(),
+    name.to_string_lossy().into_owned(),
+    options.preserve.unwrap_or(false),
+    progress,
+  )
+  .await?;
+  scp.finish().await
+}
+
+/// Send the file or directory at `path` as `name`, a directory with its contents.
+fn send<'a>(
+  scp: &'a mut Scp,
+  path: PathBuf,
+  name: String,
+  preserve: bool,
+  progress: Progress<'a>,
+) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+  Box::pin(async move {
+    let metadata = tokio::fs::metadata(&path).await.map_err(local_error)?;
+    let attrs = local_attributes(&metadata);
+    if preserve {
+      if let (Some(mtime), Some(atime)) = (attrs.mtime, attrs.atime) {
+        scp
+          .write(format!("T{mtime} 0 {atime} 0\n").as_bytes())
+          .await?;
+        scp.expect_ok().await?;
+      }
+    }
+    if metadata.is_dir() {
+      let mode = attrs.permissions.unwrap_or(0o755);
+      scp
+        .write(format!("D{mode:04o} 0 {name}\n").as_bytes())
+        .await?;
+      scp.expect_ok().await?;
+      let mut entries = tokio::fs::read_dir(&path).await.map_err(local_error)?;
+      let mut names = Vec::new();
+      while let Some(entry) = entries.next_entry().await.map_err(local_error)? {
+        names.push(entry.file_name());
+      }
+      names.sort();
+      for entry in names {
+        let entry_name = entry.to_string_lossy().into_owned();
+        send(scp, path.join(entry), entry_name, preserve, progress).await?;
+      }
+      scp.write(b"E\n").await?;
+      return scp.expect_ok().await;
+    }
+    let mode = attrs.permissions.unwrap_or(0o644);
+    let size = metadata.len();
+    scp
+      .write(format!("C{mode:04o} {size} {name}\n").as_bytes())
+      .await?;
+    scp.expect_ok().await?;
+    let mut file = tokio::fs::File::open(&path).await.map_err(local_error)?;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut sent = 0;
+    while sent < size {
+      let read = file.read(&mut chunk).await.map_err(local_error)?;
+      if read == 0 {
+        // the file shrank since its size was sent
+        return Err(Error::new(
+          Status::GenericFailure,
+          format!("{} changed while it was copied", path.display()),
+        ));
+      }
+      let read = read.min((size - sent) as usize);
+      Throttle::take_from(&mut scp.throttle, read).await;
+      scp.write(&chunk[..read]).await?;
+      sent += read as u64;
+      progress(sent, size);
+    }
+    scp.write(&[0]).await?;
+    scp.expect_ok().await
+  })
+}
+
+/// The mode, size and name of a `C` or `D` message of the source.
+fn parse_entry(line: &str) -> Option<(u32, u64, &str)> {
+  let mut parts = line.splitn(3, ' ');
+  let mode = u32::from_str_radix(parts.next()?, 8).ok()?;
+  let size = parts.next()?.parse().ok()?;
+  let name = parts.next()?;
+  // a name must not leave the directory being copied into
+  if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+    return None;
+  }
+  Some((mode, size, name))
+}
+
+/// The modification and access times of a `T` message of the source.
+fn parse_times(line: &str) -> Option<(u32, u32)> {
+  let mut parts = line.split(' ');
+  let mtime = parts.next()?.parse().ok()?;
+  parts.next()?;
+  let atime = parts.next()?.parse().ok()?;
+  Some((mtime, atime))
+}
+
+/// Give the local `path` the permissions `mode` and, if sent, the modification and access times.
+async fn apply(path: &Path, mode: u32, times: Option<(u32, u32)>) -> Result<()> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))
+      .await
+      .map_err(local_error)?;
+  }
+  #[cfg(not(unix))]
+  let _ = mode;
+  if let Some((mtime, atime)) = times {
+    let time =
+      |seconds: u32| std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds as u64);
+    let times = std::fs::FileTimes::new()
+      .set_modified(time(mtime))
+      .set_accessed(time(atime));
+    let file = std::fs::File::options()
+      .write(!path.is_dir())
+      .read(true)
+      .open(path)
+      .map_err(local_error)?;
+    file.set_times(times).map_err(local_error)?;
+  }
+  Ok(())
+}
+
+/// Copy the remote file or directory at `remote_path` to `local_path` by running `scp -f`. Into
+/// an existing local directory, the copy keeps its name. The bytes of the current file received
+/// so far and its size are passed to `progress`.
+pub(crate) async fn download(
+  handle: &SharedHandle,
+  remote_path: String,
+  local_path: String,
+  options: &ScpOptions,
+  progress: Progress<'_>,
+) -> Result<()> {
+  let preserve = options.preserve.unwrap_or(false);
+  let target = PathBuf::from(&local_path);
+  let into_dir = tokio::fs::metadata(&target)
+    .await
+    .is_ok_and(|metadata| metadata.is_dir());
+  let mut scp = Scp::start(handle, scp_args("-f", &remote_path, options), options).await?;
+  // the directories being received, with the times to give them once complete
+  let mut dirs: Vec<(PathBuf, u32, Option<(u32, u32)>)> = Vec::new();
+  let mut times = None;
+  scp.write(&[0]).await?;
+  while let Some(kind) = scp.read_byte().await? {
+    let line = match kind {
+      b'C' | b'D' | b'E' | b'T' => scp.read_line().await?,
+      1 | 2 => {
+        let message = scp.read_line().await?;
+        return Err(Error::new(Status::GenericFailure, message));
+      }
+      byte => return Err(unexpected(byte)),
+    };
+    match kind {
+      b'T' => {
+        times = Some(parse_times(&line).ok_or_else(|| malformed(&line))?);
+      }
+      b'E' => {
+        let (path, mode, dir_times) = dirs.pop().ok_or_else(|| malformed(&line))?;
+        if preserve {
+          apply(&path, mode, dir_times).await?;
+        }
+      }
+      _ => {
+        let (mode, size, name) = parse_entry(&line).ok_or_else(|| malformed(&line))?;
+        let path = match dirs.last() {
+          Some((dir, ..)) => dir.join(name),
+          None if into_dir => target.join(name),
+          None => target.clone(),
+        };
+        if kind == b'D' {
+          if let Err(err) = tokio::fs::create_dir(&path).await {
+            if err.kind() != std::io::ErrorKind::AlreadyExists {
+              return Err(local_error(err));
+            }
+          }
+          dirs.push((path, mode, times.take()));
+        } else {
+          let mut file = tokio::fs::File::create(&path).await.map_err(local_error)?;
+          scp.write(&[0]).await?;
+          scp.read_into(&mut file, size, progress).await?;
+          file.flush().await.map_err(local_error)?;
+          drop(file);
+          scp.expect_ok().await?;
+          let file_times = times.take();
+          if preserve {
+            apply(&path, mode, file_times).await?;
+          }
+        }
+      }
+    }
+    scp.write(&[0]).await?;
+  }
+  scp.finish().await
+}
+
+fn malformed(line: &str) -> Error {
+  Error::new(
+    Status::GenericFailure,
+    format!("scp sent a malformed message: {line}"),
+  )
+}
@@ -0,0 +1,1095 @@
+use std::{
+  borrow::Cow,
+  collections::HashMap,
+  io,
+  net::SocketAddr,
+  pin::Pin,
+  sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError},
+  task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode, UnknownReturnValue},
+};
+use napi_derive::napi;
+use russh::{
+  server::{self, Auth, Msg, Session},
+  Channel, ChannelId, CryptoVec, MethodSet, Pty, Sig,
+};
+use russh_keys::key;
+use tokio::{
+  io::{AsyncRead, AsyncWrite, ReadBuf},
+  net::{TcpListener, TcpStream},
+  sync::{mpsc, oneshot, watch},
+};
+
+use crate::{
+  client::DisconnectReason,
+  keypair::{KeyPair, PublicKey},
+};
+
+#[napi(object)]
+pub struct KeyboardInteractivePrompt {
+  pub prompt: String,
+  /// Whether the client should echo the answer as it is typed.
+  pub echo: Option<bool>,
+}
+
+#[napi(object)]
+/// A round of keyboard-interactive prompts.
+pub struct KeyboardInteractiveChallenge {
+  pub name: Option<String>,
+  pub instructions: Option<String>,
+  pub prompts: Vec<KeyboardInteractivePrompt>,
+}
+
+#[napi(object, object_to_js = false)]
+pub struct ServerOptions {
+  /// The address to bind. Defaults to `127.0.0.1`.
+  pub host: Option<String>,
+  /// The port to bind. Defaults to `0`, which picks a free port.
+  pub port: Option<u32>,
+  /// The host keys offered to clients, e.g. an ed25519 and an RSA key.
+  /// The client picks the first key type it prefers among them.
+  pub host_keys: Vec<Reference<KeyPair>>,
+  /// A message shown to clients before they authenticate.
+  pub banner: Option<String>,
+  /// Accept the `none` authentication method, letting any client in without credentials.
+  pub none_allowed: Option<bool>,
+  /// How many rejected authentication attempts a client gets before it is disconnected. Defaults to `6`.
+  pub max_auth_attempts: Option<u32>,
+  /// Check a password. The `password` method is only offered when this is set.
+  pub on_auth_password: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, String, ServerConnection)>,
+      Either3<bool, Promise<bool>, UnknownReturnValue>,
+      FnArgs<(String, String, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  /// Check a public key. Called both when the client asks whether a key would be accepted and
+  /// when it signs with it. The `publickey` method is only offered when this is set.
+  pub on_auth_public_key: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, PublicKey, ServerConnection)>,
+      Either3<bool, Promise<bool>, UnknownReturnValue>,
+      FnArgs<(String, PublicKey, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  /// Run a keyboard-interactive round. `responses` is `null` on the first round and holds the
+  /// answers to the previous challenge afterwards. Return a challenge to ask more questions,
+  /// or a boolean to accept or reject the client.
+  /// The `keyboard-interactive` method is only offered when this is set.
+  pub on_auth_keyboard_interactive: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, String, Either<Vec<String>, Null>, ServerConnection)>,
+      Either4<
+        bool,
+        Promise<Either<bool, KeyboardInteractiveChallenge>>,
+        KeyboardInteractiveChallenge,
+        UnknownReturnValue,
+      >,
+      FnArgs<(String, String, Either<Vec<String>, Null>, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  /// Called when a client runs a command on a session channel.
+  pub on_exec: Option<
+    ThreadsafeFunction<
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      (),
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  /// Called when a client starts a shell on a session channel.
+  pub on_shell: Option<
+    ThreadsafeFunction<
+      FnArgs<(ServerChannel, ServerConnection)>,
+      (),
+      FnArgs<(ServerChannel, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  /// Called when a client starts a subsystem on a session channel, with its name.
+  pub on_subsystem: Option<
+    ThreadsafeFunction<
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      (),
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+}
+
+/// Callbacks shared by every connection of a server.
+struct ServerCallbacks {
+  none_allowed: bool,
+  max_auth_attempts: usize,
+  on_auth_password: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, String, ServerConnection)>,
+      Either3<bool, Promise<bool>, UnknownReturnValue>,
+      FnArgs<(String, String, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  on_auth_public_key: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, PublicKey, ServerConnection)>,
+      Either3<bool, Promise<bool>, UnknownReturnValue>,
+      FnArgs<(String, PublicKey, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  on_auth_keyboard_interactive: Option<
+    ThreadsafeFunction<
+      FnArgs<(String, String, Either<Vec<String>, Null>, ServerConnection)>,
+      Either4<
+        bool,
+        Promise<Either<bool, KeyboardInteractiveChallenge>>,
+        KeyboardInteractiveChallenge,
+        UnknownReturnValue,
+      >,
+      FnArgs<(String, String, Either<Vec<String>, Null>, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  on_exec: Option<
+    ThreadsafeFunction<
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      (),
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  on_shell: Option<
+    ThreadsafeFunction<
+      FnArgs<(ServerChannel, ServerConnection)>,
+      (),
+      FnArgs<(ServerChannel, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+  on_subsystem: Option<
+    ThreadsafeFunction<
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      (),
+      FnArgs<(ServerChannel, String, ServerConnection)>,
+      Status,
+      false,
+    >,
+  >,
+}
+
+#[napi]
+/// An SSH server running on the Node.js tokio runtime, mostly useful as a test fixture.
+pub struct SshServer {
+  local_addr: SocketAddr,
+  shutdown: watch::Sender<bool>,
+}
+
+#[napi]
+impl SshServer {
+  #[napi]
+  /// Bind `host:port` and start accepting connections.
+  pub fn listen(env: &Env, options: ServerOptions) -> Result<PromiseRaw<'_, SshServer>> {
+    if options.host_keys.is_empty() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "At least one host key is required".to_owned(),
+      ));
+    }
+    let mut methods = MethodSet::empty();
+    if options.none_allowed == Some(true) {
+      methods |= MethodSet::NONE;
+    }
+    if options.on_auth_password.is_some() {
+      methods |= MethodSet::PASSWORD;
+    }
+    if options.on_auth_public_key.is_some() {
+      methods |= MethodSet::PUBLICKEY;
+    }
+    if options.on_auth_keyboard_interactive.is_some() {
+      methods |= MethodSet::KEYBOARD_INTERACTIVE;
+    }
+    let mut keys = Vec::with_capacity(options.host_keys.len());
+    for keypair in options.host_keys.iter() {
+      // russh advertises a host key algorithm per key pair, so an RSA key is offered with both
+      // SHA-2 signature algorithms
+      let variants = match &keypair.inner {
+        key::KeyPair::RSA { .. } => vec![
+          keypair.inner.clone(),
+          keypair
+            .inner
+            .with_signature_hash(key::SignatureHash::SHA2_512)
+            .unwrap_or_else(|| keypair.inner.clone()),
+          keypair
+            .inner
+            .with_signature_hash(key::SignatureHash::SHA2_256)
+            .unwrap_or_else(|| keypair.inner.clone()),
+        ],
+        _ => vec![keypair.inner.clone()],
+      };
+      for variant in variants {
+        if !keys
+          .iter()
+          .any(|key: &key::KeyPair| key.name() == variant.name())
+        {
+          keys.push(variant);
+        }
+      }
+    }
+    let config = server::Config {
+      keys,
+      // russh wants a `&'static str`; the banner lives as long as the process, which is fine for
+      // the handful of servers a process starts
+      auth_banner: options
+        .banner
+        .map(|banner| &*Box::leak(banner.into_boxed_str())),
+      methods,
+      max_auth_attempts: options.max_auth_attempts.unwrap_or(6) as usize,
+      ..Default::default()
+    };
+    let addr = format!(
+      "{}:{}",
+      options.host.as_deref().unwrap_or("127.0.0.1"),
+      options.port.unwrap_or(0)
+    );
+    let callbacks = Arc::new(ServerCallbacks {
+      none_allowed: options.none_allowed == Some(true),
+      max_auth_attempts: config.max_auth_attempts,
+      on_auth_password: options.on_auth_password,
+      on_auth_public_key: options.on_auth_public_key,
+      on_auth_keyboard_interactive: options.on_auth_keyboard_interactive,
+      on_exec: options.on_exec,
+      on_shell: options.on_shell,
+      on_subsystem: options.on_subsystem,
+    });
+    env.spawn_future(async move {
+      let listener = TcpListener::bind(&addr)
+        .await
+        .map_err(|err| Error::new(Status::GenericFailure, format!("Bind {addr} failed: {err}")))?;
+      let local_addr = listener.local_addr()?;
+      let (shutdown, shutdown_rx) = watch::channel(false);
+      tokio::spawn(accept_loop(
+        listener,
+        Arc::new(config),
+        callbacks,
+        shutdown_rx,
+      ));
+      Ok(SshServer {
+        local_addr,
+        shutdown,
+      })
+    })
+  }
+
+  #[napi(getter)]
+  pub fn host(&self) -> String {
+    self.local_addr.ip().to_string()
+  }
+
+  #[napi(getter)]
+  /// The bound port, useful when listening on port `0`.
+  pub fn port(&self) -> u32 {
+    self.local_addr.port() as u32
+  }
+
+  #[napi]
+  /// Stop accepting connections and disconnect every connected client.
+  pub fn close(&self) {
+    self.shutdown.send_replace(true);
+  }
+}
+
+async fn accept_loop(
+  listener: TcpListener,
+  config: Arc<server::Config>,
+  callbacks: Arc<ServerCallbacks>,
+  mut shutdown: watch::Receiver<bool>,
+) {
+  loop {
+    tokio::select! {
+      accepted = listener.accept() => {
+        if let Ok((socket, remote_addr)) = accepted {
+          let connection = Arc::new(ConnectionInfo {
+            remote_addr,
+            client_version: OnceLock::new(),
+            handle: OnceLock::new(),
+          });
+          tokio::spawn(serve_connection(
+            config.clone(),
+            RecordingStream {
+              inner: socket,
+              connection: connection.clone(),
+              first_line: Vec::new(),
+            },
+            ServerHandler::new(callbacks.clone(), config.methods, connection),
+            shutdown.clone(),
+          ));
+        }
+      }
+      true = closed(&mut shutdown) => break,
+    }
+  }
+}
+
+/// Resolves to `true` once `close` is called, or `false` if the server was dropped without closing.
+async fn closed(shutdown: &mut watch::Receiver<bool>) -> bool {
+  shutdown.wait_for(|closed| *closed).await.is_ok()
+}
+
+async fn serve_connection(
+  config: Arc<server::Config>,
+  socket: RecordingStream,
+  handler: ServerHandler,
+  mut shutdown: watch::Receiver<bool>,
+) {
+  let connection = handler.connection.clone();
+  let Ok(session) = server::run_stream(config, socket, handler).await else {
+    return;
+  };
+  let handle = session.handle();
+  let _ = connection.handle.set(handle.clone());
+  tokio::select! {
+    _ = session => {}
+    true = closed(&mut shutdown) => {
+      let _ = handle
+        .disconnect(
+          russh::Disconnect::ByApplication,
+          "Server is shutting down".to_owned(),
+          String::new(),
+        )
+        .await;
+    }
+  }
+}
+
+struct ServerHandler {
+  callbacks: Arc<ServerCallbacks>,
+  /// The methods offered again after a rejection, so that a client may retry.
+  methods: MethodSet,
+  auth_rejections: usize,
+  connection: Arc<ConnectionInfo>,
+  channels: HashMap<ChannelId, Arc<ChannelEvents>>,
+}
+
+impl ServerHandler {
+  fn new(
+    callbacks: Arc<ServerCallbacks>,
+    methods: MethodSet,
+    connection: Arc<ConnectionInfo>,
+  ) -> Self {
+    Self {
+      callbacks,
+      methods,
+      auth_rejections: 0,
+      connection,
+      channels: HashMap::new(),
+    }
+  }
+
+  fn connection(&self) -> ServerConnection {
+    ServerConnection {
+      info: self.connection.clone(),
+    }
+  }
+
+  fn accept_or_reject(&mut self, accepted: bool) -> Auth {
+    if accepted {
+      return Auth::Accept;
+    }
+    self.auth_rejections += 1;
+    if self.auth_rejections < self.callbacks.max_auth_attempts {
+      return Auth::Reject {
+        proceed_with_methods: Some(self.methods),
+      };
+    }
+    // russh does not enforce `max_auth_attempts` itself
+    if let Some(handle) = self.connection.handle.get().cloned() {
+      tokio::spawn(async move {
+        let _ = handle
+          .disconnect(
+            russh::Disconnect::NoMoreAuthMethodsAvailable,
+            "Too many authentication failures".to_owned(),
+            String::new(),
+          )
+          .await;
+      });
+    }
+    Auth::Reject {
+      proceed_with_methods: Some(MethodSet::empty()),
+    }
+  }
+}
+
+async fn resolve_auth(result: Either3<bool, Promise<bool>, UnknownReturnValue>) -> Result<bool> {
+  match result {
+    Either3::A(accepted) => Ok(accepted),
+    Either3::B(promise) => promise.await,
+    Either3::C(_) => Ok(false),
+  }
+}
+
+impl From<KeyboardInteractiveChallenge> for Auth {
+  fn from(challenge: KeyboardInteractiveChallenge) -> Self {
+    Auth::Partial {
+      name: Cow::Owned(challenge.name.unwrap_or_default()),
+      instructions: Cow::Owned(challenge.instructions.unwrap_or_default()),
+      prompts: challenge
+        .prompts
+        .into_iter()
+        .map(|prompt| (Cow::Owned(prompt.prompt), prompt.echo.unwrap_or(false)))
+        .collect::<Vec<_>>()
+        .into(),
+    }
+  }
+}
+
+#[async_trait]
+impl server::Handler for ServerHandler {
+  type Error = anyhow::Error;
+
+  async fn auth_none(&mut self, _user: &str) -> std::result::Result<Auth, Self::Error> {
+    // clients probe with `none` to learn the methods, so this does not count as a failed attempt
+    if self.callbacks.none_allowed {
+      Ok(Auth::Accept)
+    } else {
+      Ok(Auth::Reject {
+        proceed_with_methods: Some(self.methods),
+      })
+    }
+  }
+
+  async fn auth_password(
+    &mut self,
+    user: &str,
+    password: &str,
+  ) -> std::result::Result<Auth, Self::Error> {
+    let accepted = match &self.callbacks.on_auth_password {
+      Some(on_auth_password) => {
+        resolve_auth(
+          on_auth_password
+            .call_async((user.to_owned(), password.to_owned(), self.connection()).into())
+            .await?,
+        )
+        .await?
+      }
+      None => false,
+    };
+    Ok(self.accept_or_reject(accepted))
+  }
+
+  async fn auth_publickey_offered(
+    &mut self,
+    user: &str,
+    public_key: &key::PublicKey,
+  ) -> std::result::Result<Auth, Self::Error> {
+    self.auth_publickey(user, public_key).await
+  }
+
+  async fn auth_publickey(
+    &mut self,
+    user: &str,
+    public_key: &key::PublicKey,
+  ) -> std::result::Result<Auth, Self::Error> {
+    let accepted = match &self.callbacks.on_auth_public_key {
+      Some(on_auth_public_key) => {
+        resolve_auth(
+          on_auth_public_key
+            .call_async(
+              (
+                user.to_owned(),
+                PublicKey::new(public_key.clone()),
+                self.connection(),
+              )
+                .into(),
+            )
+            .await?,
+        )
+        .await?
+      }
+      None => false,
+    };
+    Ok(self.accept_or_reject(accepted))
+  }
+
+  async fn auth_keyboard_interactive(
+    &mut self,
+    user: &str,
+    submethods: &str,
+    response: Option<server::Response<'async_trait>>,
+  ) -> std::result::Result<Auth, Self::Error> {
+    let Some(on_auth_keyboard_interactive) = &self.callbacks.on_auth_keyboard_interactive else {
+      return Ok(self.accept_or_reject(false));
+    };
+    let responses = match response {
+      Some(response) => Either::A(
+        response
+          .map(|answer| String::from_utf8_lossy(answer).into_owned())
+          .collect(),
+      ),
+      None => Either::B(Null),
+    };
+    let result = on_auth_keyboard_interactive
+      .call_async(
+        (
+          user.to_owned(),
+          submethods.to_owned(),
+          responses,
+          self.connection(),
+        )
+          .into(),
+      )
+      .await?;
+    let result = match result {
+      Either4::A(accepted) => Either::A(accepted),
+      Either4::B(promise) => promise.await?,
+      Either4::C(challenge) => Either::B(challenge),
+      Either4::D(_) => Either::A(false),
+    };
+    Ok(match result {
+      Either::A(accepted) => self.accept_or_reject(accepted),
+      Either::B(challenge) => challenge.into(),
+    })
+  }
+
+  async fn channel_open_session(
+    &mut self,
+    channel: Channel<Msg>,
+    _session: &mut Session,
+  ) -> std::result::Result<bool, Self::Error> {
+    // channel events are dispatched from the handler methods below, so the channel itself is
+    // not kept around
+    self.channels.insert(channel.id(), Default::default());
+    Ok(true)
+  }
+
+  async fn pty_request(
+    &mut self,
+    channel: ChannelId,
+    term: &str,
+    col_width: u32,
+    row_height: u32,
+    _pix_width: u32,
+    _pix_height: u32,
+    _modes: &[(Pty, u32)],
+    session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    // there is no real terminal behind the channel, but clients that insist on one should still
+    // get to run their command
+    if let Some(events) = self.channels.get(&channel) {
+      events.state().terminal = Some(Terminal {
+        term: term.to_owned(),
+        cols: col_width,
+        rows: row_height,
+      });
+    }
+    session.channel_success(channel);
+    Ok(())
+  }
+
+  async fn env_request(
+    &mut self,
+    channel: ChannelId,
+    variable_name: &str,
+    variable_value: &str,
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    if let Some(events) = self.channels.get(&channel) {
+      events
+        .state()
+        .env
+        .insert(variable_name.to_owned(), variable_value.to_owned());
+    }
+    Ok(())
+  }
+
+  async fn window_change_request(
+    &mut self,
+    channel: ChannelId,
+    col_width: u32,
+    row_height: u32,
+    _pix_width: u32,
+    _pix_height: u32,
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    if let Some(events) = self.channels.get(&channel) {
+      if let Some(terminal) = &mut events.state().terminal {
+        terminal.cols = col_width;
+        terminal.rows = row_height;
+      }
+    }
+    Ok(())
+  }
+
+  async fn exec_request(
+    &mut self,
+    channel: ChannelId,
+    data: &[u8],
+    session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    match (&self.callbacks.on_exec, self.channels.get(&channel)) {
+      (Some(on_exec), Some(events)) => {
+        session.channel_success(channel);
+        let server_channel = ServerChannel::new(channel, session.handle(), events.clone());
+        on_exec.call(
+          (
+            server_channel,
+            String::from_utf8_lossy(data).into_owned(),
+            self.connection(),
+          )
+            .into(),
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+      _ => session.channel_failure(channel),
+    }
+    Ok(())
+  }
+
+  async fn shell_request(
+    &mut self,
+    channel: ChannelId,
+    session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    match (&self.callbacks.on_shell, self.channels.get(&channel)) {
+      (Some(on_shell), Some(events)) => {
+        session.channel_success(channel);
+        let server_channel = ServerChannel::new(channel, session.handle(), events.clone());
+        on_shell.call(
+          (server_channel, self.connection()).into(),
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+      _ => session.channel_failure(channel),
+    }
+    Ok(())
+  }
+
+  async fn subsystem_request(
+    &mut self,
+    channel: ChannelId,
+    name: &str,
+    session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    match (&self.callbacks.on_subsystem, self.channels.get(&channel)) {
+      (Some(on_subsystem), Some(events)) => {
+        session.channel_success(channel);
+        let server_channel = ServerChannel::new(channel, session.handle(), events.clone());
+        on_subsystem.call(
+          (server_channel, name.to_owned(), self.connection()).into(),
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+      _ => session.channel_failure(channel),
+    }
+    Ok(())
+  }
+
+  async fn data(
+    &mut self,
+    channel: ChannelId,
+    data: &[u8],
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    if let Some(events) = self.channels.get(&channel) {
+      events.data(data);
+    }
+    Ok(())
+  }
+
+  async fn channel_eof(
+    &mut self,
+    channel: ChannelId,
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    if let Some(events) = self.channels.get(&channel) {
+      events.eof();
+    }
+    Ok(())
+  }
+
+  async fn channel_close(
+    &mut self,
+    channel: ChannelId,
+    _session: &mut Session,
+  ) -> std::result::Result<(), Self::Error> {
+    if let Some(events) = self.channels.remove(&channel) {
+      events.eof();
+    }
+    Ok(())
+  }
+}
+
+impl Drop for ServerHandler {
+  fn drop(&mut self) {
+    // release the JS listeners of channels that were never closed cleanly,
+    // or they will keep the Node.js process alive
+    for events in self.channels.values() {
+      events.eof();
+    }
+  }
+}
+
+/// What is known about a client connection.
+struct ConnectionInfo {
+  remote_addr: SocketAddr,
+  /// Recorded by `RecordingStream` while russh reads it.
+  client_version: OnceLock<String>,
+  /// Set once the session is running; the auth hooks have no access to the session otherwise.
+  handle: OnceLock<server::Handle>,
+}
+
+#[napi]
+/// The client connection a callback was invoked for.
+pub struct ServerConnection {
+  info: Arc<ConnectionInfo>,
+}
+
+#[napi]
+impl ServerConnection {
+  #[napi(getter)]
+  pub fn remote_address(&self) -> String {
+    self.info.remote_addr.ip().to_string()
+  }
+
+  #[napi(getter)]
+  pub fn remote_port(&self) -> u32 {
+    self.info.remote_addr.port() as u32
+  }
+
+  #[napi(getter)]
+  /// The identification string sent by the client, e.g. `SSH-2.0-OpenSSH_9.6`.
+  pub fn client_version(&self) -> Option<String> {
+    self.info.client_version.get().cloned()
+  }
+
+  #[napi]
+  /// Disconnect this client.
+  pub async fn disconnect(&self, reason: DisconnectReason, message: String) -> Result<()> {
+    let handle = self.info.handle.get().ok_or_else(|| {
+      Error::new(
+        Status::GenericFailure,
+        "Connection is not established yet".to_owned(),
+      )
+    })?;
+    handle
+      .disconnect(reason.into(), message, String::new())
+      .await
+      .map_err(|err| Error::new(Status::GenericFailure, format!("Disconnect failed: {err}")))
+  }
+}
+
+/// Passes the socket through to russh, recording the client identification line on the way.
+struct RecordingStream {
+  inner: TcpStream,
+  connection: Arc<ConnectionInfo>,
+  first_line: Vec<u8>,
+}
+
+impl AsyncRead for RecordingStream {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let filled = buf.filled().len();
+    let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+    if self.connection.client_version.get().is_none() {
+      let this = &mut *self;
+      // RFC 4253 caps the identification line at 255 bytes
+      let read = &buf.filled()[filled..];
+      let wanted = read.len().min(256 - this.first_line.len());
+      this.first_line.extend_from_slice(&read[..wanted]);
+      if let Some(end) = this.first_line.iter().position(|&byte| byte == b'\n') {
+        let line = String::from_utf8_lossy(&this.first_line[..end]);
+        let _ = this
+          .connection
+          .client_version
+          .set(line.trim_end_matches('\r').to_owned());
+        this.first_line = Vec::new();
+      } else if this.first_line.len() >= 256 {
+        let _ = this.connection.client_version.set(String::new());
+        this.first_line = Vec::new();
+      }
+    }
+    poll
+  }
+}
+
+impl AsyncWrite for RecordingStream {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<io::Result<usize>> {
+    Pin::new(&mut self.inner).poll_write(cx, buf)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_shutdown(cx)
+  }
+}
+
+/// Data received from the client, buffered until a listener is registered.
+#[derive(Default)]
+struct ChannelEvents(Mutex<ChannelEventsState>);
+
+#[derive(Default)]
+struct ChannelEventsState {
+  on_data: Option<ThreadsafeFunction<Buffer, (), Buffer, Status, false>>,
+  on_eof: Option<ThreadsafeFunction<(), (), (), Status, false>>,
+  pending: Vec<u8>,
+  eof: bool,
+  terminal: Option<Terminal>,
+  env: HashMap<String, String>,
+}
+
+impl ChannelEvents {
+  fn state(&self) -> MutexGuard<'_, ChannelEventsState> {
+    self.0.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  fn data(&self, data: &[u8]) {
+    let mut state = self.state();
+    match &state.on_data {
+      Some(on_data) => {
+        on_data.call(
+          data.to_vec().into(),
+          ThreadsafeFunctionCallMode::NonBlocking,
+        );
+      }
+      None => state.pending.extend_from_slice(data),
+    }
+  }
+
+  fn eof(&self) {
+    let mut state = self.state();
+    state.eof = true;
+    state.on_data = None;
+    if let Some(on_eof) = state.on_eof.take() {
+      on_eof.call((), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+  }
+
+  fn set_on_data(&self, on_data: ThreadsafeFunction<Buffer, (), Buffer, Status, false>) {
+    let mut state = self.state();
+    if !state.pending.is_empty() {
+      let pending = std::mem::take(&mut state.pending);
+      on_data.call(pending.into(), ThreadsafeFunctionCallMode::NonBlocking);
+    }
+    if !state.eof {
+      state.on_data = Some(on_data);
+    }
+  }
+
+  fn set_on_eof(&self, on_eof: ThreadsafeFunction<(), (), (), Status, false>) {
+    let mut state = self.state();
+    if state.eof {
+      on_eof.call((), ThreadsafeFunctionCallMode::NonBlocking);
+    } else {
+      state.on_eof = Some(on_eof);
+    }
+  }
+}
+
+#[napi(object)]
+#[derive(Clone)]
+/// A pseudo-terminal requested by the client.
+pub struct Terminal {
+  pub term: String,
+  pub cols: u32,
+  pub rows: u32,
+}
+
+enum Outgoing {
+  Data(Vec<u8>),
+  ExtendedData(u32, Vec<u8>),
+  ExitStatus(u32),
+  ExitSignal(String, bool, String),
+  Eof,
+  Close,
+}
+
+#[napi]
+/// A session channel opened by a client.
+pub struct ServerChannel {
+  id: ChannelId,
+  outgoing: mpsc::UnboundedSender<(Outgoing, oneshot::Sender<bool>)>,
+  events: Arc<ChannelEvents>,
+}
+
+#[napi]
+impl ServerChannel {
+  fn new(id: ChannelId, handle: server::Handle, events: Arc<ChannelEvents>) -> Self {
+    // every write is queued synchronously and sent by a single task, so the client sees them in
+    // the order they were issued from JavaScript even if the returned promises are not awaited
+    let (outgoing, mut queue) = mpsc::unbounded_channel::<(Outgoing, oneshot::Sender<bool>)>();
+    tokio::spawn(async move {
+      while let Some((message, sent)) = queue.recv().await {
+        let result = match message {
+          Outgoing::Data(data) => handle.data(id, CryptoVec::from(data)).await.is_ok(),
+          Outgoing::ExtendedData(ext, data) => handle
+            .extended_data(id, ext, CryptoVec::from(data))
+            .await
+            .is_ok(),
+          Outgoing::ExitStatus(status) => handle.exit_status_request(id, status).await.is_ok(),
+          Outgoing::ExitSignal(signal, core_dumped, message) => handle
+            .exit_signal_request(id, Sig::Custom(signal), core_dumped, message, String::new())
+            .await
+            .is_ok(),
+          Outgoing::Eof => handle.eof(id).await.is_ok(),
+          Outgoing::Close => handle.close(id).await.is_ok(),
+        };
+        let _ = sent.send(result);
+      }
+    });
+    Self {
+      id,
+      outgoing,
+      events,
+    }
+  }
+
+  fn send<'env>(&self, env: &'env Env, messages: Vec<Outgoing>) -> Result<PromiseRaw<'env, ()>> {
+    let sent = messages
+      .into_iter()
+      .map(|message| {
+        let (tx, rx) = oneshot::channel();
+        let _ = self.outgoing.send((message, tx));
+        rx
+      })
+      .collect::<Vec<_>>();
+    env.spawn_future(async move {
+      for rx in sent {
+        if rx.await != Ok(true) {
+          return Err(Error::new(
+            Status::GenericFailure,
+            "Channel is closed".to_owned(),
+          ));
+        }
+      }
+      Ok(())
+    })
+  }
+
+  #[napi(getter)]
+  pub fn id(&self) -> u32 {
+    self.id.into()
+  }
+
+  #[napi(getter)]
+  /// The environment variables the client set on the channel.
+  pub fn env(&self) -> HashMap<String, String> {
+    self.events.state().env.clone()
+  }
+
+  #[napi(getter)]
+  /// The terminal the client asked for, with its current size. `null` without a PTY.
+  pub fn terminal(&self) -> Option<Terminal> {
+    self.events.state().terminal.clone()
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Write to the client's stdout.
+  pub fn write<'env>(
+    &self,
+    env: &'env Env,
+    data: Either<String, Buffer>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, vec![Outgoing::Data(into_bytes(data))])
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Write to the client's stderr.
+  pub fn write_stderr<'env>(
+    &self,
+    env: &'env Env,
+    data: Either<String, Buffer>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, vec![Outgoing::ExtendedData(1, into_bytes(data))])
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Send the exit status of the command, then EOF, then close the channel.
+  pub fn exit<'env>(&self, env: &'env Env, status: u32) -> Result<PromiseRaw<'env, ()>> {
+    self.send(
+      env,
+      vec![Outgoing::ExitStatus(status), Outgoing::Eof, Outgoing::Close],
+    )
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Report that the command was killed by `signal`, named without the `SIG` prefix, then send
+  /// EOF and close the channel.
+  pub fn exit_signal<'env>(
+    &self,
+    env: &'env Env,
+    signal: String,
+    core_dumped: Option<bool>,
+    message: Option<String>,
+  ) -> Result<PromiseRaw<'env, ()>> {
+    self.send(
+      env,
+      vec![
+        Outgoing::ExitSignal(
+          signal,
+          core_dumped.unwrap_or(false),
+          message.unwrap_or_default(),
+        ),
+        Outgoing::Eof,
+        Outgoing::Close,
+      ],
+    )
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Signal that no more data will be sent.
+  pub fn eof<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, vec![Outgoing::Eof])
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  pub fn close<'env>(&self, env: &'env Env) -> Result<PromiseRaw<'env, ()>> {
+    self.send(env, vec![Outgoing::Eof, Outgoing::Close])
+  }
+
+  #[napi]
+  /// Listen for data sent by the client. Data received before a listener is registered is buffered.
+  pub fn on_data(&self, callback: ThreadsafeFunction<Buffer, (), Buffer, Status, false>) {
+    self.events.set_on_data(callback);
+  }
+
+  #[napi]
+  /// Listen for the client signalling EOF or closing the channel.
+  pub fn on_eof(&self, callback: ThreadsafeFunction<(), (), (), Status, false>) {
+    self.events.set_on_eof(callback);
+  }
+}
+
+pub(crate) fn into_bytes(data: Either<String, Buffer>) -> Vec<u8> {
+  match data {
+    Either::A(string) => string.into_bytes(),
+    Either::B(buffer) => buffer.to_vec(),
+  }
+}
@@ -0,0 +1,3250 @@
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+  fs::FileTimes,
+  future::Future,
+  io::SeekFrom,
+  path::Path,
+  pin::Pin,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc, Weak,
+  },
+  task::{Context, Poll},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{stream, stream::FuturesOrdered, FutureExt, StreamExt};
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use napi_derive::napi;
+use russh_sftp::{
+  client::{error::Error as SftpError, rawsession::Limits, RawSftpSession},
+  extensions::Statvfs,
+  protocol::{self, FileMode, OpenFlags, Packet, StatusCode},
+};
+use sha2::{Digest, Sha256};
+use tokio::{
+  io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+  sync::{Notify, RwLock},
+  time::Instant,
+};
+
+use crate::{
+  abort::Abort,
+  channel::wait_for_reply,
+  client::{exec_argv, SharedHandle},
+  err::{coded, Coded, IntoError},
+  server::into_bytes,
+  throttle::Throttle,
+};
+
+/// How many bytes one read or write request carries, as with OpenSSH's `sftp`.
+const CHUNK_SIZE: u32 = 32768;
+/// How many requests of a transfer may be in flight, as with OpenSSH's `sftp`.
+const MAX_INFLIGHT: u32 = 64;
+
+#[napi(object)]
+pub struct SftpOptions {
+  /// How long to wait for the server to answer a request before failing it with `TIMEOUT`. In
+  /// milliseconds, rounded up to whole seconds. Defaults to `10000`.
+  pub timeout_ms: Option<u32>,
+  /// Send a request whenever nothing was received from the server for this long, for servers
+  /// and middleboxes that drop idle sessions. In milliseconds.
+  pub keepalive_interval_ms: Option<u32>,
+  /// Start the subsystem again on a new channel when the server closes this one. Requests in
+  /// flight and open files fail, and later requests go to the new channel.
+  pub reopen: Option<bool>,
+}
+
+#[napi(object)]
+pub struct WriteFileOptions {
+  /// The permissions of the file if the write creates it. By default the server picks them.
+  pub mode: Option<u32>,
+  /// Have the server flush the file to disk before the write resolves. Takes the
+  /// `fsync@openssh.com` extension.
+  pub fsync: Option<bool>,
+}
+
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+pub struct TransferOptions {
+  /// Called after every chunk with the bytes transferred so far and the size of the file.
+  #[napi(ts_type = "(transferred: bigint, total: bigint) => void")]
+  pub on_progress: Option<
+    ThreadsafeFunction<FnArgs<(BigInt, BigInt)>, (), FnArgs<(BigInt, BigInt)>, Status, false>,
+  >,
+  /// Continue an interrupted transfer instead of starting over. Uploads keep the part of the
+  /// remote file already there, downloads the `.partial` file a failed download left next to
+  /// the local path. Only the length of that part is checked, not its content.
+  pub resume: Option<bool>,
+  /// How many bytes one read or write request carries, 32768 by default. It is lowered to what
+  /// the server accepts when it announces limits, see `Sftp.limits`.
+  pub chunk_size: Option<u32>,
+  /// How many requests may wait for the server's answer at once, 64 by default. More keep a
+  /// connection with a long round trip busy.
+  pub max_inflight: Option<u32>,
+  /// Keep the transfer below this rate, averaged over about a chunk.
+  pub max_bytes_per_second: Option<u32>,
+  /// Upload to a temporary file next to the remote path, named after it with `.partial-` and a
+  /// random suffix, then rename it into place, so the remote path never holds a partial upload.
+  /// Replacing an existing file this way takes the `posix-rename@openssh.com` extension.
+  pub atomic: Option<bool>,
+  /// Give the copy the permissions and the access and modification times of the original, like
+  /// `scp -p`. Windows has no permissions to take or give.
+  pub preserve: Option<bool>,
+  /// Flush the copy to disk before the transfer resolves. Uploads take the `fsync@openssh.com`
+  /// extension.
+  pub fsync: Option<bool>,
+  /// Split the file into up to this many ranges, each transferred over an SFTP session on a
+  /// channel of its own, for fast links where the flow control of one channel is the bottleneck.
+  /// A range gets at least `maxInflight` chunks, as a shorter one could not keep its channel
+  /// busy, and the ranges only go to as many channels as the server opens, e.g. within
+  /// OpenSSH's `MaxSessions`; with one range left, the file goes in a single stream. The size of
+  /// the copy is checked once all ranges are done. Ignored with `resume`. Defaults to `1`.
+  pub parallelism: Option<u32>,
+  /// Compare the sha256 hash of the copy with that of the original before the transfer
+  /// resolves, see `Sftp.checksum`.
+  pub verify_checksum: Option<bool>,
+}
+
+impl TransferOptions {
+  /// Pass the progress of a transfer to `onProgress`, if set.
+  fn report(&self, transferred: u64, total: u64) {
+    if let Some(on_progress) = &self.on_progress {
+      on_progress.call(
+        FnArgs::from((BigInt::from(transferred), BigInt::from(total))),
+        ThreadsafeFunctionCallMode::NonBlocking,
+      );
+    }
+  }
+
+  /// The chunk size, capped to `limit`, and the number of requests in flight.
+  fn pipelining(&self, limit: Option<u64>) -> Result<(u32, usize)> {
+    let chunk_size = capped(self.chunk_size.unwrap_or(CHUNK_SIZE), limit);
+    let max_inflight = self.max_inflight.unwrap_or(MAX_INFLIGHT);
+    if chunk_size == 0 || max_inflight == 0 {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "chunkSize and maxInflight must be positive".to_owned(),
+      ));
+    }
+    Ok((chunk_size, max_inflight as usize))
+  }
+}
+
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+  File,
+  Directory,
+  Symlink,
+  /// A device, FIFO or socket, or a file the server reported no type for.
+  Other,
+}
+
+#[napi(object)]
+/// What the server reported about a file. SFTP servers may leave any attribute out.
+pub struct FileAttributes {
+  pub file_type: FileType,
+  pub size: Option<BigInt>,
+  pub uid: Option<u32>,
+  pub gid: Option<u32>,
+  /// The permission bits, e.g. `0o644`.
+  pub permissions: Option<u32>,
+  /// The last access, in seconds since the Unix epoch.
+  pub atime: Option<u32>,
+  /// The last modification, in seconds since the Unix epoch.
+  pub mtime: Option<u32>,
+}
+
+impl From<protocol::FileAttributes> for FileAttributes {
+  fn from(attrs: protocol::FileAttributes) -> Self {
+    Self {
+      file_type: file_type(&attrs),
+      size: attrs.size.map(BigInt::from),
+      uid: attrs.uid,
+      gid: attrs.gid,
+      permissions: attrs.permissions.map(|mode| mode & 0o7777),
+      atime: attrs.atime,
+      mtime: attrs.mtime,
+    }
+  }
+}
+
+#[napi(object)]
+/// An entry of a remote directory.
+pub struct DirEntry {
+  pub name: String,
+  /// The line `ls -l` would print for the entry, as formatted by the server.
+  pub longname: String,
+  pub attributes: FileAttributes,
+}
+
+#[napi(object)]
+/// The attributes `FileHandle.setstat` changes. Unset ones are left as they are.
+pub struct SetStatOptions {
+  /// Truncate or extend the file to this many bytes.
+  pub size: Option<Either<f64, BigInt>>,
+  /// Set together with `gid`.
+  pub uid: Option<u32>,
+  pub gid: Option<u32>,
+  /// The permission bits, e.g. `0o644`.
+  pub mode: Option<u32>,
+  /// Set together with `mtime`, in seconds since the Unix epoch.
+  pub atime: Option<u32>,
+  pub mtime: Option<u32>,
+}
+
+impl TryFrom<SetStatOptions> for protocol::FileAttributes {
+  type Error = Error;
+
+  fn try_from(options: SetStatOptions) -> Result<Self> {
+    // the protocol sends these in pairs, so one alone would reset the other to 0
+    if options.uid.is_some() != options.gid.is_some() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "uid and gid must be set together".to_owned(),
+      ));
+    }
+    if options.atime.is_some() != options.mtime.is_some() {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "atime and mtime must be set together".to_owned(),
+      ));
+    }
+    Ok(Self {
+      size: options.size.map(|size| to_u64(size, "size")).transpose()?,
+      uid: options.uid,
+      gid: options.gid,
+      permissions: options.mode,
+      atime: options.atime,
+      mtime: options.mtime,
+      ..Self::empty()
+    })
+  }
+}
+
+#[napi(object)]
+pub struct ReadStreamOptions {
+  /// The offset to start reading at, `0` by default.
+  pub start: Option<Either<f64, BigInt>>,
+  /// The offset of the last byte to read, inclusive like in Node's `fs.createReadStream`. By
+  /// default the stream reads to the end of the file.
+  pub end: Option<Either<f64, BigInt>>,
+}
+
+#[napi(object)]
+pub struct WriteStreamOptions {
+  /// How to open the file, as in `Sftp.open`, `w` by default.
+  pub flags: Option<String>,
+  /// The permissions of the file if the open creates it.
+  pub mode: Option<u32>,
+  /// The offset to start writing at, `0` by default.
+  pub start: Option<Either<f64, BigInt>>,
+}
+
+#[napi(object)]
+/// The filesystem holding a remote path, as `statvfs` describes it. Block counts are in units of
+/// `fragmentSize`, so the bytes free for unprivileged users are `blocksAvailable * fragmentSize`.
+pub struct FilesystemStats {
+  pub block_size: f64,
+  pub fragment_size: f64,
+  pub blocks: f64,
+  pub blocks_free: f64,
+  /// The free blocks unprivileged users may use.
+  pub blocks_available: f64,
+  pub files: f64,
+  pub files_free: f64,
+  /// The free inodes unprivileged users may use.
+  pub files_available: f64,
+  /// The longest file name the filesystem allows.
+  pub name_max: f64,
+}
+
+impl From<Statvfs> for FilesystemStats {
+  fn from(stats: Statvfs) -> Self {
+    Self {
+      block_size: stats.block_size as f64,
+      fragment_size: stats.fragment_size as f64,
+      blocks: stats.blocks as f64,
+      blocks_free: stats.blocks_free as f64,
+      blocks_available: stats.blocks_avail as f64,
+      files: stats.inodes as f64,
+      files_free: stats.inodes_free as f64,
+      files_available: stats.inodes_avail as f64,
+      name_max: stats.name_max as f64,
+    }
+  }
+}
+
+#[napi(object)]
+/// The limits a server announced with `limits@openssh.com`. Each is unset when the server did
+/// not announce the extension or put no limit on it.
+pub struct SftpLimits {
+  /// The longest packet the server accepts, in bytes.
+  pub max_packet_length: Option<f64>,
+  /// The most bytes one read returns.
+  pub max_read_length: Option<f64>,
+  /// The most bytes one write accepts.
+  pub max_write_length: Option<f64>,
+  /// How many files may be open at once.
+  pub max_open_handles: Option<f64>,
+}
+
+#[napi(object)]
+pub struct ChecksumOptions {
+  /// `md5`, `sha1`, `sha224`, `sha256`, `sha384` or `sha512`, `sha256` by default.
+  pub algorithm: Option<String>,
+}
+
+#[napi(object)]
+pub struct GlobOptions {
+  /// The remote directory relative patterns are matched in, and the paths found are relative
+  /// to. By default the directory the session started in.
+  pub cwd: Option<String>,
+}
+
+#[napi(object)]
+/// An entry found by `Sftp.walk`.
+pub struct WalkEntry {
+  /// The path of the entry, the walked path joined with the names of the directories down to
+  /// it.
+  pub path: String,
+  pub attributes: FileAttributes,
+}
+
+#[napi(object)]
+pub struct MkdirOptions {
+  /// The permissions of the created directories. By default the server picks them.
+  pub mode: Option<u32>,
+  /// Create the missing parent directories too, and accept an existing directory, like
+  /// `mkdir -p`.
+  pub recursive: Option<bool>,
+}
+
+#[napi(object)]
+pub struct RmdirOptions {
+  /// Remove the contents of the directory first, like `rm -r`. Symlinks are removed, not
+  /// followed.
+  pub recursive: Option<bool>,
+}
+
+#[napi(object)]
+pub struct RmOptions {
+  /// Remove directories with their contents. Without it, directories are reported as errors.
+  pub recursive: Option<bool>,
+}
+
+#[napi(object)]
+/// The outcome of removing one path with `Sftp.rm`.
+pub struct RmResult {
+  pub path: String,
+  /// Why the path could not be removed, unset if it was.
+  pub error: Option<String>,
+}
+
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What `Sftp.sync` does to a remote path.
+pub enum SyncAction {
+  /// Create the directory.
+  Mkdir,
+  /// Upload the local file, missing or changed on the server.
+  Upload,
+  /// Remove the path, with its contents if it is a directory.
+  Delete,
+}
+
+#[napi(object)]
+pub struct SyncOptions {
+  /// Remove the remote files and directories the local directory does not have, like
+  /// `rsync --delete`, and replace a remote directory by a local file of the same name or the
+  /// other way around. Without it, such a mismatch fails the sync.
+  pub delete: Option<bool>,
+  /// How to tell a file changed. `'mtime-size'`, the default, compares the sizes and
+  /// modification times; `'checksum'` compares the sizes and the sha256 hashes, see
+  /// `Sftp.checksum`.
+  #[napi(ts_type = "'mtime-size' | 'checksum'")]
+  pub compare: Option<String>,
+  /// Only work out the changes, without making them.
+  pub dry_run: Option<bool>,
+}
+
+#[napi(object)]
+/// A change `Sftp.sync` makes to the remote directory.
+pub struct SyncChange {
+  pub action: SyncAction,
+  /// The path relative to the synchronized directories, with `/` between components, empty for
+  /// the remote directory itself.
+  pub path: String,
+}
+
+#[napi(object, object_to_js = false)]
+pub struct TailOptions {
+  /// Called with each line, without its line ending.
+  #[napi(ts_type = "(line: string) => void")]
+  pub on_line: ThreadsafeFunction<String, (), String, Status, false>,
+  /// How often to check the file for appended data, in milliseconds, 1000 by default.
+  pub poll_interval_ms: Option<u32>,
+  /// Start at the end of the file and keep passing the lines appended to it, like `tail -f`,
+  /// until `signal` aborts. Without it, the lines already in the file are passed.
+  pub follow: Option<bool>,
+  /// Stop following the file, resolving `Sftp.tail`.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<Abort>,
+}
+
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+  Upload,
+  Download,
+}
+
+#[napi(object, object_to_js = false)]
+/// One upload or download of `Sftp.transfer`.
+pub struct TransferJob {
+  pub direction: TransferDirection,
+  pub local_path: String,
+  pub remote_path: String,
+  /// As for `Sftp.upload` and `Sftp.download`, with `onProgress` called for this job alone.
+  pub options: Option<TransferOptions>,
+}
+
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+pub struct TransferQueueOptions {
+  /// How many jobs run at once, 4 by default.
+  pub concurrency: Option<u32>,
+  /// Called as any job moves a chunk, with the bytes all jobs transferred so far and the size of
+  /// all their files.
+  #[napi(ts_type = "(transferred: bigint, total: bigint) => void")]
+  pub on_progress: Option<
+    ThreadsafeFunction<FnArgs<(BigInt, BigInt)>, (), FnArgs<(BigInt, BigInt)>, Status, false>,
+  >,
+  /// Called as each job starts and ends.
+  #[napi(ts_type = "(event: TransferEvent) => void")]
+  pub on_job: Option<ThreadsafeFunction<TransferEvent, (), TransferEvent, Status, false>>,
+}
+
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferState {
+  Started,
+  Finished,
+  Failed,
+}
+
+#[napi(object)]
+/// A job of `Sftp.transfer` starting or ending.
+pub struct TransferEvent {
+  /// The position of the job among those passed to `Sftp.transfer`.
+  pub index: u32,
+  pub state: TransferState,
+  /// Why the job failed, for `Failed`.
+  pub error: Option<String>,
+}
+
+#[napi(object)]
+/// The outcome of one job of `Sftp.transfer`.
+pub struct TransferResult {
+  pub local_path: String,
+  pub remote_path: String,
+  /// Why the job failed, unset if it succeeded.
+  pub error: Option<String>,
+}
+
+/// Receives the bytes a transfer moved so far and the size of the file.
+pub(crate) type Progress<'a> = &'a (dyn Fn(u64, u64) + Sync);
+
+/// Tells apart the temporary files of concurrent downloads.
+static DOWNLOADS: AtomicU32 = AtomicU32::new(0);
+
+/// Parse the `flags` of `Sftp.open`, spelled like those of Node's `fs.open`.
+fn parse_open_flags(flags: &str) -> Result<OpenFlags> {
+  let create = OpenFlags::WRITE | OpenFlags::CREATE;
+  let (base, read) = match flags.strip_suffix('+') {
+    Some(base) => (base, true),
+    None => (flags, false),
+  };
+  let open_flags = match base {
+    "r" => {
+      OpenFlags::READ
+        | if read {
+          OpenFlags::WRITE
+        } else {
+          OpenFlags::empty()
+        }
+    }
+    "w" => create | OpenFlags::TRUNCATE,
+    "wx" | "xw" => create | OpenFlags::EXCLUDE,
+    "a" => create | OpenFlags::APPEND,
+    "ax" | "xa" => create | OpenFlags::APPEND | OpenFlags::EXCLUDE,
+    _ => {
+      return Err(Error::new(
+        Status::InvalidArg,
+        format!("Unknown file open flags {flags}"),
+      ))
+    }
+  };
+  Ok(if read {
+    open_flags | OpenFlags::READ
+  } else {
+    open_flags
+  })
+}
+
+/// An offset or size passed as a `number` or a `bigint`, which must be a non-negative integer.
+fn to_u64(value: Either<f64, BigInt>, name: &str) -> Result<u64> {
+  let value = match value {
+    // larger numbers may have lost their last digits already
+    Either::A(number) if number >= 0.0 && number.fract() == 0.0 && number <= 2f64.powi(53) => {
+      Some(number as u64)
+    }
+    Either::A(_) => None,
+    Either::B(bigint) => match bigint.get_u64() {
+      (_, value, true) => Some(value),
+      _ => None,
+    },
+  };
+  value.ok_or_else(|| {
+    Error::new(
+      Status::InvalidArg,
+      format!("{name} must be a non-negative integer below 2^64"),
+    )
+  })
+}
+
+fn has_status(err: &SftpError, code: StatusCode) -> bool {
+  matches!(err, SftpError::Status(status) if status.status_code == code)
+}
+
+fn is_eof(err: &SftpError) -> bool {
+  has_status(err, StatusCode::Eof)
+}
+
+/// Append `value` to the data of an extended request, prefixed with its length.
+fn put_string(data: &mut Vec<u8>, value: &str) {
+  data.extend_from_slice(&(value.len() as u32).to_be_bytes());
+  data.extend_from_slice(value.as_bytes());
+}
+
+/// Split the string at the start of `data`, prefixed with its length, from what follows.
+fn take_string(data: &[u8]) -> Option<(&[u8], &[u8])> {
+  let length = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+  let rest = &data[4..];
+  (rest.len() >= length).then(|| rest.split_at(length))
+}
+
+/// The hash in the reply to a `check-file-name` request, in hex.
+fn check_file_hash(data: &[u8]) -> Option<String> {
+  let (first, rest) = take_string(data)?;
+  // the drafts disagree on whether the reply starts with the name of the extension
+  let hash = if first == b"check-file" {
+    take_string(rest)?.1
+  } else {
+    rest
+  };
+  Some(hash.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Check the reply to an extended request that answers with a status.
+fn expect_ok(reply: Packet) -> Result<()> {
+  match reply {
+    Packet::Status(status) if status.status_code == StatusCode::Ok => Ok(()),
+    Packet::Status(status) => Err(SftpError::Status(status)).into_error(),
+    _ => Err(SftpError::UnexpectedPacket).into_error(),
+  }
+}
+
+/// Read `length` bytes of the open file `handle` at `offset`, asking again for the rest when
+/// the server answers with fewer. Fewer come back only at the end of the file.
+async fn read_chunk(
+  session: &RawSftpSession,
+  handle: &str,
+  offset: u64,
+  length: u32,
+) -> Result<Vec<u8>> {
+  let mut data = Vec::new();
+  while data.len() < length as usize {
+    let wanted = length - data.len() as u32;
+    match session
+      .read(handle, offset + data.len() as u64, wanted)
+      .await
+    {
+      Ok(chunk) if chunk.data.is_empty() => break,
+      Ok(chunk) => data.extend_from_slice(&chunk.data),
+      Err(err) if is_eof(&err) => break,
+      Err(err) => return Err(err).into_error(),
+    }
+  }
+  Ok(data)
+}
+
+/// What the ranges of a parallel transfer share.
+struct RangeTransfer<'a> {
+  /// The size of the file.
+  total: u64,
+  chunk_size: u32,
+  /// Of every range.
+  max_inflight: usize,
+  throttle: tokio::sync::Mutex<Option<Throttle>>,
+  /// The bytes of all ranges transferred so far.
+  transferred: AtomicU64,
+  progress: Progress<'a>,
+}
+
+impl<'a> RangeTransfer<'a> {
+  fn new(
+    total: u64,
+    chunk_size: u32,
+    max_inflight: usize,
+    throttle: Option<Throttle>,
+    progress: Progress<'a>,
+  ) -> Self {
+    Self {
+      total,
+      chunk_size,
+      max_inflight,
+      throttle: tokio::sync::Mutex::new(throttle),
+      transferred: AtomicU64::new(0),
+      progress,
+    }
+  }
+
+  /// The file split into up to `count` ranges of whole chunks, each with at least
+  /// `max_inflight` chunks, as a shorter range could not keep the requests of its channel going
+  /// and only adds the cost of opening it.
+  fn split(&self, count: u32) -> Vec<(u64, u64)> {
+    let chunk_size = self.chunk_size as u64;
+    let min_length = chunk_size * self.max_inflight as u64;
+    let count = (self.total / min_length).clamp(1, count.max(1) as u64);
+    let length = self.total.div_ceil(count).div_ceil(chunk_size) * chunk_size;
+    (0..count)
+      .map(|range| (range * length, ((range + 1) * length).min(self.total)))
+      .filter(|(start, end)| start < end)
+      .collect()
+  }
+
+  /// Wait until `bytes` more keep the ranges together within the rate.
+  async fn throttle(&self, bytes: usize) {
+    Throttle::take_from(&mut *self.throttle.lock().await, bytes).await;
+  }
+
+  /// Count `bytes` more as transferred and report the progress.
+  fn advance(&self, bytes: usize) {
+    let transferred = self.transferred.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+    (self.progress)(transferred, self.total);
+  }
+
+  /// Fail unless the copy at `path` ended up `size` bytes long, the size of the original.
+  fn check_size(&self, size: Option<u64>, path: &str) -> Result<()> {
+    match size {
+      Some(size) if size != self.total => Err(Error::new(
+        Status::GenericFailure,
+        format!(
+          "{path} is {size} bytes long after the transfer instead of {}",
+          self.total
+        ),
+      )),
+      _ => Ok(()),
+    }
+  }
+
+  /// Write the range `(start, end)` of the local file at `local_path` to the open remote file
+  /// `handle` of `session`.
+  async fn upload_range(
+    &self,
+    session: Arc<RawSftpSession>,
+    handle: String,
+    local_path: &str,
+    (start, end): (u64, u64),
+  ) -> Result<()> {
+    let local_error = |err: std::io::Error| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to read {local_path}: {err}"),
+      )
+    };
+    let mut file = tokio::fs::File::open(local_path)
+      .await
+      .map_err(local_error)?;
+    file
+      .seek(SeekFrom::Start(start))
+      .await
+      .map_err(local_error)?;
+    let mut writes = FuturesOrdered::new();
+    let mut offset = start;
+    loop {
+      while offset < end && writes.len() < self.max_inflight {
+        let length = (end - offset).min(self.chunk_size as u64) as usize;
+        let mut chunk = vec![0; length];
+        // fails with UnexpectedEof for a file that shrank since the transfer started
+        file.read_exact(&mut chunk).await.map_err(local_error)?;
+        self.throttle(length).await;
+        writes.push_back(
+          session
+            .write(&handle, offset, chunk)
+            .map(move |written| written.into_error().map(|_| length)),
+        );
+        offset += length as u64;
+      }
+      let Some(written) = writes.next().await else {
+        return Ok(());
+      };
+      self.advance(written?);
+    }
+  }
+
+  /// Read the range `(start, end)` of the open remote file `handle` of `session` into the same
+  /// range of the local file at `local_path`.
+  async fn download_range(
+    &self,
+    session: Arc<RawSftpSession>,
+    handle: String,
+    local_path: &str,
+    (start, end): (u64, u64),
+  ) -> Result<()> {
+    let local_error = |err: std::io::Error| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to write {local_path}: {err}"),
+      )
+    };
+    let mut file = tokio::fs::OpenOptions::new()
+      .write(true)
+      .open(local_path)
+      .await
+      .map_err(local_error)?;
+    file
+      .seek(SeekFrom::Start(start))
+      .await
+      .map_err(local_error)?;
+    let mut chunks = stream::iter((start..end).step_by(self.chunk_size as usize))
+      .map(|offset| {
+        let length = (end - offset).min(self.chunk_size as u64) as u32;
+        read_chunk(&session, &handle, offset, length).map(move |chunk| (chunk, length))
+      })
+      .buffered(self.max_inflight);
+    while let Some((chunk, length)) = chunks.next().await {
+      let chunk = chunk?;
+      if chunk.len() < length as usize {
+        return Err(Error::new(
+          Status::GenericFailure,
+          format!("The remote file ended before {end} bytes, it changed during the transfer"),
+        ));
+      }
+      self.throttle(chunk.len()).await;
+      file.write_all(&chunk).await.map_err(local_error)?;
+      self.advance(chunk.len());
+    }
+    file.flush().await.map_err(local_error)
+  }
+}
+
+/// Split the complete lines off the start of `pending`, without their line endings.
+fn take_lines(pending: &mut Vec<u8>) -> Vec<String> {
+  let mut lines = Vec::new();
+  while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+    let line = pending.drain(..=end).collect::<Vec<_>>();
+    let line = line.strip_suffix(b"\r\n").unwrap_or(&line[..end]);
+    lines.push(String::from_utf8_lossy(line).into_owned());
+  }
+  lines
+}
+
+/// `size` lowered to `limit`, if any.
+fn capped(size: u32, limit: Option<u64>) -> u32 {
+  limit.map_or(size, |limit| size.min(limit.min(u32::MAX as u64) as u32))
+}
+
+/// Have the server flush the open file `handle` to disk, with the `fsync@openssh.com` extension.
+async fn fsync(session: &RawSftpSession, handle: &str) -> Result<()> {
+  let mut data = Vec::new();
+  put_string(&mut data, handle);
+  let reply = session
+    .extended("fsync@openssh.com", data)
+    .await
+    .into_error()?;
+  expect_ok(reply)
+}
+
+/// The permissions and times of a local file, for `preserve`.
+pub(crate) fn local_attributes(metadata: &std::fs::Metadata) -> protocol::FileAttributes {
+  #[cfg(unix)]
+  let permissions = {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+  };
+  #[cfg(not(unix))]
+  let permissions = None;
+  let seconds = |time: std::io::Result<SystemTime>| {
+    let since_epoch = time.ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs() as u32)
+  };
+  let mtime = seconds(metadata.modified());
+  protocol::FileAttributes {
+    permissions,
+    // the protocol sends both times or neither
+    atime: mtime.and(seconds(metadata.accessed()).or(mtime)),
+    mtime,
+    ..protocol::FileAttributes::empty()
+  }
+}
+
+/// Give the local `file` the permissions and times of a remote one, for `preserve`.
+async fn apply_attributes(
+  file: tokio::fs::File,
+  attrs: &protocol::FileAttributes,
+) -> std::io::Result<()> {
+  #[cfg(unix)]
+  if let Some(mode) = attrs.permissions {
+    use std::os::unix::fs::PermissionsExt;
+    file
+      .set_permissions(std::fs::Permissions::from_mode(mode & 0o7777))
+      .await?;
+  }
+  let time = |seconds: u32| UNIX_EPOCH + Duration::from_secs(seconds as u64);
+  let mut times = FileTimes::new();
+  if let Some(atime) = attrs.atime {
+    times = times.set_accessed(time(atime));
+  }
+  if let Some(mtime) = attrs.mtime {
+    times = times.set_modified(time(mtime));
+  }
+  file.into_std().await.set_times(times)
+}
+
+/// The one name in the reply to a request that resolves a path.
+fn only_name(name: protocol::Name) -> Result<String> {
+  name
+    .files
+    .into_iter()
+    .next()
+    .map(|file| file.filename)
+    .ok_or_else(|| Error::new(Status::GenericFailure, "The server sent no name".to_owned()))
+}
+
+/// The remote path of `name` in the directory `dir`.
+fn join_remote(dir: &str, name: &str) -> String {
+  format!("{}/{name}", dir.trim_end_matches('/'))
+}
+
+/// The files and directories below the local directory `root`, by their paths relative to it.
+/// Symlinks are followed, as uploads do.
+async fn local_tree(root: &Path) -> Result<BTreeMap<String, std::fs::Metadata>> {
+  let local_error = |path: &Path, err: std::io::Error| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to read {}: {err}", path.display()),
+    )
+  };
+  let mut tree = BTreeMap::new();
+  let mut dirs = vec![String::new()];
+  while let Some(dir) = dirs.pop() {
+    let dir_path = root.join(&dir);
+    let mut entries = tokio::fs::read_dir(&dir_path)
+      .await
+      .map_err(|err| local_error(&dir_path, err))?;
+    while let Some(entry) = entries
+      .next_entry()
+      .await
+      .map_err(|err| local_error(&dir_path, err))?
+    {
+      let metadata = tokio::fs::metadata(entry.path())
+        .await
+        .map_err(|err| local_error(&entry.path(), err))?;
+      let path = glob_child(&dir, &entry.file_name().to_string_lossy());
+      if metadata.is_dir() {
+        dirs.push(path.clone());
+      } else if !metadata.is_file() {
+        continue;
+      }
+      tree.insert(path, metadata);
+    }
+  }
+  Ok(tree)
+}
+
+/// The sha256 hash of the local file at `path`, in hex like `Sftp.checksum` gives it.
+async fn local_sha256(path: &Path) -> Result<String> {
+  let local_error = |err: std::io::Error| {
+    Error::new(
+      Status::GenericFailure,
+      format!("Failed to read {}: {err}", path.display()),
+    )
+  };
+  let mut file = tokio::fs::File::open(path).await.map_err(local_error)?;
+  let mut hasher = Sha256::new();
+  let mut buffer = vec![0; CHUNK_SIZE as usize];
+  loop {
+    let read = file.read(&mut buffer).await.map_err(local_error)?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buffer[..read]);
+  }
+  Ok(
+    hasher
+      .finalize()
+      .iter()
+      .map(|byte| format!("{byte:02x}"))
+      .collect(),
+  )
+}
+
+fn file_type(attrs: &protocol::FileAttributes) -> FileType {
+  match attrs.permissions.map(|mode| mode & 0o170000) {
+    Some(mode) if mode == FileMode::REG.bits() => FileType::File,
+    Some(mode) if mode == FileMode::DIR.bits() => FileType::Directory,
+    Some(mode) if mode == FileMode::LNK.bits() => FileType::Symlink,
+    _ => FileType::Other,
+  }
+}
+
+fn is_dir(attrs: &protocol::FileAttributes) -> bool {
+  file_type(attrs) == FileType::Directory
+}
+
+/// The path of `name` in `dir` while expanding a glob, where an empty `dir` is where the
+/// pattern is matched.
+fn glob_child(dir: &str, name: &str) -> String {
+  if dir.is_empty() {
+    name.to_owned()
+  } else {
+    join_remote(dir, name)
+  }
+}
+
+/// Where the server finds `dir` of a glob matched in `cwd`.
+fn glob_listing(cwd: &str, dir: &str) -> String {
+  match (cwd, dir) {
+    ("", "") => ".".to_owned(),
+    (cwd, "") => cwd.to_owned(),
+    ("", dir) => dir.to_owned(),
+    (_, dir) if dir.starts_with('/') => dir.to_owned(),
+    (cwd, dir) => join_remote(cwd, dir),
+  }
+}
+
+fn has_wildcard(component: &str) -> bool {
+  component.contains(['*', '?', '['])
+}
+
+/// Translate one path component of a glob pattern into a regex matching whole names. `*` and
+/// `?` match any run of characters and any one character, `[...]` a character class, negated
+/// with `!` or `^`, and `\` escapes the next character.
+fn component_regex(component: &str) -> Result<regex::Regex> {
+  let mut pattern = String::from("^");
+  let mut chars = component.chars().peekable();
+  while let Some(c) = chars.next() {
+    match c {
+      '*' => pattern.push_str(".*"),
+      '?' => pattern.push('.'),
+      '\\' => {
+        if let Some(escaped) = chars.next() {
+          pattern.push_str(&regex::escape(escaped.encode_utf8(&mut [0; 4])));
+        }
+      }
+      '[' => {
+        let rest = chars.clone().collect::<String>();
+        // a `]` right after the opening bracket, or its negation, is part of the class
+        let skip = match rest.chars().next() {
+          Some('!' | '^') => 2,
+          _ => 1,
+        };
+        let Some(end) = rest
+          .char_indices()
+          .skip(skip)
+          .find(|(_, c)| *c == ']')
+          .map(|(index, _)| index)
+        else {
+          pattern.push_str(r"\[");
+          continue;
+        };
+        pattern.push('[');
+        for (index, c) in rest[..end].chars().enumerate() {
+          match c {
+            '!' | '^' if index == 0 => pattern.push('^'),
+            '\\' | '[' | ']' | '^' | '&' | '~' => {
+              pattern.push('\\');
+              pattern.push(c);
+            }
+            c => pattern.push(c),
+          }
+        }
+        pattern.push(']');
+        for _ in 0..=rest[..end].chars().count() {
+          chars.next();
+        }
+      }
+      c => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+    }
+  }
+  pattern.push('$');
+  regex::Regex::new(&pattern).map_err(|err| {
+    Error::new(
+      Status::InvalidArg,
+      format!("Invalid glob pattern {component}: {err}"),
+    )
+  })
+}
+
+/// Where an `Sftp` keeps the session on its current channel.
+type SessionSlot = std::sync::RwLock<Arc<RawSftpSession>>;
+
+/// What an `Sftp` learns from the stream of its channel.
+struct ChannelActivity {
+  /// When the server last sent data.
+  received: std::sync::Mutex<Instant>,
+  /// Notified once the channel closed.
+  closed: Notify,
+}
+
+impl ChannelActivity {
+  fn received(&self) -> Instant {
+    *self.received.lock().unwrap()
+  }
+}
+
+/// The stream of a session channel, noting in `activity` when data arrives and when it ends.
+struct WatchedStream<S> {
+  stream: S,
+  activity: Arc<ChannelActivity>,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for WatchedStream<S> {
+  fn poll_read(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    let (filled, wanted) = (buf.filled().len(), buf.remaining() > 0);
+    let polled = Pin::new(&mut self.stream).poll_read(cx, buf);
+    match &polled {
+      Poll::Ready(Ok(())) if buf.filled().len() > filled => {
+        *self.activity.received.lock().unwrap() = Instant::now();
+      }
+      Poll::Ready(Ok(())) if !wanted => {}
+      Poll::Ready(_) => self.activity.closed.notify_one(),
+      Poll::Pending => {}
+    }
+    polled
+  }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for WatchedStream<S> {
+  fn poll_write(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+  ) -> Poll<std::io::Result<usize>> {
+    Pin::new(&mut self.stream).poll_write(cx, buf)
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.stream).poll_flush(cx)
+  }
+
+  fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+    Pin::new(&mut self.stream).poll_shutdown(cx)
+  }
+}
+
+/// Start the `sftp` subsystem on a new channel of `handle` and negotiate SFTP version 3, failing
+/// requests the server takes longer than `timeout` seconds to answer.
+/// Open a session channel on `handle` and start the `sftp` subsystem on it. The inner error is
+/// the server refusing the channel or the subsystem, the outer one any other failure.
+pub(crate) async fn request_subsystem(
+  handle: &SharedHandle,
+) -> Result<Result<russh::Channel<russh::client::Msg>>> {
+  let opened = handle.read().await.channel_open_session().await;
+  let mut channel = match opened {
+    Ok(channel) => channel,
+    Err(err @ russh::Error::ChannelOpenFailure(_)) => {
+      return Ok(Err(Error::new(Status::GenericFailure, err.to_string())))
+    }
+    Err(err) => return Err(err).into_error(),
+  };
+  channel.request_subsystem(true, "sftp").await.into_error()?;
+  Ok(
+    wait_for_reply(&mut channel, "sftp subsystem")
+      .await
+      .map(|()| channel),
+  )
+}
+
+async fn open_session(
+  handle: &SharedHandle,
+  timeout: u64,
+) -> Result<(RawSftpSession, protocol::Version, Arc<ChannelActivity>)> {
+  let channel = request_subsystem(handle).await??;
+  init_session(channel, timeout).await
+}
+
+/// Negotiate SFTP version 3 on `channel`, which the subsystem was started on.
+async fn init_session(
+  channel: russh::Channel<russh::client::Msg>,
+  timeout: u64,
+) -> Result<(RawSftpSession, protocol::Version, Arc<ChannelActivity>)> {
+  let activity = Arc::new(ChannelActivity {
+    received: std::sync::Mutex::new(Instant::now()),
+    closed: Notify::new(),
+  });
+  let session = RawSftpSession::new(WatchedStream {
+    stream: channel.into_stream(),
+    activity: activity.clone(),
+  });
+  session.set_timeout(timeout);
+  let version = session.init().await.into_error()?;
+  Ok((session, version, activity))
+}
+
+/// How `supervise` keeps a session going.
+struct Supervision {
+  keepalive: Option<Duration>,
+  reopen: bool,
+  /// The request timeout and the limits for a session on a new channel.
+  timeout: u64,
+  limits: Limits,
+  ended: Arc<AtomicBool>,
+}
+
+/// Send a request whenever the server was quiet for `keepalive`, and with `reopen`, put a session
+/// on a new channel into `session` when the server closes the current one. Ends with the `Sftp`,
+/// once it is closed, or when a new channel can not be opened.
+async fn supervise(
+  handle: SharedHandle,
+  session: Weak<SessionSlot>,
+  mut activity: Arc<ChannelActivity>,
+  supervision: Supervision,
+) {
+  loop {
+    let idle = async {
+      match supervision.keepalive {
+        Some(interval) => tokio::time::sleep_until(activity.received() + interval).await,
+        None => std::future::pending().await,
+      }
+    };
+    tokio::select! {
+      _ = activity.closed.notified() => {
+        if !supervision.reopen || supervision.ended.load(Ordering::Acquire) {
+          return;
+        }
+        let Some(slot) = session.upgrade() else {
+          return;
+        };
+        let Ok((mut reopened, _, reopened_activity)) =
+          open_session(&handle, supervision.timeout).await
+        else {
+          return;
+        };
+        reopened.set_limits(supervision.limits);
+        *slot.write().unwrap() = Arc::new(reopened);
+        activity = reopened_activity;
+      }
+      _ = idle => {
+        let Some(slot) = session.upgrade() else {
+          return;
+        };
+        let current = slot.read().unwrap().clone();
+        drop(slot);
+        // any answer counts as data from the server
+        let _ = current.realpath(".").await;
+      }
+    }
+  }
+}
+
+#[napi]
+/// An SFTP session, opened with `Client.sftp`. File operations are sent as they are called,
+/// so several may be in flight on the one channel.
+///
+/// When the server fails a request, the error's `code` is the name of its status, which its
+/// message also starts with, e.g. `NO_SUCH_FILE: No such file`: one of `EOF`, `NO_SUCH_FILE`,
+/// `PERMISSION_DENIED`, `FAILURE`, `BAD_MESSAGE`, `NO_CONNECTION`, `CONNECTION_LOST` or
+/// `OP_UNSUPPORTED`. A request the server does not answer in time fails with `TIMEOUT`.
+pub struct Sftp {
+  /// The connection, to run commands for what the server lacks extensions for.
+  handle: SharedHandle,
+  /// The session on the current channel, replaced when the channel is opened again.
+  session: Arc<SessionSlot>,
+  /// Set by `close`, so the channel it closes is not opened again.
+  ended: Arc<AtomicBool>,
+  version: u32,
+  /// The extensions the server announced, with their versions.
+  extensions: HashMap<String, String>,
+  /// What the server accepts, from `limits@openssh.com`.
+  limits: Limits,
+  /// The request timeout in seconds, for sessions on further channels.
+  timeout: u64,
+}
+
+impl Sftp {
+  /// Start the `sftp` subsystem on a new channel of `handle`, negotiate SFTP version 3 and ask
+  /// for the limits of the server if it announces them.
+  pub(crate) async fn start(handle: SharedHandle, options: Option<SftpOptions>) -> Result<Self> {
+    let channel = request_subsystem(&handle).await??;
+    Self::start_on(handle, channel, options).await
+  }
+
+  /// `start`, on a `channel` of `handle` the subsystem was already started on.
+  pub(crate) async fn start_on(
+    handle: SharedHandle,
+    channel: russh::Channel<russh::client::Msg>,
+    options: Option<SftpOptions>,
+  ) -> Result<Self> {
+    let options = options.unwrap_or(SftpOptions {
+      timeout_ms: None,
+      keepalive_interval_ms: None,
+      reopen: None,
+    });
+    let timeout = match options.timeout_ms {
+      None => 10,
+      Some(0) => {
+        return Err(Error::new(
+          Status::InvalidArg,
+          "timeoutMs must be positive".to_owned(),
+        ))
+      }
+      Some(timeout_ms) => timeout_ms.div_ceil(1000) as u64,
+    };
+    let (mut session, version, activity) = init_session(channel, timeout).await?;
+    let mut limits = Limits::default();
+    if version.extensions.contains_key("limits@openssh.com") {
+      limits = session.limits().await.into_error()?.into();
+      // the session refuses requests beyond them
+      session.set_limits(limits);
+    }
+    let session = Arc::new(std::sync::RwLock::new(Arc::new(session)));
+    let ended = Arc::new(AtomicBool::new(false));
+    let keepalive = options
+      .keepalive_interval_ms
+      .map(|interval| Duration::from_millis(interval as u64));
+    let reopen = options.reopen.unwrap_or(false);
+    if keepalive.is_some() || reopen {
+      tokio::spawn(supervise(
+        handle.clone(),
+        Arc::downgrade(&session),
+        activity,
+        Supervision {
+          keepalive,
+          reopen,
+          timeout,
+          limits,
+          ended: ended.clone(),
+        },
+      ));
+    }
+    Ok(Self {
+      handle,
+      session,
+      ended,
+      version: version.version,
+      extensions: version.extensions,
+      limits,
+      timeout,
+    })
+  }
+
+  /// The session on the current channel.
+  fn session(&self) -> Arc<RawSftpSession> {
+    self.session.read().unwrap().clone()
+  }
+
+  /// Remove the directory `path` with everything in it.
+  fn remove_tree(&self, path: String) -> Pin<Box<dyn Future<Output = Result<()>> + Send + '_>> {
+    Box::pin(async move {
+      for entry in self.readdir(path.clone()).await?.into_result()? {
+        let entry_path = join_remote(&path, &entry.name);
+        if entry.attributes.file_type == FileType::Directory {
+          self.remove_tree(entry_path).await?;
+        } else {
+          self.session().remove(entry_path).await.into_error()?;
+        }
+      }
+      self.session().rmdir(path).await.into_error().map(drop)
+    })
+  }
+
+  /// The paths matching the glob `pattern`, sorted. Relative patterns are matched in `cwd`, or
+  /// in the directory the session started in if it is empty, and give paths relative to it.
+  /// Components without wildcards are taken as they are, without checking that they exist,
+  /// `**` matches any number of directories, and names starting with `.` only match a
+  /// component that does too.
+  async fn expand_glob(&self, pattern: &str, cwd: &str) -> Result<Vec<String>> {
+    let mut paths = vec![if pattern.starts_with('/') {
+      "/".to_owned()
+    } else {
+      String::new()
+    }];
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+      if component == "**" {
+        let mut matched = Vec::new();
+        for dir in paths {
+          let below = self
+            .subdirectories(glob_listing(cwd, &dir), dir.clone())
+            .await;
+          matched.push(dir);
+          matched.extend(below);
+        }
+        paths = matched;
+        continue;
+      }
+      if !has_wildcard(component) {
+        paths = paths.iter().map(|dir| glob_child(dir, component)).collect();
+        continue;
+      }
+      let regex = component_regex(component)?;
+      let mut matched = Vec::new();
+      for dir in &paths {
+        // like a shell, skip what can not be listed
+        let Ok(entries) = self
+          .readdir(glob_listing(cwd, dir))
+          .await
+          .and_then(Coded::into_result)
+        else {
+          continue;
+        };
+        matched.extend(
+          entries
+            .into_iter()
+            .filter(|entry| !entry.name.starts_with('.') || component.starts_with('.'))
+            .filter(|entry| regex.is_match(&entry.name))
+            .map(|entry| glob_child(dir, &entry.name)),
+        );
+      }
+      paths = matched;
+    }
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+  }
+
+  /// The directories below `dir` at any depth, for `**`, without those starting with `.` and
+  /// without following symlinks. `listing` is where the server finds `dir`.
+  fn subdirectories(
+    &self,
+    listing: String,
+    dir: String,
+  ) -> Pin<Box<dyn Future<Output = Vec<String>> + Send + '_>> {
+    Box::pin(async move {
+      let Ok(entries) = self
+        .readdir(listing.clone())
+        .await
+        .and_then(Coded::into_result)
+      else {
+        return Vec::new();
+      };
+      let mut found = Vec::new();
+      for entry in entries {
+        if entry.attributes.file_type != FileType::Directory || entry.name.starts_with('.') {
+          continue;
+        }
+        let path = glob_child(&dir, &entry.name);
+        found.push(path.clone());
+        let below = join_remote(&listing, &entry.name);
+        found.extend(self.subdirectories(below, path).await);
+      }
+      found
+    })
+  }
+
+  /// Add everything below the remote directory `dir` to `tree`, by its path relative to the
+  /// directory `prefix` names, without following symlinks.
+  fn remote_tree<'a>(
+    &'a self,
+    dir: String,
+    prefix: String,
+    tree: &'a mut BTreeMap<String, FileAttributes>,
+  ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+    Box::pin(async move {
+      for entry in self.readdir(dir.clone()).await?.into_result()? {
+        let path = glob_child(&prefix, &entry.name);
+        if entry.attributes.file_type == FileType::Directory {
+          self
+            .remote_tree(join_remote(&dir, &entry.name), path.clone(), tree)
+            .await?;
+        }
+        tree.insert(path, entry.attributes);
+      }
+      Ok(())
+    })
+  }
+
+  async fn setstat(&self, path: String, attrs: protocol::FileAttributes) -> Result<()> {
+    self
+      .session()
+      .setstat(path, attrs)
+      .await
+      .into_error()
+      .map(drop)
+  }
+
+  /// Copy the local file at `local_path` to `remote_path`, as `Sftp.upload` does without
+  /// `atomic`, passing the bytes written so far and the size of the file to `progress`.
+  async fn upload_to(
+    &self,
+    local_path: &str,
+    remote_path: String,
+    options: &TransferOptions,
+    progress: Progress<'_>,
+  ) -> Result<()> {
+    let local_error = |err: std::io::Error| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to read {local_path}: {err}"),
+      )
+    };
+    // the whole transfer goes to the channel it started on
+    let session = self.session();
+    let mut file = tokio::fs::File::open(&local_path)
+      .await
+      .map_err(local_error)?;
+    let metadata = file.metadata().await.map_err(local_error)?;
+    let total = metadata.len();
+    let (chunk_size, max_inflight) = options.pipelining(self.limits.write_len)?;
+    let mut throttle = Throttle::new(options.max_bytes_per_second, chunk_size)?;
+    let sync = options.fsync.unwrap_or(false);
+    if sync {
+      self.require_extension("fsync@openssh.com")?;
+    }
+    let mut offset = 0;
+    if options.resume.unwrap_or(false) {
+      if let Ok(existing) = session.stat(remote_path.as_str()).await {
+        // a longer remote file is not a part of this one
+        offset = existing
+          .attrs
+          .size
+          .filter(|size| *size <= total)
+          .unwrap_or(0);
+      }
+    }
+    let flags = if offset > 0 {
+      OpenFlags::WRITE | OpenFlags::CREATE
+    } else {
+      OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE
+    };
+    file
+      .seek(SeekFrom::Start(offset))
+      .await
+      .map_err(local_error)?;
+    let handle = session
+      .open(
+        remote_path.clone(),
+        flags,
+        protocol::FileAttributes::empty(),
+      )
+      .await
+      .into_error()?
+      .handle;
+    let parallelism = match options.resume {
+      Some(true) => 1,
+      _ => options.parallelism.unwrap_or(1),
+    };
+    let upload = async {
+      if parallelism > 1 {
+        let transfer = RangeTransfer::new(total, chunk_size, max_inflight, throttle, progress);
+        self
+          .upload_ranges(
+            &session,
+            &handle,
+            local_path,
+            &remote_path,
+            parallelism,
+            &transfer,
+          )
+          .await?;
+      } else {
+        let mut writes = FuturesOrdered::new();
+        let mut read_all = false;
+        loop {
+          // keep reading ahead of the writes the server has yet to answer
+          while !read_all && writes.len() < max_inflight {
+            let mut chunk = vec![0; chunk_size as usize];
+            let read = file.read(&mut chunk).await.map_err(local_error)?;
+            if read == 0 {
+              read_all = true;
+              break;
+            }
+            chunk.truncate(read);
+            Throttle::take_from(&mut throttle, read).await;
+            let end = offset + read as u64;
+            writes.push_back(
+              session
+                .write(&handle, offset, chunk)
+                .map(move |written| written.into_error().map(|_| end)),
+            );
+            offset = end;
+          }
+          let Some(written) = writes.next().await else {
+            break;
+          };
+          let end = written?;
+          progress(end, total);
+        }
+      }
+      if options.preserve.unwrap_or(false) {
+        session
+          .fsetstat(&handle, local_attributes(&metadata))
+          .await
+          .into_error()?;
+      }
+      if sync {
+        fsync(&session, &handle).await?;
+      }
+      if options.verify_checksum.unwrap_or(false) {
+        self
+          .verify_checksum(Path::new(local_path), remote_path.clone())
+          .await?;
+      }
+      Ok(())
+    };
+    self.closing(handle.clone(), upload).await
+  }
+
+  /// `Sftp.upload`, passing the bytes written so far and the size of the file to `progress`.
+  pub(crate) async fn upload_file(
+    &self,
+    local_path: &str,
+    remote_path: String,
+    options: &TransferOptions,
+    progress: Progress<'_>,
+  ) -> Result<()> {
+    if !options.atomic.unwrap_or(false) {
+      return self
+        .upload_to(local_path, remote_path, options, progress)
+        .await;
+    }
+    if options.resume.unwrap_or(false) {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "resume and atomic can not be combined".to_owned(),
+      ));
+    }
+    let partial = format!("{remote_path}.partial-{:016x}", rand::random::<u64>());
+    let uploaded = match self
+      .upload_to(local_path, partial.clone(), options, progress)
+      .await
+    {
+      Ok(()) => self
+        .rename(partial.clone(), remote_path)
+        .await?
+        .into_result(),
+      Err(err) => Err(err),
+    };
+    if uploaded.is_err() {
+      let _ = self.session().remove(partial).await;
+    }
+    uploaded
+  }
+
+  /// `Sftp.download`, passing the bytes written so far and the size of the file to `progress`.
+  pub(crate) async fn download_file(
+    &self,
+    remote_path: String,
+    local_path: &str,
+    options: &TransferOptions,
+    progress: Progress<'_>,
+  ) -> Result<()> {
+    // the whole transfer goes to the channel it started on
+    let session = self.session();
+    let (chunk_size, max_inflight) = options.pipelining(self.limits.read_len)?;
+    let mut throttle = Throttle::new(options.max_bytes_per_second, chunk_size)?;
+    let resume = options.resume.unwrap_or(false);
+    let handle = session
+      .open(
+        remote_path.clone(),
+        OpenFlags::READ,
+        protocol::FileAttributes::empty(),
+      )
+      .await
+      .into_error()?
+      .handle;
+    let parallelism = match resume {
+      true => 1,
+      false => options.parallelism.unwrap_or(1),
+    };
+    let partial = if resume {
+      format!("{local_path}.partial")
+    } else {
+      format!(
+        "{local_path}.{}-{}.partial",
+        std::process::id(),
+        DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+      )
+    };
+    let local_error = |err: std::io::Error| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Failed to write {local_path}: {err}"),
+      )
+    };
+    let download = async {
+      let attrs = session.fstat(&handle).await.into_error()?.attrs;
+      let total = attrs.size;
+      let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(!resume)
+        .open(&partial)
+        .await
+        .map_err(local_error)?;
+      let mut offset = file.metadata().await.map_err(local_error)?.len();
+      if total.is_some_and(|total| offset > total) {
+        offset = 0;
+      }
+      match total.filter(|_| parallelism > 1) {
+        Some(total) => {
+          // the ranges are written in place, each through its own handle
+          file.set_len(total).await.map_err(local_error)?;
+          let transfer = RangeTransfer::new(total, chunk_size, max_inflight, throttle, progress);
+          self
+            .download_ranges(
+              &session,
+              &handle,
+              &remote_path,
+              &partial,
+              parallelism,
+              &transfer,
+            )
+            .await?;
+        }
+        None => {
+          file.set_len(offset).await.map_err(local_error)?;
+          file
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(local_error)?;
+          // read ahead of the chunk being written, until a short chunk shows the end of the file
+          let mut chunks = stream::iter((offset..).step_by(chunk_size as usize))
+            .map(|chunk_offset| read_chunk(&session, &handle, chunk_offset, chunk_size))
+            .buffered(max_inflight);
+          while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            Throttle::take_from(&mut throttle, chunk.len()).await;
+            file.write_all(&chunk).await.map_err(local_error)?;
+            offset += chunk.len() as u64;
+            progress(offset, total.unwrap_or(offset));
+            if chunk.len() < chunk_size as usize {
+              break;
+            }
+          }
+        }
+      }
+      file.flush().await.map_err(local_error)?;
+      if options.fsync.unwrap_or(false) {
+        file.sync_all().await.map_err(local_error)?;
+      }
+      if options.verify_checksum.unwrap_or(false) {
+        self
+          .verify_checksum(Path::new(&partial), remote_path.clone())
+          .await?;
+      }
+      if options.preserve.unwrap_or(false) {
+        apply_attributes(file, &attrs).await.map_err(local_error)?;
+      }
+      Ok(())
+    };
+    let downloaded = self.closing(handle.clone(), download).await;
+    match downloaded {
+      Ok(()) => tokio::fs::rename(&partial, &local_path)
+        .await
+        .map_err(local_error),
+      Err(err) => {
+        if !resume {
+          let _ = tokio::fs::remove_file(&partial).await;
+        }
+        Err(err)
+      }
+    }
+  }
+
+  /// SFTP sessions on up to `count` more channels, for the ranges of a parallel transfer. Fewer
+  /// come back when the server refuses channels or the subsystem.
+  async fn extra_sessions(&self, count: usize) -> Vec<Arc<RawSftpSession>> {
+    futures::future::join_all((0..count).map(|_| open_session(&self.handle, self.timeout)))
+      .await
+      .into_iter()
+      .filter_map(|opened| {
+        let (mut session, ..) = opened.ok()?;
+        session.set_limits(self.limits);
+        Some(Arc::new(session))
+      })
+      .collect()
+  }
+
+  /// Open `remote_path` with `flags` on sessions on more channels, for up to `parallelism`
+  /// ranges in all, and run `transfer_range` for every range, the first on `handle` of
+  /// `session`. The handles and sessions opened here are closed again, whatever the outcome.
+  async fn transfer_ranges<F: Future<Output = Result<()>>>(
+    &self,
+    (session, handle): (&Arc<RawSftpSession>, &str),
+    remote_path: &str,
+    flags: OpenFlags,
+    parallelism: u32,
+    transfer: &RangeTransfer<'_>,
+    transfer_range: impl Fn(Arc<RawSftpSession>, String, (u64, u64)) -> F,
+  ) -> Result<()> {
+    let wanted = transfer.split(parallelism).len();
+    let extra = self.extra_sessions(wanted.saturating_sub(1)).await;
+    let mut opened = Vec::new();
+    for extra in &extra {
+      if let Ok(file) = extra
+        .open(remote_path, flags, protocol::FileAttributes::empty())
+        .await
+      {
+        opened.push((extra.clone(), file.handle));
+      }
+    }
+    let ranges = transfer.split(opened.len() as u32 + 1);
+    let sessions =
+      std::iter::once((session.clone(), handle.to_owned())).chain(opened.iter().cloned());
+    let transferred = futures::future::try_join_all(
+      sessions
+        .zip(ranges)
+        .map(|((session, handle), range)| transfer_range(session, handle, range)),
+    )
+    .await;
+    for (extra, handle) in opened {
+      let _ = extra.close(handle).await;
+    }
+    for extra in extra {
+      let _ = extra.close_session();
+    }
+    transferred.map(drop)
+  }
+
+  /// Upload the local file at `local_path` to `remote_path`, open as `handle` on `session`, in
+  /// ranges on up to `parallelism` channels, then check the size of the copy.
+  async fn upload_ranges(
+    &self,
+    session: &Arc<RawSftpSession>,
+    handle: &str,
+    local_path: &str,
+    remote_path: &str,
+    parallelism: u32,
+    transfer: &RangeTransfer<'_>,
+  ) -> Result<()> {
+    self
+      .transfer_ranges(
+        (session, handle),
+        remote_path,
+        OpenFlags::WRITE,
+        parallelism,
+        transfer,
+        |session, handle, range| transfer.upload_range(session, handle, local_path, range),
+      )
+      .await?;
+    let size = session.fstat(handle).await.into_error()?.attrs.size;
+    transfer.check_size(size, remote_path)
+  }
+
+  /// Download `remote_path`, open as `handle` on `session`, into the local file at `partial`,
+  /// already as long as the remote file, in ranges on up to `parallelism` channels. The remote
+  /// file is checked not to have changed size meanwhile.
+  async fn download_ranges(
+    &self,
+    session: &Arc<RawSftpSession>,
+    handle: &str,
+    remote_path: &str,
+    partial: &str,
+    parallelism: u32,
+    transfer: &RangeTransfer<'_>,
+  ) -> Result<()> {
+    self
+      .transfer_ranges(
+        (session, handle),
+        remote_path,
+        OpenFlags::READ,
+        parallelism,
+        transfer,
+        |session, handle, range| transfer.download_range(session, handle, partial, range),
+      )
+      .await?;
+    let size = session.fstat(handle).await.into_error()?.attrs.size;
+    transfer.check_size(size, remote_path)
+  }
+
+  /// Fail unless the local file at `local_path` and the remote one at `remote_path` have the
+  /// same sha256 hash.
+  async fn verify_checksum(&self, local_path: &Path, remote_path: String) -> Result<()> {
+    let remote_hash = self
+      .checksum(remote_path.clone(), None)
+      .await?
+      .into_result()?;
+    if local_sha256(local_path).await? != remote_hash {
+      return Err(Error::new(
+        Status::GenericFailure,
+        format!(
+          "The checksum of {} does not match that of {remote_path}",
+          local_path.display()
+        ),
+      ));
+    }
+    Ok(())
+  }
+
+  /// Fail unless the server announced the extension `name`.
+  fn require_extension(&self, name: &str) -> Result<()> {
+    if self.extensions.contains_key(name) {
+      Ok(())
+    } else {
+      Err(Error::new(
+        Status::GenericFailure,
+        format!("The server does not support {name}"),
+      ))
+    }
+  }
+
+  /// Run `operation` on the open `handle`, then close the handle whatever the outcome.
+  async fn closing<T>(
+    &self,
+    handle: String,
+    operation: impl Future<Output = Result<T>>,
+  ) -> Result<T> {
+    let result = operation.await;
+    let closed = self.session().close(handle).await.into_error();
+    let value = result?;
+    closed?;
+    Ok(value)
+  }
+}
+
+#[napi]
+impl Sftp {
+  #[napi(getter)]
+  /// The protocol version the server answered with, `3` for OpenSSH.
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+
+  #[napi]
+  /// The extensions the server announced when the session started, with their versions, e.g.
+  /// `{ "posix-rename@openssh.com": "1" }`.
+  pub fn extensions(&self) -> HashMap<String, String> {
+    self.extensions.clone()
+  }
+
+  #[napi]
+  /// What the server accepts, as it announced when the session started. Reads and writes are
+  /// kept within these limits.
+  pub fn limits(&self) -> SftpLimits {
+    let limit = |limit: Option<u64>| limit.map(|limit| limit as f64);
+    SftpLimits {
+      max_packet_length: limit(self.limits.packet_len),
+      max_read_length: limit(self.limits.read_len),
+      max_write_length: limit(self.limits.write_len),
+      max_open_handles: limit(self.limits.open_handles),
+    }
+  }
+
+  #[napi(ts_return_type = "Promise<Buffer>")]
+  /// Read the whole remote file at `path` into memory.
+  pub async fn read_file(&self, path: String) -> Result<Coded<Buffer>> {
+    coded(async {
+      let handle = self
+        .session()
+        .open(path, OpenFlags::READ, protocol::FileAttributes::empty())
+        .await
+        .into_error()?
+        .handle;
+      let read_size = capped(CHUNK_SIZE, self.limits.read_len);
+      let read = async {
+        let mut data = Vec::new();
+        loop {
+          match self
+            .session()
+            .read(&handle, data.len() as u64, read_size)
+            .await
+          {
+            Ok(chunk) if chunk.data.is_empty() => return Ok(data),
+            Ok(chunk) => data.extend_from_slice(&chunk.data),
+            Err(err) if is_eof(&err) => return Ok(data),
+            Err(err) => return Err(err).into_error(),
+          }
+        }
+      };
+      let data = self.closing(handle.clone(), read).await?;
+      Ok(data.into())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Replace the remote file at `path` with `data`, creating it if needed.
+  pub async fn write_file(
+    &self,
+    path: String,
+    data: Either<String, Buffer>,
+    options: Option<WriteFileOptions>,
+  ) -> Result<Coded<()>> {
+    coded(async {
+      let data = into_bytes(data);
+      let (mode, sync) = options
+        .map(|options| (options.mode, options.fsync.unwrap_or(false)))
+        .unwrap_or_default();
+      if sync {
+        self.require_extension("fsync@openssh.com")?;
+      }
+      let attrs = protocol::FileAttributes {
+        permissions: mode,
+        ..protocol::FileAttributes::empty()
+      };
+      let handle = self
+        .session()
+        .open(
+          path,
+          OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+          attrs,
+        )
+        .await
+        .into_error()?
+        .handle;
+      let write = async {
+        let write_size = capped(CHUNK_SIZE, self.limits.write_len);
+        for (index, chunk) in data.chunks(write_size as usize).enumerate() {
+          let offset = index as u64 * write_size as u64;
+          self
+            .session()
+            .write(&handle, offset, chunk.to_vec())
+            .await
+            .into_error()?;
+        }
+        if sync {
+          fsync(&self.session(), &handle).await?;
+        }
+        Ok(())
+      };
+      self.closing(handle.clone(), write).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Copy the local file at `local_path` to `remote_path`, replacing any file there.
+  pub async fn upload(
+    &self,
+    local_path: String,
+    remote_path: String,
+    options: Option<TransferOptions>,
+  ) -> Result<Coded<()>> {
+    coded(async {
+      let options = options.unwrap_or_default();
+      let progress = |transferred, total| options.report(transferred, total);
+      self
+        .upload_file(&local_path, remote_path, &options, &progress)
+        .await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Copy the remote file at `remote_path` to `local_path`. The data is written to a temporary
+  /// file next to `local_path` that replaces it once complete, so `local_path` never holds a
+  /// partial download. With `resume`, that file is `local_path` with `.partial` appended, and it
+  /// is kept when the download fails.
+  pub async fn download(
+    &self,
+    remote_path: String,
+    local_path: String,
+    options: Option<TransferOptions>,
+  ) -> Result<Coded<()>> {
+    coded(async {
+      let options = options.unwrap_or_default();
+      let progress = |transferred, total| options.report(transferred, total);
+      self
+        .download_file(remote_path, &local_path, &options, &progress)
+        .await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<Array<TransferResult>>")]
+  /// Run many uploads and downloads, `concurrency` of them at a time. Resolves once all of them
+  /// ended, with their outcomes in the order of `jobs`; a failed job does not stop the others.
+  pub async fn transfer(
+    &self,
+    jobs: Vec<TransferJob>,
+    options: Option<TransferQueueOptions>,
+  ) -> Result<Coded<Vec<TransferResult>>> {
+    coded(async {
+      let options = options.unwrap_or_default();
+      let concurrency = options.concurrency.unwrap_or(4);
+      if concurrency == 0 {
+        return Err(Error::new(
+          Status::InvalidArg,
+          "concurrency must be positive".to_owned(),
+        ));
+      }
+      // the total starts from the sizes of the files, and follows them if they change
+      let paths = jobs
+        .iter()
+        .map(|job| {
+          (
+            job.direction,
+            job.local_path.clone(),
+            job.remote_path.clone(),
+          )
+        })
+        .collect::<Vec<_>>();
+      let sizes = stream::iter(paths)
+        .map(|(direction, local_path, remote_path)| async move {
+          match direction {
+            TransferDirection::Upload => tokio::fs::metadata(local_path)
+              .await
+              .map_or(0, |metadata| metadata.len()),
+            TransferDirection::Download => self
+              .session()
+              .stat(remote_path)
+              .await
+              .ok()
+              .and_then(|file| file.attrs.size)
+              .unwrap_or(0),
+          }
+        })
+        .buffered(MAX_INFLIGHT as usize)
+        .collect::<Vec<_>>()
+        .await;
+      let all_total = sizes.iter().sum::<u64>();
+      let jobs_progress = sizes.into_iter().map(|size| (0, size)).collect::<Vec<_>>();
+      let progress = std::sync::Mutex::new((jobs_progress, 0, all_total));
+      let report = |index: usize, transferred: u64, total: u64| {
+        let mut progress = progress.lock().unwrap();
+        let (jobs_progress, all_transferred, all_total) = &mut *progress;
+        let (job_transferred, job_total) = jobs_progress[index];
+        *all_transferred = *all_transferred + transferred - job_transferred;
+        *all_total = *all_total + total - job_total;
+        jobs_progress[index] = (transferred, total);
+        if let Some(on_progress) = &options.on_progress {
+          on_progress.call(
+            FnArgs::from((BigInt::from(*all_transferred), BigInt::from(*all_total))),
+            ThreadsafeFunctionCallMode::NonBlocking,
+          );
+        }
+      };
+      let event = |index: usize, state, error| {
+        if let Some(on_job) = &options.on_job {
+          on_job.call(
+            TransferEvent {
+              index: index as u32,
+              state,
+              error,
+            },
+            ThreadsafeFunctionCallMode::NonBlocking,
+          );
+        }
+      };
+      let results = stream::iter(jobs.into_iter().enumerate())
+        .map(|(index, job)| {
+          let (report, event) = (&report, &event);
+          async move {
+            event(index, TransferState::Started, None);
+            let job_options = job.options.unwrap_or_default();
+            let progress = |transferred, total| {
+              job_options.report(transferred, total);
+              report(index, transferred, total);
+            };
+            let transferred = match job.direction {
+              TransferDirection::Upload => {
+                self
+                  .upload_file(
+                    &job.local_path,
+                    job.remote_path.clone(),
+                    &job_options,
+                    &progress,
+                  )
+                  .await
+              }
+              TransferDirection::Download => {
+                self
+                  .download_file(
+                    job.remote_path.clone(),
+                    &job.local_path,
+                    &job_options,
+                    &progress,
+                  )
+                  .await
+              }
+            };
+            let error = transferred.err().map(|err| err.reason);
+            let state = if error.is_some() {
+              TransferState::Failed
+            } else {
+              TransferState::Finished
+            };
+            event(index, state, error.clone());
+            TransferResult {
+              local_path: job.local_path,
+              remote_path: job.remote_path,
+              error,
+            }
+          }
+        })
+        .buffered(concurrency as usize)
+        .collect()
+        .await;
+      Ok(results)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<Array<SyncChange>>")]
+  /// Make the remote directory `remote_dir` hold what the local directory `local_dir` does,
+  /// creating the missing directories and uploading only the files that are missing or changed,
+  /// with their permissions and times like `preserve` gives them. Resolves with the changes in
+  /// the order they are made, parents before their contents.
+  pub async fn sync(
+    &self,
+    local_dir: String,
+    remote_dir: String,
+    options: Option<SyncOptions>,
+  ) -> Result<Coded<Vec<SyncChange>>> {
+    coded(async {
+      let options = options.unwrap_or(SyncOptions {
+        delete: None,
+        compare: None,
+        dry_run: None,
+      });
+      let delete = options.delete.unwrap_or(false);
+      let checksum = match options.compare.as_deref() {
+        None | Some("mtime-size") => false,
+        Some("checksum") => true,
+        Some(compare) => {
+          return Err(Error::new(
+            Status::InvalidArg,
+            format!("Unknown sync comparison {compare}"),
+          ))
+        }
+      };
+      let local_root = Path::new(&local_dir);
+      let local = local_tree(local_root).await?;
+      let mut remote = BTreeMap::new();
+      let mut changes = Vec::new();
+      match self.session().stat(remote_dir.as_str()).await {
+        Ok(_) => {
+          self
+            .remote_tree(remote_dir.clone(), String::new(), &mut remote)
+            .await?
+        }
+        Err(err) if has_status(&err, StatusCode::NoSuchFile) => changes.push(SyncChange {
+          action: SyncAction::Mkdir,
+          path: String::new(),
+        }),
+        Err(err) => return Err(err).into_error(),
+      }
+      let remote_path = |path: &str| {
+        if path.is_empty() {
+          remote_dir.clone()
+        } else {
+          join_remote(&remote_dir, path)
+        }
+      };
+      // the contents of a directory sort right after it, so they come after its removal
+      let mut removed: Option<String> = None;
+      let paths = local.keys().chain(remote.keys()).collect::<BTreeSet<_>>();
+      for path in paths {
+        if removed
+          .as_ref()
+          .is_some_and(|dir| path.starts_with(&format!("{dir}/")))
+        {
+          continue;
+        }
+        let change = |action| SyncChange {
+          action,
+          path: path.clone(),
+        };
+        let Some(metadata) = local.get(path) else {
+          if delete {
+            changes.push(change(SyncAction::Delete));
+            removed = Some(path.clone());
+          }
+          continue;
+        };
+        let (wanted, action) = if metadata.is_dir() {
+          (FileType::Directory, SyncAction::Mkdir)
+        } else {
+          (FileType::File, SyncAction::Upload)
+        };
+        let Some(attributes) = remote.get(path) else {
+          changes.push(change(action));
+          continue;
+        };
+        if attributes.file_type != wanted {
+          if !delete {
+            let kind = if wanted == FileType::Directory {
+              "a directory"
+            } else {
+              "a file"
+            };
+            return Err(Error::new(
+              Status::GenericFailure,
+              format!(
+                "{} is not {kind}, replacing it takes delete",
+                remote_path(path)
+              ),
+            ));
+          }
+          changes.push(change(SyncAction::Delete));
+          changes.push(change(action));
+          // the contents of a local directory replacing a remote file still need uploading
+          if attributes.file_type == FileType::Directory {
+            removed = Some(path.clone());
+          }
+          continue;
+        }
+        if wanted == FileType::Directory {
+          continue;
+        }
+        let changed = if attributes.size != Some(metadata.len().into()) {
+          true
+        } else if checksum {
+          let local_hash = local_sha256(&local_root.join(path)).await?;
+          self
+            .checksum(remote_path(path), None)
+            .await?
+            .into_result()?
+            != local_hash
+        } else {
+          attributes.mtime != local_attributes(metadata).mtime
+        };
+        if changed {
+          changes.push(change(SyncAction::Upload));
+        }
+      }
+      if options.dry_run.unwrap_or(false) {
+        return Ok(changes);
+      }
+      let preserve = TransferOptions {
+        preserve: Some(true),
+        ..Default::default()
+      };
+      for change in &changes {
+        let path = remote_path(&change.path);
+        match change.action {
+          SyncAction::Mkdir => {
+            self
+              .session()
+              .mkdir(path, protocol::FileAttributes::empty())
+              .await
+              .into_error()?;
+          }
+          SyncAction::Upload => {
+            let local_path = local_root.join(&change.path);
+            self
+              .upload_to(&local_path.to_string_lossy(), path, &preserve, &|_, _| {})
+              .await?;
+          }
+          SyncAction::Delete if remote[&change.path].file_type == FileType::Directory => {
+            self.remove_tree(path).await?;
+          }
+          SyncAction::Delete => {
+            self.session().remove(path).await.into_error()?;
+          }
+        }
+      }
+      Ok(changes)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<Array<DirEntry>>")]
+  /// List the remote directory at `path`, without its `.` and `..` entries.
+  pub async fn readdir(&self, path: String) -> Result<Coded<Vec<DirEntry>>> {
+    coded(async {
+      let handle = self.session().opendir(path).await.into_error()?.handle;
+      let list = async {
+        let mut entries = Vec::new();
+        loop {
+          let files = match self.session().readdir(&handle).await {
+            Ok(name) => name.files,
+            Err(err) if is_eof(&err) => return Ok(entries),
+            Err(err) => return Err(err).into_error(),
+          };
+          entries.extend(
+            files
+              .into_iter()
+              .filter(|file| file.filename != "." && file.filename != "..")
+              .map(|file| DirEntry {
+                name: file.filename,
+                longname: file.longname,
+                attributes: file.attrs.into(),
+              }),
+          );
+        }
+      };
+      self.closing(handle.clone(), list).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "AsyncGenerator<WalkEntry, void, undefined>")]
+  /// Walk the remote directory tree at `path` with `for await`, yielding every entry below it,
+  /// each directory before its contents. Directories are read a batch at a time as the loop
+  /// asks for entries, and symlinks are not followed. Stopping the loop early closes the
+  /// directories the walk has open.
+  pub fn walk(&self, path: String) -> SftpWalk {
+    SftpWalk {
+      session: self.session().clone(),
+      dirs: tokio::sync::Mutex::new(vec![WalkDir {
+        path,
+        handle: None,
+        batch: VecDeque::new(),
+      }]),
+    }
+  }
+
+  #[napi(ts_return_type = "Promise<FileAttributes>")]
+  /// The attributes of the file at `path`, following symlinks.
+  pub async fn stat(&self, path: String) -> Result<Coded<FileAttributes>> {
+    coded(async {
+      let attrs = self.session().stat(path).await.into_error()?.attrs;
+      Ok(attrs.into())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<FileAttributes>")]
+  /// The attributes of the file at `path`, of the link itself if it is a symlink.
+  pub async fn lstat(&self, path: String) -> Result<Coded<FileAttributes>> {
+    coded(async {
+      let attrs = self.session().lstat(path).await.into_error()?.attrs;
+      Ok(attrs.into())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<boolean>")]
+  /// Whether a file exists at `path`, following symlinks. Errors other than a missing file,
+  /// such as a denied permission, reject.
+  pub async fn exists(&self, path: String) -> Result<Coded<bool>> {
+    coded(async {
+      match self.session().stat(path).await {
+        Ok(_) => Ok(true),
+        Err(err) if has_status(&err, StatusCode::NoSuchFile) => Ok(false),
+        Err(err) => Err(err).into_error(),
+      }
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Create the remote directory `path`.
+  pub async fn mkdir(&self, path: String, options: Option<MkdirOptions>) -> Result<Coded<()>> {
+    coded(async {
+      let attrs = protocol::FileAttributes {
+        permissions: options.as_ref().and_then(|options| options.mode),
+        ..protocol::FileAttributes::empty()
+      };
+      if !options
+        .and_then(|options| options.recursive)
+        .unwrap_or(false)
+      {
+        self.session().mkdir(path, attrs).await.into_error()?;
+        return Ok(());
+      }
+      let mut prefix = if path.starts_with('/') {
+        "/".to_owned()
+      } else {
+        String::new()
+      };
+      for component in path.split('/').filter(|component| !component.is_empty()) {
+        prefix = if prefix.is_empty() {
+          component.to_owned()
+        } else {
+          join_remote(&prefix, component)
+        };
+        match self.session().stat(prefix.as_str()).await {
+          Ok(existing) if is_dir(&existing.attrs) => {}
+          Ok(_) => {
+            return Err(Error::new(
+              Status::GenericFailure,
+              format!("{prefix} exists and is not a directory"),
+            ))
+          }
+          Err(err) if has_status(&err, StatusCode::NoSuchFile) => {
+            self
+              .session()
+              .mkdir(prefix.as_str(), attrs.clone())
+              .await
+              .into_error()?;
+          }
+          Err(err) => return Err(err).into_error(),
+        }
+      }
+      Ok(())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Remove the remote directory `path`, which must be empty unless `recursive` is set.
+  pub async fn rmdir(&self, path: String, options: Option<RmdirOptions>) -> Result<Coded<()>> {
+    coded(async {
+      if options
+        .and_then(|options| options.recursive)
+        .unwrap_or(false)
+      {
+        self.remove_tree(path).await
+      } else {
+        self.session().rmdir(path).await.into_error().map(drop)
+      }
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Rename `from` to `to`. With the `posix-rename@openssh.com` extension an existing `to` is
+  /// replaced atomically; without it, most servers refuse to overwrite.
+  pub async fn rename(&self, from: String, to: String) -> Result<Coded<()>> {
+    coded(async {
+      if !self.extensions.contains_key("posix-rename@openssh.com") {
+        return self.session().rename(from, to).await.into_error().map(drop);
+      }
+      let mut data = Vec::new();
+      put_string(&mut data, &from);
+      put_string(&mut data, &to);
+      let reply = self
+        .session()
+        .extended("posix-rename@openssh.com", data)
+        .await
+        .into_error()?;
+      expect_ok(reply)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Copy the remote file at `from` to `to`, replacing any file there, without the data leaving
+  /// the server. Takes the `copy-data` extension.
+  pub async fn copy(&self, from: String, to: String) -> Result<Coded<()>> {
+    coded(async {
+      self.require_extension("copy-data")?;
+      let source = self
+        .session()
+        .open(from, OpenFlags::READ, protocol::FileAttributes::empty())
+        .await
+        .into_error()?
+        .handle;
+      let copy = async {
+        let target = self
+          .session()
+          .open(
+            to,
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+            protocol::FileAttributes::empty(),
+          )
+          .await
+          .into_error()?
+          .handle;
+        let copied = async {
+          let mut data = Vec::new();
+          put_string(&mut data, &source);
+          // from offset 0, with length 0 meaning up to the end
+          data.extend_from_slice(&0u64.to_be_bytes());
+          data.extend_from_slice(&0u64.to_be_bytes());
+          put_string(&mut data, &target);
+          data.extend_from_slice(&0u64.to_be_bytes());
+          expect_ok(
+            self
+              .session()
+              .extended("copy-data", data)
+              .await
+              .into_error()?,
+          )
+        };
+        self.closing(target.clone(), copied).await
+      };
+      self.closing(source.clone(), copy).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Remove the remote file at `path`.
+  pub async fn unlink(&self, path: String) -> Result<Coded<()>> {
+    coded(async { self.session().remove(path).await.into_error().map(drop) }).await
+  }
+
+  #[napi(ts_return_type = "Promise<Array<string>>")]
+  /// The paths matching the glob `pattern`, sorted, e.g. `logs/*.gz`. `*` and `?` match any
+  /// characters and any one character of a name, `[...]` one of a set of characters and `**`
+  /// any number of directories. Names starting with `.` only match a pattern that does too.
+  /// Only the directories a wildcard applies to are listed.
+  pub async fn glob(
+    &self,
+    pattern: String,
+    options: Option<GlobOptions>,
+  ) -> Result<Coded<Vec<String>>> {
+    coded(async {
+      let cwd = options.and_then(|options| options.cwd).unwrap_or_default();
+      let paths = self.expand_glob(&pattern, &cwd).await?;
+      // what a wildcard matched was listed, literal paths have yet to be found
+      if pattern
+        .rsplit('/')
+        .find(|component| !component.is_empty())
+        .is_some_and(has_wildcard)
+      {
+        return Ok(paths);
+      }
+      let mut found = Vec::with_capacity(paths.len());
+      for path in paths {
+        if self
+          .session()
+          .lstat(glob_listing(&cwd, &path))
+          .await
+          .is_ok()
+        {
+          found.push(path);
+        }
+      }
+      Ok(found)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<Array<RmResult>>")]
+  /// Remove every path in `paths`, or every path matching the glob pattern `paths`, carrying
+  /// on past failures. Resolves with the outcome for each path.
+  pub async fn rm(
+    &self,
+    paths: Either<Vec<String>, String>,
+    options: Option<RmOptions>,
+  ) -> Result<Coded<Vec<RmResult>>> {
+    coded(async {
+      let recursive = options
+        .and_then(|options| options.recursive)
+        .unwrap_or(false);
+      let paths = match paths {
+        Either::A(paths) => paths,
+        Either::B(pattern) => self.expand_glob(&pattern, "").await?,
+      };
+      let mut results = Vec::with_capacity(paths.len());
+      for path in paths {
+        let removed = match self.session().lstat(path.as_str()).await.into_error() {
+          Ok(existing) if !is_dir(&existing.attrs) => self
+            .session()
+            .remove(path.as_str())
+            .await
+            .into_error()
+            .map(drop),
+          Ok(_) if recursive => self.remove_tree(path.clone()).await,
+          Ok(_) => Err(Error::new(
+            Status::GenericFailure,
+            format!("{path} is a directory"),
+          )),
+          Err(err) => Err(err),
+        };
+        results.push(RmResult {
+          path,
+          error: removed.err().map(|err| err.reason),
+        });
+      }
+      Ok(results)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Create a symlink at `link_path` pointing to `target`.
+  pub async fn symlink(&self, target: String, link_path: String) -> Result<Coded<()>> {
+    coded(async {
+      // OpenSSH reads the fields of SSH_FXP_SYMLINK in the reverse order of the draft, and other
+      // servers followed it
+      self
+        .session()
+        .symlink(target, link_path)
+        .await
+        .into_error()
+        .map(drop)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Create a hard link at `new_path` to the file at `existing_path`. Takes the
+  /// `hardlink@openssh.com` extension.
+  pub async fn link(&self, existing_path: String, new_path: String) -> Result<Coded<()>> {
+    coded(async {
+      self.require_extension("hardlink@openssh.com")?;
+      self
+        .session()
+        .hardlink(existing_path, new_path)
+        .await
+        .into_error()
+        .map(drop)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<string>")]
+  /// The target of the symlink at `path`.
+  pub async fn readlink(&self, path: String) -> Result<Coded<String>> {
+    coded(async { only_name(self.session().readlink(path).await.into_error()?) }).await
+  }
+
+  #[napi(ts_return_type = "Promise<string>")]
+  /// The absolute, canonical form of `path`, resolved by the server. Relative paths start from
+  /// the directory the session started in, usually the home directory, so `realpath(".")`
+  /// finds it.
+  pub async fn realpath(&self, path: String) -> Result<Coded<String>> {
+    coded(async { only_name(self.session().realpath(path).await.into_error()?) }).await
+  }
+
+  #[napi(ts_return_type = "Promise<FilesystemStats>")]
+  /// Describe the filesystem holding `path`, e.g. to check for free space before an upload.
+  /// Takes the `statvfs@openssh.com` extension.
+  pub async fn statvfs(&self, path: String) -> Result<Coded<FilesystemStats>> {
+    coded(async {
+      self.require_extension("statvfs@openssh.com")?;
+      let stats = self.session().statvfs(path).await.into_error()?;
+      Ok(stats.into())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<string>")]
+  /// The hash of the remote file at `path`, in hex, computed on the server to check a transfer
+  /// without reading the file back. Servers announcing the `check-file` extension compute it
+  /// themselves; on others, OpenSSH among them, it comes from running e.g. `sha256sum` over an
+  /// exec channel.
+  pub async fn checksum(
+    &self,
+    path: String,
+    options: Option<ChecksumOptions>,
+  ) -> Result<Coded<String>> {
+    coded(async {
+      let algorithm = options
+        .and_then(|options| options.algorithm)
+        .unwrap_or_else(|| "sha256".to_owned());
+      if !["md5", "sha1", "sha224", "sha256", "sha384", "sha512"].contains(&algorithm.as_str()) {
+        return Err(Error::new(
+          Status::InvalidArg,
+          format!("Unknown checksum algorithm {algorithm}"),
+        ));
+      }
+      if self.extensions.contains_key("check-file") {
+        let mut data = Vec::new();
+        put_string(&mut data, &path);
+        put_string(&mut data, &algorithm);
+        // the whole file, from offset 0 with length 0, in one block
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&0u32.to_be_bytes());
+        let reply = self
+          .session()
+          .extended("check-file-name", data)
+          .await
+          .into_error()?;
+        return match reply {
+          Packet::ExtendedReply(reply) => check_file_hash(&reply.data).ok_or_else(|| {
+            Error::new(
+              Status::GenericFailure,
+              "The server sent a malformed check-file reply".to_owned(),
+            )
+          }),
+          Packet::Status(status) => Err(SftpError::Status(status)).into_error(),
+          _ => Err(SftpError::UnexpectedPacket).into_error(),
+        };
+      }
+      let argv = [format!("{algorithm}sum"), "--".to_owned(), path];
+      let output = exec_argv(&self.handle, &argv).await?;
+      let output = String::from_utf8_lossy(&output.output);
+      output
+        .split_whitespace()
+        .next()
+        // names with a backslash or a newline are escaped, which a leading backslash marks
+        .map(|hash| hash.trim_start_matches('\\').to_owned())
+        .ok_or_else(|| {
+          Error::new(
+            Status::GenericFailure,
+            format!("{} printed no checksum", argv[0]),
+          )
+        })
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<string>")]
+  /// `path` with a leading `~` or `~user` replaced by the home directory, made absolute and
+  /// canonical like `realpath` does. Without the `expand-path@openssh.com` extension, `~` is
+  /// taken to be the directory the session started in, and `~user` fails.
+  pub async fn expand_path(&self, path: String) -> Result<Coded<String>> {
+    coded(async {
+      if self.extensions.contains_key("expand-path@openssh.com") {
+        return only_name(self.session().expand_path(path).await.into_error()?);
+      }
+      let relative = match path.as_str() {
+        "~" => ".",
+        path => path.strip_prefix("~/").unwrap_or(path),
+      };
+      if relative.starts_with('~') {
+        return Err(Error::new(
+          Status::GenericFailure,
+          "Expanding ~user takes the expand-path@openssh.com extension".to_owned(),
+        ));
+      }
+      self.realpath(relative.to_owned()).await?.into_result()
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Change the permission bits of the file at `path`.
+  pub async fn chmod(&self, path: String, mode: u32) -> Result<Coded<()>> {
+    coded(async {
+      let attrs = protocol::FileAttributes {
+        permissions: Some(mode),
+        ..protocol::FileAttributes::empty()
+      };
+      self.setstat(path, attrs).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Change the owner and group of the file at `path`, by their numeric ids.
+  pub async fn chown(&self, path: String, uid: u32, gid: u32) -> Result<Coded<()>> {
+    coded(async {
+      let attrs = protocol::FileAttributes {
+        uid: Some(uid),
+        gid: Some(gid),
+        ..protocol::FileAttributes::empty()
+      };
+      self.setstat(path, attrs).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Change the access and modification times of the file at `path`, in seconds since the
+  /// Unix epoch.
+  pub async fn utimes(&self, path: String, atime: u32, mtime: u32) -> Result<Coded<()>> {
+    coded(async {
+      let attrs = protocol::FileAttributes {
+        atime: Some(atime),
+        mtime: Some(mtime),
+        ..protocol::FileAttributes::empty()
+      };
+      self.setstat(path, attrs).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<FileHandle>")]
+  /// Open the remote file at `path` for reading and writing at any offset. `flags` are those of
+  /// Node's `fs.open`, `r` by default, and `mode` the permissions of a file the open creates.
+  pub async fn open(
+    &self,
+    path: String,
+    flags: Option<String>,
+    mode: Option<u32>,
+  ) -> Result<Coded<FileHandle>> {
+    coded(async {
+      let flags = parse_open_flags(flags.as_deref().unwrap_or("r"))?;
+      let attrs = protocol::FileAttributes {
+        permissions: mode,
+        ..protocol::FileAttributes::empty()
+      };
+      let handle = self
+        .session()
+        .open(path, flags, attrs)
+        .await
+        .into_error()?
+        .handle;
+      Ok(FileHandle {
+        session: self.session().clone(),
+        handle,
+        fsync: self.extensions.contains_key("fsync@openssh.com"),
+        limits: self.limits,
+      })
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<import('node:stream').Readable>")]
+  /// Open the remote file at `path` as a Node `Readable`, for `pipeline`. A chunk is requested
+  /// each time the stream wants more, so a slow consumer holds the reads back. The file is
+  /// closed once it has been read to the end, or when the stream is destroyed.
+  pub async fn create_read_stream(
+    &self,
+    path: String,
+    options: Option<ReadStreamOptions>,
+  ) -> Result<Coded<SftpReadStream>> {
+    coded(async {
+      let (start, end) = options
+        .map(|options| (options.start, options.end))
+        .unwrap_or_default();
+      let start = start.map(|start| to_u64(start, "start")).transpose()?;
+      let end = end.map(|end| to_u64(end, "end")).transpose()?;
+      let file = self.open(path, None, None).await?.into_result()?;
+      Ok(SftpReadStream {
+        file,
+        offset: AtomicU64::new(start.unwrap_or(0)),
+        end: end.map(|end| end + 1),
+        done: AtomicBool::new(false),
+      })
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<import('node:stream').Writable>")]
+  /// Open the remote file at `path` as a Node `Writable` that writes it from the start, or from
+  /// `start`, in order. The stream finishes once the server has stored every chunk and the file
+  /// is closed.
+  pub async fn create_write_stream(
+    &self,
+    path: String,
+    options: Option<WriteStreamOptions>,
+  ) -> Result<Coded<SftpWriteStream>> {
+    coded(async {
+      let options = options.unwrap_or(WriteStreamOptions {
+        flags: None,
+        mode: None,
+        start: None,
+      });
+      let start = options
+        .start
+        .map(|start| to_u64(start, "start"))
+        .transpose()?;
+      let flags = options.flags.unwrap_or_else(|| "w".to_owned());
+      let file = self
+        .open(path, Some(flags), options.mode)
+        .await?
+        .into_result()?;
+      Ok(SftpWriteStream {
+        file,
+        offset: Arc::new(AtomicU64::new(start.unwrap_or(0))),
+        writes: Arc::new(RwLock::new(())),
+        ended: AtomicBool::new(false),
+      })
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Pass the lines of the remote file at `path` to `onLine`, for following a log where running
+  /// `tail -f` is not allowed. The file is polled by its size: when it shrinks, it is taken to be
+  /// truncated or replaced and read again from the start, and while it is missing, as during a
+  /// rotation, the next one to appear is read from the start. Resolves once the lines are passed,
+  /// or once `signal` aborts when following.
+  pub async fn tail(&self, path: String, options: TailOptions) -> Result<Coded<()>> {
+    coded(async {
+      let follow = options.follow.unwrap_or(false);
+      let interval = Duration::from_millis(options.poll_interval_ms.unwrap_or(1000) as u64);
+      let chunk_size = capped(CHUNK_SIZE, self.limits.read_len);
+      let mut abort = options.signal;
+      let mut offset = if follow {
+        let file = self.session().stat(path.as_str()).await.into_error()?;
+        file.attrs.size.unwrap_or(0)
+      } else {
+        0
+      };
+      let mut pending = Vec::new();
+      loop {
+        let size = match self.session().stat(path.as_str()).await {
+          Ok(file) => file.attrs.size.unwrap_or(0),
+          Err(err) if follow && has_status(&err, StatusCode::NoSuchFile) => 0,
+          Err(err) => return Err(err).into_error(),
+        };
+        if size < offset {
+          offset = 0;
+          pending.clear();
+        }
+        if size > offset {
+          let handle = self
+            .session()
+            .open(
+              path.as_str(),
+              OpenFlags::READ,
+              protocol::FileAttributes::empty(),
+            )
+            .await
+            .into_error()?
+            .handle;
+          let read = async {
+            while offset < size {
+              let chunk = read_chunk(&self.session(), &handle, offset, chunk_size).await?;
+              if chunk.is_empty() {
+                break;
+              }
+              offset += chunk.len() as u64;
+              pending.extend_from_slice(&chunk);
+              for line in take_lines(&mut pending) {
+                options
+                  .on_line
+                  .call(line, ThreadsafeFunctionCallMode::NonBlocking);
+              }
+            }
+            Ok(())
+          };
+          self.closing(handle.clone(), read).await?;
+        }
+        if !follow {
+          break;
+        }
+        tokio::select! {
+          _ = tokio::time::sleep(interval) => {}
+          _ = Abort::wait_for(&mut abort) => return Ok(()),
+        }
+      }
+      // the last line of a file may lack its line ending
+      if !pending.is_empty() {
+        let line = String::from_utf8_lossy(&pending).into_owned();
+        options
+          .on_line
+          .call(line, ThreadsafeFunctionCallMode::NonBlocking);
+      }
+      Ok(())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// End the session and close its channel. Requests still in flight fail.
+  pub async fn close(&self) -> Result<Coded<()>> {
+    coded(async {
+      self.ended.store(true, Ordering::Release);
+      self.session().close_session().into_error()
+    })
+    .await
+  }
+}
+
+#[napi]
+#[derive(Clone)]
+/// A remote file opened with `Sftp.open`. The server keeps it open until `close` or the end of
+/// the session.
+pub struct FileHandle {
+  session: Arc<RawSftpSession>,
+  handle: String,
+  /// Whether the server announced `fsync@openssh.com`.
+  fsync: bool,
+  limits: Limits,
+}
+
+impl FileHandle {
+  async fn read_at(&self, offset: u64, length: u32) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while data.len() < length as usize {
+      let wanted = (length - data.len() as u32).min(capped(CHUNK_SIZE, self.limits.read_len));
+      let chunk = read_chunk(
+        &self.session,
+        &self.handle,
+        offset + data.len() as u64,
+        wanted,
+      )
+      .await?;
+      data.extend_from_slice(&chunk);
+      if chunk.len() < wanted as usize {
+        break;
+      }
+    }
+    Ok(data)
+  }
+
+  async fn write_at(&self, offset: u64, data: &[u8]) -> Result<()> {
+    let write_size = capped(CHUNK_SIZE, self.limits.write_len);
+    for (index, chunk) in data.chunks(write_size as usize).enumerate() {
+      let chunk_offset = offset + index as u64 * write_size as u64;
+      self
+        .session
+        .write(&self.handle, chunk_offset, chunk.to_vec())
+        .await
+        .into_error()?;
+    }
+    Ok(())
+  }
+}
+
+#[napi]
+impl FileHandle {
+  #[napi(ts_return_type = "Promise<Buffer>")]
+  /// Read `length` bytes starting at `offset`. Fewer come back only when the file ends first.
+  pub async fn read(&self, offset: Either<f64, BigInt>, length: u32) -> Result<Coded<Buffer>> {
+    coded(async {
+      let offset = to_u64(offset, "offset")?;
+      Ok(self.read_at(offset, length).await?.into())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Write `data` starting at `offset`. Files opened for appending get it at their end instead.
+  pub async fn write(
+    &self,
+    offset: Either<f64, BigInt>,
+    data: Either<String, Buffer>,
+  ) -> Result<Coded<()>> {
+    coded(async {
+      let offset = to_u64(offset, "offset")?;
+      self.write_at(offset, &into_bytes(data)).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<FileAttributes>")]
+  pub async fn stat(&self) -> Result<Coded<FileAttributes>> {
+    coded(async {
+      let attrs = self.session.fstat(&self.handle).await.into_error()?.attrs;
+      Ok(attrs.into())
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Change the size, owner, permissions or times of the open file.
+  pub async fn setstat(&self, attributes: SetStatOptions) -> Result<Coded<()>> {
+    coded(async {
+      self
+        .session
+        .fsetstat(&self.handle, attributes.try_into()?)
+        .await
+        .into_error()
+        .map(drop)
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Have the server flush the file to disk, with the `fsync@openssh.com` extension.
+  pub async fn fsync(&self) -> Result<Coded<()>> {
+    coded(async {
+      if !self.fsync {
+        return Err(Error::new(
+          Status::GenericFailure,
+          "The server does not support fsync@openssh.com".to_owned(),
+        ));
+      }
+      fsync(&self.session, &self.handle).await
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  pub async fn close(&self) -> Result<Coded<()>> {
+    coded(async {
+      self
+        .session
+        .close(&self.handle)
+        .await
+        .into_error()
+        .map(drop)
+    })
+    .await
+  }
+}
+
+#[napi]
+/// A remote file opened by `Sftp.createReadStream`, which reads it with `read` until it returns
+/// `null`. The file is closed once it has been read to the end, or by `close`.
+pub struct SftpReadStream {
+  file: FileHandle,
+  offset: AtomicU64,
+  /// The offset after the last byte to read.
+  end: Option<u64>,
+  done: AtomicBool,
+}
+
+#[napi]
+impl SftpReadStream {
+  #[napi(ts_return_type = "Promise<Buffer | null>")]
+  /// The next chunk of the file, or `null` once it has been read.
+  pub async fn read(&self) -> Result<Coded<Option<Buffer>>> {
+    coded(async {
+      if self.done.load(Ordering::Acquire) {
+        return Ok(None);
+      }
+      let start = self.offset.load(Ordering::Acquire);
+      let length = self.end.map_or(CHUNK_SIZE as u64, |end| {
+        end.saturating_sub(start).min(CHUNK_SIZE as u64)
+      });
+      let chunk = if length == 0 {
+        Ok(Vec::new())
+      } else {
+        self.file.read_at(start, length as u32).await
+      };
+      match chunk {
+        Ok(chunk) if !chunk.is_empty() => {
+          self
+            .offset
+            .store(start + chunk.len() as u64, Ordering::Release);
+          Ok(Some(chunk.into()))
+        }
+        result => {
+          self.done.store(true, Ordering::Release);
+          let closed = self.file.close().await?.into_result();
+          result?;
+          closed.map(|()| None)
+        }
+      }
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Close the file, unless it has been read to the end already.
+  pub async fn close(&self) -> Result<Coded<()>> {
+    coded(async {
+      if self.done.swap(true, Ordering::AcqRel) {
+        return Ok(());
+      }
+      self.file.close().await?.into_result()
+    })
+    .await
+  }
+}
+
+#[napi]
+/// A remote file opened by `Sftp.createWriteStream`. Each write is sent at once after the
+/// previous ones and resolves when the server has stored it, so awaiting writes bounds the data
+/// in flight.
+pub struct SftpWriteStream {
+  file: FileHandle,
+  /// Where the next write goes.
+  offset: Arc<AtomicU64>,
+  /// Held shared by writes in flight and exclusively by `end`, so the file closes after them.
+  writes: Arc<RwLock<()>>,
+  ended: AtomicBool,
+}
+
+#[napi]
+impl SftpWriteStream {
+  #[napi(ts_return_type = "Promise<void>")]
+  pub fn write<'env>(
+    &self,
+    env: &'env Env,
+    data: Either<String, Buffer>,
+  ) -> Result<PromiseRaw<'env, Coded<()>>> {
+    let ended = || Error::new(Status::GenericFailure, "The stream has ended".to_owned());
+    if self.ended.load(Ordering::Acquire) {
+      return Err(ended());
+    }
+    let writing = self.writes.clone().try_read_owned().map_err(|_| ended())?;
+    let data = into_bytes(data);
+    let offset = self.offset.fetch_add(data.len() as u64, Ordering::AcqRel);
+    let file = self.file.clone();
+    env.spawn_future(coded(async move {
+      let written = file.write_at(offset, &data).await;
+      drop(writing);
+      written
+    }))
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Write `data`, if any, then close the file once every write has finished.
+  pub fn end<'env>(
+    &self,
+    env: &'env Env,
+    data: Option<Either<String, Buffer>>,
+  ) -> Result<PromiseRaw<'env, Coded<()>>> {
+    if self.ended.swap(true, Ordering::AcqRel) {
+      return Err(Error::new(
+        Status::GenericFailure,
+        "The stream has ended".to_owned(),
+      ));
+    }
+    let written = data.map(|data| {
+      let data = into_bytes(data);
+      (
+        self.offset.fetch_add(data.len() as u64, Ordering::AcqRel),
+        data,
+      )
+    });
+    let file = self.file.clone();
+    let writes = self.writes.clone();
+    env.spawn_future(coded(async move {
+      let _ended = writes.write().await;
+      if let Some((offset, data)) = written {
+        file.write_at(offset, &data).await?;
+      }
+      file.close().await?.into_result()
+    }))
+  }
+}
+
+/// A directory `SftpWalk` is in, opened once the walk reaches it.
+struct WalkDir {
+  path: String,
+  handle: Option<String>,
+  /// What the server sent of the directory and the walk has yet to yield.
+  batch: VecDeque<protocol::File>,
+}
+
+#[napi]
+/// A walk over a remote directory tree, started with `Sftp.walk`, which takes its entries with
+/// `read` until it returns `null`. `close` closes the directories it has open.
+pub struct SftpWalk {
+  session: Arc<RawSftpSession>,
+  /// The directories from the walked one down to where the walk is.
+  dirs: tokio::sync::Mutex<Vec<WalkDir>>,
+}
+
+#[napi]
+impl SftpWalk {
+  #[napi(ts_return_type = "Promise<WalkEntry | null>")]
+  /// The next entry of the walk, or `null` once it is done.
+  pub async fn read(&self) -> Result<Coded<Option<WalkEntry>>> {
+    coded(async {
+      let mut dirs = self.dirs.lock().await;
+      loop {
+        let Some(dir) = dirs.last_mut() else {
+          return Ok(None);
+        };
+        let handle = match &dir.handle {
+          Some(handle) => handle.clone(),
+          None => match self.session.opendir(dir.path.as_str()).await {
+            Ok(opened) => dir.handle.insert(opened.handle).clone(),
+            Err(err) => {
+              // skip the directory if the walk goes on
+              dirs.pop();
+              return Err(err).into_error();
+            }
+          },
+        };
+        if let Some(file) = dir.batch.pop_front() {
+          if file.filename == "." || file.filename == ".." {
+            continue;
+          }
+          let path = join_remote(&dir.path, &file.filename);
+          let attributes = FileAttributes::from(file.attrs);
+          if attributes.file_type == FileType::Directory {
+            dirs.push(WalkDir {
+              path: path.clone(),
+              handle: None,
+              batch: VecDeque::new(),
+            });
+          }
+          return Ok(Some(WalkEntry { path, attributes }));
+        }
+        match self.session.readdir(handle.as_str()).await {
+          Ok(name) => dir.batch.extend(name.files),
+          Err(err) => {
+            dirs.pop();
+            let _ = self.session.close(handle).await;
+            if !is_eof(&err) {
+              return Err(err).into_error();
+            }
+          }
+        }
+      }
+    })
+    .await
+  }
+
+  #[napi(ts_return_type = "Promise<void>")]
+  /// Stop the walk, closing the directories it has open.
+  pub async fn close(&self) -> Result<Coded<()>> {
+    coded(async {
+      for dir in self.dirs.lock().await.drain(..) {
+        if let Some(handle) = dir.handle {
+          self.session.close(handle).await.into_error()?;
+        }
+      }
+      Ok(())
+    })
+    .await
+  }
+}
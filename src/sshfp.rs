@@ -0,0 +1,274 @@
+use std::{
+  net::{IpAddr, SocketAddr},
+  time::Duration,
+};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use russh_keys::{key, PublicKeyBase64};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::{TcpStream, UdpSocket},
+};
+
+/// The DNS resource record type of SSHFP, RFC 4255.
+const SSHFP: u16 = 44;
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[napi(object)]
+/// Verify host keys against SSHFP records published in DNS (RFC 4255).
+///
+/// The DNSSEC status is taken from the AD flag of the resolver's answer, so the resolver and
+/// the path to it have to be trusted, as with OpenSSH's `VerifyHostKeyDNS`.
+pub struct SshfpOptions {
+  /// The name to look up. Defaults to the host part of the address passed to `connect`.
+  pub hostname: Option<String>,
+  /// The resolver to ask, as `ip` or `ip:port`. Defaults to the first `nameserver` in
+  /// `/etc/resolv.conf`.
+  pub nameserver: Option<String>,
+  /// Trust a matching record even if the answer was not DNSSEC-authenticated.
+  pub allow_insecure_dns: Option<bool>,
+}
+
+/// The outcome of checking a host key against its SSHFP records.
+#[napi]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SshfpStatus {
+  /// A record matches the host key, in an answer that can be trusted.
+  Matched,
+  /// The host has SSHFP records, but none of them match the host key.
+  Unmatched,
+  /// The host has no SSHFP records.
+  NoRecords,
+  /// A record matches the host key, but the answer was not DNSSEC-authenticated.
+  Unverified,
+  /// The records could not be looked up.
+  LookupFailed,
+}
+
+pub(crate) struct Sshfp {
+  hostname: String,
+  nameserver: Option<String>,
+  allow_insecure_dns: bool,
+}
+
+impl Sshfp {
+  /// `host` is the host passed to `connect`, used when no hostname is configured.
+  pub(crate) fn new(options: SshfpOptions, host: &str) -> Self {
+    let hostname = options.hostname.unwrap_or_else(|| host.to_owned());
+    Self {
+      hostname,
+      nameserver: options.nameserver,
+      allow_insecure_dns: options.allow_insecure_dns.unwrap_or(false),
+    }
+  }
+
+  pub(crate) async fn verify(&self, host_key: &key::PublicKey) -> SshfpStatus {
+    // an address has no name to publish records under
+    if self.hostname.parse::<IpAddr>().is_ok() {
+      return SshfpStatus::NoRecords;
+    }
+    let answer = match tokio::time::timeout(LOOKUP_TIMEOUT, self.lookup()).await {
+      Ok(Ok(answer)) => answer,
+      _ => return SshfpStatus::LookupFailed,
+    };
+    if answer.records.is_empty() {
+      return SshfpStatus::NoRecords;
+    }
+    let algorithm = match host_key {
+      key::PublicKey::RSA { .. } => 1,
+      key::PublicKey::EC { .. } => 3,
+      key::PublicKey::Ed25519(_) => 4,
+    };
+    let blob = host_key.public_key_bytes();
+    let matched = answer.records.iter().any(|record| {
+      record.algorithm == algorithm
+        && match record.fingerprint_type {
+          1 => record.fingerprint[..] == Sha1::digest(&blob)[..],
+          2 => record.fingerprint[..] == Sha256::digest(&blob)[..],
+          _ => false,
+        }
+    });
+    match (matched, answer.authenticated || self.allow_insecure_dns) {
+      (true, true) => SshfpStatus::Matched,
+      (true, false) => SshfpStatus::Unverified,
+      (false, _) => SshfpStatus::Unmatched,
+    }
+  }
+
+  async fn lookup(&self) -> Result<Answer> {
+    let nameserver = match &self.nameserver {
+      Some(nameserver) => nameserver.clone(),
+      None => system_nameserver().await?,
+    };
+    let nameserver = nameserver
+      .parse::<SocketAddr>()
+      .or_else(|_| {
+        nameserver
+          .parse::<IpAddr>()
+          .map(|ip| SocketAddr::new(ip, 53))
+      })
+      .map_err(|_| {
+        Error::new(
+          Status::InvalidArg,
+          format!("Invalid nameserver address {nameserver}"),
+        )
+      })?;
+    // an answer is only taken for this query when it echoes the id, so it must not be guessable
+    let id = rand::random::<u16>();
+    let query = build_query(id, &self.hostname)?;
+    let socket = UdpSocket::bind(match nameserver {
+      SocketAddr::V4(_) => "0.0.0.0:0",
+      SocketAddr::V6(_) => "[::]:0",
+    })
+    .await?;
+    socket.connect(nameserver).await?;
+    socket.send(&query).await?;
+    let mut response = vec![0; 65535];
+    loop {
+      let len = socket.recv(&mut response).await?;
+      // ignore stray datagrams that do not answer this query
+      if len >= 12 && response[..2] == id.to_be_bytes() {
+        response.truncate(len);
+        break;
+      }
+    }
+    // a truncated answer is asked again over TCP
+    if response[2] & 0x02 != 0 {
+      let mut stream = TcpStream::connect(nameserver).await?;
+      stream.write_u16(query.len() as u16).await?;
+      stream.write_all(&query).await?;
+      let len = stream.read_u16().await?;
+      response = vec![0; len as usize];
+      stream.read_exact(&mut response).await?;
+    }
+    parse_answer(id, &response)
+  }
+}
+
+async fn system_nameserver() -> Result<String> {
+  let conf = tokio::fs::read_to_string("/etc/resolv.conf")
+    .await
+    .unwrap_or_default();
+  conf
+    .lines()
+    .filter_map(|line| line.strip_prefix("nameserver"))
+    .map(|nameserver| nameserver.trim().to_owned())
+    .next()
+    .ok_or_else(|| {
+      Error::new(
+        Status::GenericFailure,
+        "No nameserver configured for the SSHFP lookup".to_owned(),
+      )
+    })
+}
+
+fn build_query(id: u16, hostname: &str) -> Result<Vec<u8>> {
+  let mut query = Vec::with_capacity(hostname.len() + 29);
+  query.extend_from_slice(&id.to_be_bytes());
+  // RD, and AD to ask for the DNSSEC status of the answer (RFC 6840, section 5.7)
+  query.extend_from_slice(&[0x01, 0x20]);
+  // one question and one additional record, the EDNS0 OPT
+  query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1]);
+  for label in hostname.trim_end_matches('.').split('.') {
+    if label.is_empty() || label.len() > 63 {
+      return Err(Error::new(
+        Status::InvalidArg,
+        format!("Invalid hostname {hostname} for the SSHFP lookup"),
+      ));
+    }
+    query.push(label.len() as u8);
+    query.extend_from_slice(label.as_bytes());
+  }
+  query.push(0);
+  query.extend_from_slice(&SSHFP.to_be_bytes());
+  // class IN
+  query.extend_from_slice(&[0, 1]);
+  // OPT: root name, a 1232 byte payload size and no options
+  query.extend_from_slice(&[0, 0, 41, 0x04, 0xd0, 0, 0, 0, 0, 0, 0]);
+  Ok(query)
+}
+
+struct Answer {
+  authenticated: bool,
+  records: Vec<Record>,
+}
+
+struct Record {
+  algorithm: u8,
+  fingerprint_type: u8,
+  fingerprint: Vec<u8>,
+}
+
+fn parse_answer(id: u16, response: &[u8]) -> Result<Answer> {
+  let malformed = || {
+    Error::new(
+      Status::GenericFailure,
+      "Malformed DNS answer to the SSHFP lookup".to_owned(),
+    )
+  };
+  let header = response.get(..12).ok_or_else(malformed)?;
+  if header[..2] != id.to_be_bytes() {
+    return Err(malformed());
+  }
+  let authenticated = header[3] & 0x20 != 0;
+  match header[3] & 0x0f {
+    0 => {}
+    // NXDOMAIN
+    3 => {
+      return Ok(Answer {
+        authenticated,
+        records: Vec::new(),
+      })
+    }
+    rcode => {
+      return Err(Error::new(
+        Status::GenericFailure,
+        format!("The SSHFP lookup failed with DNS response code {rcode}"),
+      ))
+    }
+  }
+  let questions = u16::from_be_bytes([header[4], header[5]]);
+  let answers = u16::from_be_bytes([header[6], header[7]]);
+  let mut offset = 12;
+  for _ in 0..questions {
+    offset = skip_name(response, offset).ok_or_else(malformed)? + 4;
+  }
+  let mut records = Vec::new();
+  for _ in 0..answers {
+    offset = skip_name(response, offset).ok_or_else(malformed)?;
+    let fixed = response.get(offset..offset + 10).ok_or_else(malformed)?;
+    let kind = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let len = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+    offset += 10;
+    let data = response.get(offset..offset + len).ok_or_else(malformed)?;
+    offset += len;
+    // the resolver follows CNAMEs itself and puts them in the answer too
+    if kind == SSHFP && data.len() > 2 {
+      records.push(Record {
+        algorithm: data[0],
+        fingerprint_type: data[1],
+        fingerprint: data[2..].to_vec(),
+      });
+    }
+  }
+  Ok(Answer {
+    authenticated,
+    records,
+  })
+}
+
+/// The offset just past the (possibly compressed) name at `offset`.
+fn skip_name(message: &[u8], mut offset: usize) -> Option<usize> {
+  loop {
+    let len = *message.get(offset)?;
+    match len {
+      0 => return Some(offset + 1),
+      // a compression pointer ends the name
+      len if len & 0xc0 == 0xc0 => return Some(offset + 2),
+      len => offset += 1 + len as usize,
+    }
+  }
+}
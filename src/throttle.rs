@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use napi::bindgen_prelude::*;
+use tokio::time::Instant;
+
+/// A token bucket bounding the bytes a transfer moves per second.
+///
+/// It holds up to `burst` bytes, so a transfer that paused may briefly catch up, and goes into
+/// debt for a chunk larger than what it holds, making the next chunk wait longer.
+pub(crate) struct Throttle {
+  rate: f64,
+  burst: f64,
+  tokens: f64,
+  refilled: Instant,
+}
+
+impl Throttle {
+  /// A bucket for `max_bytes_per_second`, if set, starting full.
+  pub(crate) fn new(max_bytes_per_second: Option<u32>, burst: u32) -> Result<Option<Self>> {
+    match max_bytes_per_second {
+      None => Ok(None),
+      Some(0) => Err(Error::new(
+        Status::InvalidArg,
+        "maxBytesPerSecond must be positive".to_owned(),
+      )),
+      Some(rate) => Ok(Some(Self {
+        rate: rate as f64,
+        burst: burst as f64,
+        tokens: burst as f64,
+        refilled: Instant::now(),
+      })),
+    }
+  }
+
+  /// Wait until `bytes` more keep the transfer within its rate.
+  pub(crate) async fn take(&mut self, bytes: usize) {
+    let now = Instant::now();
+    let elapsed = now.duration_since(self.refilled).as_secs_f64();
+    self.tokens = (self.tokens + elapsed * self.rate).min(self.burst) - bytes as f64;
+    self.refilled = now;
+    if self.tokens < 0.0 {
+      tokio::time::sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await;
+    }
+  }
+
+  /// Take `bytes` from `throttle`, if any.
+  pub(crate) async fn take_from(throttle: &mut Option<Throttle>, bytes: usize) {
+    if let Some(throttle) = throttle {
+      throttle.take(bytes).await;
+    }
+  }
+}